@@ -0,0 +1,3 @@
+-- 用户名唯一
+
+CREATE UNIQUE INDEX users_username_idx ON users (username);
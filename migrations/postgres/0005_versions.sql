@@ -0,0 +1,9 @@
+-- 乐观并发控制使用的版本号，每次更新加1
+
+ALTER TABLE users ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE workspaces ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE catalogs ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE tables ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE rows ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE memberships ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
+ALTER TABLE api_keys ADD COLUMN version BIGINT NOT NULL DEFAULT 0;
@@ -0,0 +1,13 @@
+-- 工作区成员，同一个用户在一个工作区中只有一个角色
+
+CREATE TABLE memberships (
+    id TEXT PRIMARY KEY,
+    workspace_id TEXT NOT NULL,
+    user_id TEXT NOT NULL,
+    role TEXT NOT NULL,
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL
+);
+
+CREATE UNIQUE INDEX memberships_workspace_user_idx ON memberships (workspace_id, user_id);
+CREATE INDEX memberships_user_id_idx ON memberships (user_id);
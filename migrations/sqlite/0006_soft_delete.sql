@@ -0,0 +1,8 @@
+-- 软删除，deleted_at不为NULL的数据在回收站中
+
+ALTER TABLE workspaces ADD COLUMN deleted_at TEXT;
+ALTER TABLE workspaces ADD COLUMN deleted_by TEXT;
+ALTER TABLE catalogs ADD COLUMN deleted_at TEXT;
+ALTER TABLE catalogs ADD COLUMN deleted_by TEXT;
+ALTER TABLE tables ADD COLUMN deleted_at TEXT;
+ALTER TABLE tables ADD COLUMN deleted_by TEXT;
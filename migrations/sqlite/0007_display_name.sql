@@ -0,0 +1,3 @@
+-- 用户的显示名，可以为空
+
+ALTER TABLE users ADD COLUMN display_name TEXT;
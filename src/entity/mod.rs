@@ -1,23 +1,47 @@
+use std::{fmt, str::FromStr};
+
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct User {
     pub id: String,
     pub username: String,
-    pub passowrd_hash: String,
+    /// 显示名，没有设置时客户端显示用户名
+    pub display_name: Option<String>,
+    /// 密码哈希不会出现在任何返回给客户端的数据中
+    #[serde(skip_serializing)]
+    pub password_hash: String,
+    pub version: i64,
 }
 
+/// 其他实体中引用的用户，只包含可以公开的信息
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct UserSummary {
+    pub id: String,
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+}
+
+/// 工作区、目录和表删除后进入回收站，deletedAt和deletedBy记录删除的时间和用户
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct Workspace {
     pub id: String,
     pub name: String,
     pub description: String,
-    pub creator: User,
+    pub creator: UserSummary,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub version: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -27,19 +51,178 @@ pub struct Catalog {
     pub workspace_id: String,
     pub name: String,
     pub description: String,
-    pub creator: User,
+    pub creator: UserSummary,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub version: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
 }
 
-#[derive(Serialize, Deserialize, Debug)]
+#[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(rename_all = "camelCase")]
 pub struct Table {
     pub id: String,
     pub catalog_id: String,
     pub name: String,
     pub description: String,
-    pub creator: User,
+    pub columns: Vec<Column>,
+    pub creator: UserSummary,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub version: i64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub deleted_by: Option<String>,
+}
+
+/// 工作区成员
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Membership {
+    pub id: String,
+    pub workspace_id: String,
+    pub user_id: String,
+    pub role: Role,
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
+    pub version: i64,
+}
+
+/// 工作区成员的角色，按权限从低到高排列，高的角色拥有低的角色的所有权限
+///
+/// - viewer：查看工作区和其中的数据
+/// - editor：编辑目录、表和行
+/// - admin：修改工作区信息，管理成员
+/// - owner：删除工作区，授予或撤销owner
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "camelCase")]
+pub enum Role {
+    Viewer,
+    Editor,
+    Admin,
+    Owner,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+            Role::Owner => "owner",
+        }
+    }
+}
+
+impl FromStr for Role {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "viewer" => Ok(Role::Viewer),
+            "editor" => Ok(Role::Editor),
+            "admin" => Ok(Role::Admin),
+            "owner" => Ok(Role::Owner),
+            _ => Err(format!("unknown role: {}", s)),
+        }
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// 用户的API key，只保存key的哈希，明文只在创建时返回一次
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+    pub id: String,
+    pub user_id: String,
+    pub name: String,
+    /// key开头的几位，用来在列表中辨认key
+    pub prefix: String,
+    pub key_hash: String,
+    pub scope: KeyScope,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub version: i64,
+}
+
+/// API key的权限范围，默认可以读写用户有权限的所有工作区
+#[derive(Serialize, Deserialize, Debug, Clone, Default)]
+#[serde(rename_all = "camelCase")]
+pub struct KeyScope {
+    /// 只读的key最多拥有viewer的权限
+    #[serde(default)]
+    pub read_only: bool,
+    /// 限定只能访问某个工作区
+    #[serde(default)]
+    pub workspace_id: Option<String>,
+}
+
+/// 表中的一行数据，data的键是列名
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Row {
+    pub id: String,
+    pub table_id: String,
+    pub data: Map<String, Value>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    pub version: i64,
+}
+
+/// 表的列定义
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Column {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    pub nullable: bool,
+    pub default: Option<Value>,
+    pub unique: bool,
+}
+
+/// 列的数据类型
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(tag = "kind", rename_all = "camelCase")]
+pub enum ColumnType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    Datetime,
+    /// 枚举，值只能是values中的一个
+    Enum {
+        values: Vec<String>,
+    },
+    /// 引用另一张表的行id
+    Reference {
+        #[serde(rename = "tableId")]
+        table_id: String,
+    },
+}
+
+impl fmt::Display for ColumnType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let name = match self {
+            ColumnType::String => "string",
+            ColumnType::Integer => "integer",
+            ColumnType::Float => "float",
+            ColumnType::Boolean => "boolean",
+            ColumnType::Datetime => "datetime",
+            ColumnType::Enum { .. } => "enum",
+            ColumnType::Reference { .. } => "reference",
+        };
+        write!(f, "{}", name)
+    }
 }
@@ -1,3 +1,5 @@
+#![recursion_limit = "256"]
+
 mod entity;
 mod po;
 pub mod repository;
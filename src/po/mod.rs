@@ -1,6 +1,85 @@
+use std::str::FromStr;
+
 use chrono::{DateTime, Utc};
-use mongodb::bson::oid::ObjectId;
-use serde::{Deserialize, Serialize};
+use mongodb::bson::oid::{self, ObjectId};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use serde_json::{Map, Value};
+
+use crate::entity::{self, ColumnType, KeyScope, Role};
+
+/// 持久化对象特型，描述持久化对象和实体之间的转换
+///
+/// 持久化对象都带有version字段，每次更新加1，旧数据没有这个字段时按0处理
+pub trait Persistent: Serialize + DeserializeOwned + Sized + Send + Sync {
+    type Entity: Send + Sync;
+
+    /// 存储时使用的集合名
+    const COLLECTION: &'static str;
+
+    /// 实体转成持久化对象，实体id为空时持久化对象没有id
+    fn from_entity(entity: &Self::Entity) -> Result<Self, oid::Error>;
+    /// 持久化对象转成实体
+    fn into_entity(self) -> Self::Entity;
+}
+
+/// 时间保存成精确到毫秒的UTC RFC3339字符串
+///
+/// 和查询条件、行数据中的时间格式相同，按字符串比较时顺序正确。
+/// 读取时接受任意精度，旧数据中chrono默认格式的时间也能读取
+mod millis {
+    use chrono::{DateTime, SecondsFormat, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(dt: &DateTime<Utc>, s: S) -> Result<S::Ok, S::Error> {
+        s.serialize_str(&dt.to_rfc3339_opts(SecondsFormat::Millis, true))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<DateTime<Utc>, D::Error> {
+        DateTime::<Utc>::deserialize(d)
+    }
+}
+
+/// 可以为空的时间，格式和millis相同，没有值时保存为null
+mod optional_millis {
+    use chrono::{DateTime, Utc};
+    use serde::{Deserialize, Deserializer, Serializer};
+
+    pub fn serialize<S: Serializer>(dt: &Option<DateTime<Utc>>, s: S) -> Result<S::Ok, S::Error> {
+        match dt {
+            Some(dt) => super::millis::serialize(dt, s),
+            None => s.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(d: D) -> Result<Option<DateTime<Utc>>, D::Error> {
+        Option::<DateTime<Utc>>::deserialize(d)
+    }
+}
+
+/// 实体中的字符串id转成ObjectId，空字符串表示还没有id
+fn to_oid(id: &str) -> Result<Option<ObjectId>, oid::Error> {
+    if id.is_empty() {
+        Ok(None)
+    } else {
+        ObjectId::from_str(id).map(Some)
+    }
+}
+
+fn to_hex(id: Option<ObjectId>) -> String {
+    match id {
+        Some(i) => i.to_hex(),
+        None => String::new(),
+    }
+}
+
+/// 只有id的创建者，用户名和显示名由服务层批量查询后填充
+fn creator_entity(creator: ObjectId) -> entity::UserSummary {
+    entity::UserSummary {
+        id: creator.to_hex(),
+        username: String::new(),
+        display_name: None,
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
@@ -8,7 +87,13 @@ pub struct User {
     #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
     pub id: Option<ObjectId>,
     pub username: String,
-    pub passowrd_hash: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+    /// 旧版本中字段名拼错成了passowrdHash
+    #[serde(alias = "passowrdHash")]
+    pub password_hash: String,
+    #[serde(default)]
+    pub version: i64,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -19,8 +104,16 @@ pub struct Workspace {
     pub name: String,
     pub description: String,
     pub creator: ObjectId,
+    #[serde(with = "millis")]
     pub created_at: DateTime<Utc>,
+    #[serde(with = "millis")]
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub version: i64,
+    #[serde(default, with = "optional_millis")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub deleted_by: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -32,8 +125,16 @@ pub struct Catalog {
     pub name: String,
     pub description: String,
     pub creator: ObjectId,
+    #[serde(with = "millis")]
     pub created_at: DateTime<Utc>,
+    #[serde(with = "millis")]
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub version: i64,
+    #[serde(default, with = "optional_millis")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub deleted_by: Option<String>,
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -44,7 +145,331 @@ pub struct Table {
     pub catalog_id: String,
     pub name: String,
     pub description: String,
+    #[serde(default)]
+    pub columns: Vec<Column>,
     pub creator: ObjectId,
+    #[serde(with = "millis")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "millis")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub version: i64,
+    #[serde(default, with = "optional_millis")]
+    pub deleted_at: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub deleted_by: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Row {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub table_id: String,
+    pub data: Map<String, Value>,
+    #[serde(with = "millis")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "millis")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub version: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Membership {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub workspace_id: String,
+    pub user_id: String,
+    pub role: Role,
+    #[serde(with = "millis")]
+    pub created_at: DateTime<Utc>,
+    #[serde(with = "millis")]
+    pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub version: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKey {
+    #[serde(rename = "_id", skip_serializing_if = "Option::is_none")]
+    pub id: Option<ObjectId>,
+    pub user_id: String,
+    pub name: String,
+    pub prefix: String,
+    pub key_hash: String,
+    pub scope: KeyScope,
+    #[serde(default, with = "optional_millis")]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(default, with = "optional_millis")]
+    pub last_used_at: Option<DateTime<Utc>>,
+    #[serde(with = "millis")]
     pub created_at: DateTime<Utc>,
+    #[serde(with = "millis")]
     pub updated_at: DateTime<Utc>,
+    #[serde(default)]
+    pub version: i64,
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Column {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    pub nullable: bool,
+    pub default: Option<Value>,
+    pub unique: bool,
+}
+
+impl Persistent for User {
+    type Entity = entity::User;
+
+    const COLLECTION: &'static str = "users";
+
+    fn from_entity(entity: &entity::User) -> Result<Self, oid::Error> {
+        Ok(User {
+            id: to_oid(&entity.id)?,
+            username: entity.username.clone(),
+            display_name: entity.display_name.clone(),
+            password_hash: entity.password_hash.clone(),
+            version: entity.version,
+        })
+    }
+
+    fn into_entity(self) -> entity::User {
+        entity::User {
+            id: to_hex(self.id),
+            username: self.username,
+            display_name: self.display_name,
+            password_hash: self.password_hash,
+            version: self.version,
+        }
+    }
+}
+
+impl Persistent for Workspace {
+    type Entity = entity::Workspace;
+
+    const COLLECTION: &'static str = "workspaces";
+
+    fn from_entity(entity: &entity::Workspace) -> Result<Self, oid::Error> {
+        Ok(Workspace {
+            id: to_oid(&entity.id)?,
+            name: entity.name.clone(),
+            description: entity.description.clone(),
+            creator: ObjectId::from_str(&entity.creator.id)?,
+            created_at: entity.created_at,
+            updated_at: entity.updated_at,
+            version: entity.version,
+            deleted_at: entity.deleted_at,
+            deleted_by: entity.deleted_by.clone(),
+        })
+    }
+
+    fn into_entity(self) -> entity::Workspace {
+        entity::Workspace {
+            id: to_hex(self.id),
+            name: self.name,
+            description: self.description,
+            creator: creator_entity(self.creator),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            version: self.version,
+            deleted_at: self.deleted_at,
+            deleted_by: self.deleted_by,
+        }
+    }
+}
+
+impl Persistent for Catalog {
+    type Entity = entity::Catalog;
+
+    const COLLECTION: &'static str = "catalogs";
+
+    fn from_entity(entity: &entity::Catalog) -> Result<Self, oid::Error> {
+        Ok(Catalog {
+            id: to_oid(&entity.id)?,
+            workspace_id: entity.workspace_id.clone(),
+            name: entity.name.clone(),
+            description: entity.description.clone(),
+            creator: ObjectId::from_str(&entity.creator.id)?,
+            created_at: entity.created_at,
+            updated_at: entity.updated_at,
+            version: entity.version,
+            deleted_at: entity.deleted_at,
+            deleted_by: entity.deleted_by.clone(),
+        })
+    }
+
+    fn into_entity(self) -> entity::Catalog {
+        entity::Catalog {
+            id: to_hex(self.id),
+            workspace_id: self.workspace_id,
+            name: self.name,
+            description: self.description,
+            creator: creator_entity(self.creator),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            version: self.version,
+            deleted_at: self.deleted_at,
+            deleted_by: self.deleted_by,
+        }
+    }
+}
+
+impl Persistent for Table {
+    type Entity = entity::Table;
+
+    const COLLECTION: &'static str = "tables";
+
+    fn from_entity(entity: &entity::Table) -> Result<Self, oid::Error> {
+        Ok(Table {
+            id: to_oid(&entity.id)?,
+            catalog_id: entity.catalog_id.clone(),
+            name: entity.name.clone(),
+            description: entity.description.clone(),
+            columns: entity
+                .columns
+                .iter()
+                .map(|c| Column {
+                    name: c.name.clone(),
+                    column_type: c.column_type.clone(),
+                    nullable: c.nullable,
+                    default: c.default.clone(),
+                    unique: c.unique,
+                })
+                .collect(),
+            creator: ObjectId::from_str(&entity.creator.id)?,
+            created_at: entity.created_at,
+            updated_at: entity.updated_at,
+            version: entity.version,
+            deleted_at: entity.deleted_at,
+            deleted_by: entity.deleted_by.clone(),
+        })
+    }
+
+    fn into_entity(self) -> entity::Table {
+        entity::Table {
+            id: to_hex(self.id),
+            catalog_id: self.catalog_id,
+            name: self.name,
+            description: self.description,
+            columns: self
+                .columns
+                .into_iter()
+                .map(|c| entity::Column {
+                    name: c.name,
+                    column_type: c.column_type,
+                    nullable: c.nullable,
+                    default: c.default,
+                    unique: c.unique,
+                })
+                .collect(),
+            creator: creator_entity(self.creator),
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            version: self.version,
+            deleted_at: self.deleted_at,
+            deleted_by: self.deleted_by,
+        }
+    }
+}
+
+impl Persistent for Row {
+    type Entity = entity::Row;
+
+    const COLLECTION: &'static str = "rows";
+
+    fn from_entity(entity: &entity::Row) -> Result<Self, oid::Error> {
+        Ok(Row {
+            id: to_oid(&entity.id)?,
+            table_id: entity.table_id.clone(),
+            data: entity.data.clone(),
+            created_at: entity.created_at,
+            updated_at: entity.updated_at,
+            version: entity.version,
+        })
+    }
+
+    fn into_entity(self) -> entity::Row {
+        entity::Row {
+            id: to_hex(self.id),
+            table_id: self.table_id,
+            data: self.data,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            version: self.version,
+        }
+    }
+}
+
+impl Persistent for Membership {
+    type Entity = entity::Membership;
+
+    const COLLECTION: &'static str = "memberships";
+
+    fn from_entity(entity: &entity::Membership) -> Result<Self, oid::Error> {
+        Ok(Membership {
+            id: to_oid(&entity.id)?,
+            workspace_id: entity.workspace_id.clone(),
+            user_id: entity.user_id.clone(),
+            role: entity.role,
+            created_at: entity.created_at,
+            updated_at: entity.updated_at,
+            version: entity.version,
+        })
+    }
+
+    fn into_entity(self) -> entity::Membership {
+        entity::Membership {
+            id: to_hex(self.id),
+            workspace_id: self.workspace_id,
+            user_id: self.user_id,
+            role: self.role,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            version: self.version,
+        }
+    }
+}
+
+impl Persistent for ApiKey {
+    type Entity = entity::ApiKey;
+
+    const COLLECTION: &'static str = "apiKeys";
+
+    fn from_entity(entity: &entity::ApiKey) -> Result<Self, oid::Error> {
+        Ok(ApiKey {
+            id: to_oid(&entity.id)?,
+            user_id: entity.user_id.clone(),
+            name: entity.name.clone(),
+            prefix: entity.prefix.clone(),
+            key_hash: entity.key_hash.clone(),
+            scope: entity.scope.clone(),
+            expires_at: entity.expires_at,
+            last_used_at: entity.last_used_at,
+            created_at: entity.created_at,
+            updated_at: entity.updated_at,
+            version: entity.version,
+        })
+    }
+
+    fn into_entity(self) -> entity::ApiKey {
+        entity::ApiKey {
+            id: to_hex(self.id),
+            user_id: self.user_id,
+            name: self.name,
+            prefix: self.prefix,
+            key_hash: self.key_hash,
+            scope: self.scope,
+            expires_at: self.expires_at,
+            last_used_at: self.last_used_at,
+            created_at: self.created_at,
+            updated_at: self.updated_at,
+            version: self.version,
+        }
+    }
 }
@@ -1,5 +1,8 @@
-use chrono::{DateTime, Utc};
+use std::{convert::TryFrom, str::FromStr};
+
+use chrono::{DateTime, SecondsFormat, Utc};
 use mongodb::bson::oid::ObjectId;
+use serde_json::{json, Value};
 
 /// 描述条件的结构
 #[derive(Clone, Debug)]
@@ -22,6 +25,23 @@ impl Condition {
         })
     }
 
+    /// 将多个条件用且组合起来，忽略其中的空条件
+    pub fn all(conditions: Vec<Condition>) -> Self {
+        let mut conds: Vec<Condition> = conditions
+            .into_iter()
+            .filter(|c| !matches!(c, Condition::Empty))
+            .collect();
+        match conds.len() {
+            0 => Condition::Empty,
+            1 => conds.remove(0),
+            _ => Condition::Complex {
+                and: Box::new(conds),
+                or: Box::new(vec![]),
+                nor: Box::new(vec![]),
+            },
+        }
+    }
+
     pub fn and(conditions: Vec<(String, Operate, ConditionValue)>) -> Self {
         let conds = conditions
             .iter()
@@ -100,9 +120,12 @@ pub enum ConditionValue {
     Int32VecValue(Vec<i32>),
     Int64Value(i64),
     Int64VecValue(Vec<i64>),
+    DoubleValue(f64),
     BooleanValue(bool),
     DateTimeValue(DateTime<Utc>),
     ObjectIdValue(ObjectId),
+    ObjectIdVecValue(Vec<ObjectId>),
+    NullValue,
 }
 
 #[derive(Clone, Debug)]
@@ -113,19 +136,244 @@ pub struct ConditionNode {
 }
 
 /// 分页设置
-/// #[derive(Clone, Debug)]
+#[derive(Clone, Debug)]
 pub struct PageOption {
     pub page: usize,
     pub size: usize,
 }
 
+/// 排序方向
+#[derive(Clone, Debug, PartialEq)]
+pub enum SortOrder {
+    Asc,
+    Desc,
+}
+
+/// 排序设置，多个排序字段按先后顺序生效
+#[derive(Clone, Debug)]
+pub struct SortOption {
+    pub field: String,
+    pub order: SortOrder,
+}
+
+impl SortOption {
+    pub fn new(field: &str, order: SortOrder) -> Self {
+        SortOption {
+            field: field.to_string(),
+            order,
+        }
+    }
+}
+
+/// 部分更新，set中的字段设为新值，unset中的字段删除，rename中的字段改名
+///
+/// 字段可以是顶层字段，也可以是JSON字段中的一层键，例如`data.name`；
+/// SQL存储中删除顶层字段即设为NULL，改名只支持同一个JSON字段中的键。
+/// 同一个字段只能出现在一种修改中
+#[derive(Clone, Debug, Default)]
+pub struct Patch {
+    pub set: Vec<(String, ConditionValue)>,
+    pub unset: Vec<String>,
+    pub rename: Vec<(String, String)>,
+}
+
+impl Patch {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set(mut self, field: &str, value: ConditionValue) -> Self {
+        self.set.push((field.to_string(), value));
+        self
+    }
+
+    pub fn unset(mut self, field: &str) -> Self {
+        self.unset.push(field.to_string());
+        self
+    }
+
+    pub fn rename(mut self, from: &str, to: &str) -> Self {
+        self.rename.push((from.to_string(), to.to_string()));
+        self
+    }
+}
+
+/// 游标分页使用的排序，在最后补上_id，保证每条数据的位置都是唯一的
+pub fn keyset_sorts(sorts: &[SortOption]) -> Vec<SortOption> {
+    let mut keys = sorts.to_vec();
+    if !keys.iter().any(|s| s.field == "_id") {
+        keys.push(SortOption::new("_id", SortOrder::Asc));
+    }
+    keys
+}
+
+/// 游标中的一个排序键，value是上一页最后一条数据在这个键上的值
+#[derive(Clone, Debug)]
+pub struct CursorKey {
+    pub field: String,
+    pub order: SortOrder,
+    pub value: ConditionValue,
+}
+
+/// 游标，记录上一页最后一条数据在各排序键上的位置
+#[derive(Clone, Debug)]
+pub struct Cursor {
+    pub keys: Vec<CursorKey>,
+}
+
+impl Cursor {
+    /// 游标是否是按这组排序生成的，sorts需要是keyset_sorts的结果
+    pub fn matches(&self, sorts: &[SortOption]) -> bool {
+        self.keys.len() == sorts.len()
+            && self
+                .keys
+                .iter()
+                .zip(sorts)
+                .all(|(k, s)| k.field == s.field && k.order == s.order)
+    }
+
+    /// 游标之后的数据的条件
+    ///
+    /// 按(k1, k2, ..., _id)的顺序比较：k1在后面，或k1相等而k2在后面，以此类推。
+    /// null在排序中是最小的，升序时排在最前，降序时排在最后
+    pub fn condition(&self) -> Condition {
+        let mut branches = vec![];
+        for (i, key) in self.keys.iter().enumerate() {
+            let after = match (&key.order, &key.value) {
+                (SortOrder::Asc, ConditionValue::NullValue) => {
+                    Condition::single(key.field.clone(), Operate::Ne, ConditionValue::NullValue)
+                }
+                (SortOrder::Asc, value) => {
+                    Condition::single(key.field.clone(), Operate::Gt, value.clone())
+                }
+                (SortOrder::Desc, ConditionValue::NullValue) => continue,
+                (SortOrder::Desc, value) => Condition::Complex {
+                    and: Box::new(vec![]),
+                    or: Box::new(vec![
+                        Condition::single(key.field.clone(), Operate::Lt, value.clone()),
+                        Condition::single(
+                            key.field.clone(),
+                            Operate::Eq,
+                            ConditionValue::NullValue,
+                        ),
+                    ]),
+                    nor: Box::new(vec![]),
+                },
+            };
+            let mut conds: Vec<Condition> = self.keys[..i]
+                .iter()
+                .map(|k| Condition::single(k.field.clone(), Operate::Eq, k.value.clone()))
+                .collect();
+            conds.push(after);
+            branches.push(Condition::all(conds));
+        }
+        Condition::Complex {
+            and: Box::new(vec![]),
+            or: Box::new(branches),
+            nor: Box::new(vec![]),
+        }
+    }
+
+    /// 编码成不透明的字符串，在接口上传递
+    pub fn encode(&self) -> String {
+        let keys: Vec<Value> = self
+            .keys
+            .iter()
+            .map(|k| {
+                let order = match k.order {
+                    SortOrder::Asc => 1,
+                    SortOrder::Desc => -1,
+                };
+                json!({"f": k.field, "o": order, "v": value_to_json(&k.value)})
+            })
+            .collect();
+        base64::encode_config(Value::Array(keys).to_string(), base64::URL_SAFE_NO_PAD)
+    }
+
+    /// 解码接口上传来的游标，格式不对时返回None
+    pub fn decode(token: &str) -> Option<Cursor> {
+        let bytes = base64::decode_config(token, base64::URL_SAFE_NO_PAD).ok()?;
+        let value: Value = serde_json::from_slice(&bytes).ok()?;
+        let keys = value
+            .as_array()?
+            .iter()
+            .map(|k| {
+                let order = match k.get("o")?.as_i64()? {
+                    1 => SortOrder::Asc,
+                    -1 => SortOrder::Desc,
+                    _ => return None,
+                };
+                Some(CursorKey {
+                    field: k.get("f")?.as_str()?.to_string(),
+                    order,
+                    value: value_from_json(k.get("v")?)?,
+                })
+            })
+            .collect::<Option<Vec<_>>>()?;
+        if keys.is_empty() {
+            None
+        } else {
+            Some(Cursor { keys })
+        }
+    }
+}
+
+/// 游标中的值带上类型标记，解码时才能还原成同样的类型
+fn value_to_json(value: &ConditionValue) -> Value {
+    use ConditionValue::*;
+    match value {
+        StringValue(v) => json!({ "s": v }),
+        Int32Value(v) => json!({ "i": v }),
+        Int64Value(v) => json!({ "l": v }),
+        DoubleValue(v) => json!({ "d": v }),
+        BooleanValue(v) => json!({ "b": v }),
+        DateTimeValue(v) => json!({ "t": v.to_rfc3339_opts(SecondsFormat::Millis, true) }),
+        ObjectIdValue(v) => json!({ "o": v.to_hex() }),
+        // 排序键上不会出现数组
+        StringVecValue(_) | Int32VecValue(_) | Int64VecValue(_) | ObjectIdVecValue(_)
+        | NullValue => Value::Null,
+    }
+}
+
+fn value_from_json(value: &Value) -> Option<ConditionValue> {
+    use ConditionValue::*;
+    if value.is_null() {
+        return Some(NullValue);
+    }
+    let (tag, v) = value.as_object()?.iter().next()?;
+    match tag.as_str() {
+        "s" => v.as_str().map(|s| StringValue(s.to_string())),
+        // 被改过的游标中的值可能超出i32，不截断，按i64处理
+        "i" => v
+            .as_i64()
+            .map(|i| i32::try_from(i).map_or(Int64Value(i), Int32Value)),
+        "l" => v.as_i64().map(Int64Value),
+        "d" => v.as_f64().map(DoubleValue),
+        "b" => v.as_bool().map(BooleanValue),
+        "t" => DateTime::parse_from_rfc3339(v.as_str()?)
+            .ok()
+            .map(|dt| DateTimeValue(dt.with_timezone(&Utc))),
+        "o" => ObjectId::from_str(v.as_str()?).ok().map(ObjectIdValue),
+        _ => None,
+    }
+}
+
+/// 游标分页设置，after为None时从头开始
+#[derive(Clone, Debug)]
+pub struct CursorOption {
+    pub after: Option<Cursor>,
+    pub size: usize,
+}
+
 /// 条件转换器特型
 ///
 /// 将描述条件的结构转成实际底层数据库交互的条件逻辑结构
 pub trait ConditionHandler {
     type TransferResult;
     type TransferPageResult;
+    type TransferSortResult;
 
     fn transfer_condition(condition: &Condition) -> Self::TransferResult;
     fn transfer_page_options(page_option: &PageOption) -> Self::TransferPageResult;
+    fn transfer_sort(sorts: &[SortOption]) -> Self::TransferSortResult;
 }
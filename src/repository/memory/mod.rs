@@ -0,0 +1,711 @@
+use std::{
+    cmp::Ordering,
+    collections::HashMap,
+    marker::PhantomData,
+    sync::{Arc, RwLock},
+};
+
+use async_trait::async_trait;
+use chrono::SecondsFormat;
+use futures::future::BoxFuture;
+use mongodb::bson::{self, oid::ObjectId, Bson, Document};
+
+use crate::{
+    po::{self, Persistent},
+    repository::{
+        condition::*, soft_delete::SoftDelete, CRUDRepository, CursorResult, PageResult,
+        PaginationRepository, RepositoryError, Storage, TransactionError,
+    },
+};
+
+/// 各集合的唯一键，和其他存储的唯一索引保持一致
+const UNIQUE_KEYS: &[(&str, &[&str])] = &[
+    ("users", &["username"]),
+    ("memberships", &["workspaceId", "userId"]),
+    ("apiKeys", &["keyHash"]),
+];
+
+/// 内存数据库，数据按集合名保存为文档，只在进程内有效
+///
+/// 事务在数据的副本上执行，提交时把改动按_id合并回来
+#[derive(Clone, Debug, Default)]
+pub struct MemoryDB {
+    collections: Arc<RwLock<HashMap<String, Vec<Document>>>>,
+    in_transaction: bool,
+}
+
+impl MemoryDB {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// 读取集合中满足条件的文档
+    fn select(&self, collection: &str, condition: &Condition) -> Vec<Document> {
+        let predicate = MemoryConditionHandler::transfer_condition(condition);
+        let collections = self.collections.read().unwrap();
+        match collections.get(collection) {
+            Some(docs) => docs.iter().filter(|d| predicate(d)).cloned().collect(),
+            None => vec![],
+        }
+    }
+
+    /// 满足条件并排序后的文档
+    fn select_sorted(
+        &self,
+        collection: &str,
+        condition: &Condition,
+        sorts: &[SortOption],
+    ) -> Vec<Document> {
+        let mut docs = self.select(collection, condition);
+        let compare = MemoryConditionHandler::transfer_sort(sorts);
+        docs.sort_by(|a, b| compare(a, b));
+        docs
+    }
+
+    /// 插入多个文档，有一个违反唯一键时都不插入
+    fn insert(
+        &self,
+        collection: &str,
+        docs: Vec<Document>,
+    ) -> Result<Vec<ObjectId>, RepositoryError> {
+        let mut collections = self.collections.write().unwrap();
+        let existing = collections.entry(collection.to_string()).or_default();
+        let mut inserted: Vec<Document> = Vec::with_capacity(docs.len());
+        let mut oids = Vec::with_capacity(docs.len());
+        for mut doc in docs {
+            let oid = ObjectId::new();
+            doc.insert("_id", oid);
+            check_unique(collection, existing.iter().chain(inserted.iter()), &doc)?;
+            inserted.push(doc);
+            oids.push(oid);
+        }
+        existing.extend(inserted);
+        Ok(oids)
+    }
+
+    /// 替换id和版本号都相同的文档，返回新的版本号
+    fn replace(
+        &self,
+        collection: &str,
+        oid: ObjectId,
+        mut doc: Document,
+    ) -> Result<i64, RepositoryError> {
+        let version = doc.get_i64("version").unwrap_or_default();
+        let mut collections = self.collections.write().unwrap();
+        let docs = collections
+            .get_mut(collection)
+            .ok_or(RepositoryError::DataNotFound)?;
+        let index = docs
+            .iter()
+            .position(|d| d.get("_id") == Some(&Bson::ObjectId(oid)))
+            .ok_or(RepositoryError::DataNotFound)?;
+        if docs[index].get_i64("version").unwrap_or_default() != version {
+            return Err(RepositoryError::VersionConflict);
+        }
+        doc.insert("version", version + 1);
+        check_unique(collection, docs.iter(), &doc)?;
+        docs[index] = doc;
+        Ok(version + 1)
+    }
+
+    /// 部分更新第一个满足条件的文档，返回更新后的文档
+    fn modify(
+        &self,
+        collection: &str,
+        condition: &Condition,
+        patch: &Patch,
+    ) -> Result<Document, RepositoryError> {
+        let predicate = MemoryConditionHandler::transfer_condition(condition);
+        let mut collections = self.collections.write().unwrap();
+        let docs = collections
+            .get_mut(collection)
+            .ok_or(RepositoryError::DataNotFound)?;
+        let index = docs
+            .iter()
+            .position(|d| predicate(d))
+            .ok_or(RepositoryError::DataNotFound)?;
+        let mut doc = docs[index].clone();
+        apply_patch(&mut doc, patch);
+        check_unique(collection, docs.iter(), &doc)?;
+        docs[index] = doc.clone();
+        Ok(doc)
+    }
+
+    /// 部分更新所有满足条件的文档，返回更新的数量，有一个违反唯一键时都不更新
+    fn modify_all(
+        &self,
+        collection: &str,
+        condition: &Condition,
+        patch: &Patch,
+    ) -> Result<u64, RepositoryError> {
+        let predicate = MemoryConditionHandler::transfer_condition(condition);
+        let mut collections = self.collections.write().unwrap();
+        let docs = match collections.get_mut(collection) {
+            Some(docs) => docs,
+            None => return Ok(0),
+        };
+        if unique_keys(collection).is_none() {
+            let mut count = 0;
+            for doc in docs.iter_mut().filter(|d| predicate(d)) {
+                apply_patch(doc, patch);
+                count += 1;
+            }
+            return Ok(count);
+        }
+        let mut updated = docs.clone();
+        let mut changed = vec![];
+        for (index, doc) in updated.iter_mut().enumerate() {
+            if predicate(doc) {
+                apply_patch(doc, patch);
+                changed.push(index);
+            }
+        }
+        for index in changed.iter() {
+            check_unique(collection, updated.iter(), &updated[*index])?;
+        }
+        *docs = updated;
+        Ok(changed.len() as u64)
+    }
+
+    /// 把事务副本相对快照的改动写回，事务期间其他请求对别的文档的改动不受影响
+    ///
+    /// 合并后违反唯一键时整个事务都不写回
+    fn merge(
+        &self,
+        snapshot: &HashMap<String, Vec<Document>>,
+        tx: &MemoryDB,
+    ) -> Result<(), RepositoryError> {
+        let changed = tx.collections.read().unwrap();
+        let mut collections = self.collections.write().unwrap();
+        let mut merged = HashMap::new();
+        for (name, after) in changed.iter() {
+            let before = snapshot.get(name).map(Vec::as_slice).unwrap_or_default();
+            let written: Vec<&Document> = after.iter().filter(|a| !before.contains(a)).collect();
+            let removed: Vec<&Document> = before
+                .iter()
+                .filter(|b| !after.iter().any(|a| a.get("_id") == b.get("_id")))
+                .collect();
+            if written.is_empty() && removed.is_empty() {
+                continue;
+            }
+            let mut docs = collections.get(name).cloned().unwrap_or_default();
+            docs.retain(|d| !removed.iter().any(|r| r.get("_id") == d.get("_id")));
+            for doc in written.iter() {
+                match docs.iter_mut().find(|d| d.get("_id") == doc.get("_id")) {
+                    Some(existing) => *existing = (*doc).clone(),
+                    None => docs.push((*doc).clone()),
+                }
+            }
+            for doc in written {
+                check_unique(name, docs.iter(), doc)?;
+            }
+            merged.insert(name.clone(), docs);
+        }
+        collections.extend(merged);
+        Ok(())
+    }
+
+    /// 删除第一个满足条件的文档
+    fn remove(&self, collection: &str, condition: &Condition) -> bool {
+        let predicate = MemoryConditionHandler::transfer_condition(condition);
+        let mut collections = self.collections.write().unwrap();
+        let docs = match collections.get_mut(collection) {
+            Some(docs) => docs,
+            None => return false,
+        };
+        match docs.iter().position(predicate) {
+            Some(index) => {
+                docs.remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// 删除所有满足条件的文档，返回删除的数量
+    fn remove_all(&self, collection: &str, condition: &Condition) -> u64 {
+        let predicate = MemoryConditionHandler::transfer_condition(condition);
+        let mut collections = self.collections.write().unwrap();
+        let docs = match collections.get_mut(collection) {
+            Some(docs) => docs,
+            None => return 0,
+        };
+        let before = docs.len();
+        docs.retain(|d| !predicate(d));
+        (before - docs.len()) as u64
+    }
+}
+
+fn unique_keys(collection: &str) -> Option<&'static [&'static str]> {
+    UNIQUE_KEYS
+        .iter()
+        .find(|(name, _)| *name == collection)
+        .map(|(_, keys)| *keys)
+}
+
+/// 检查文档的唯一键是否和集合中其他文档重复，和MongoDB一样缺失的字段也参与比较
+fn check_unique<'a>(
+    collection: &str,
+    docs: impl Iterator<Item = &'a Document>,
+    doc: &Document,
+) -> Result<(), RepositoryError> {
+    let keys = match unique_keys(collection) {
+        Some(keys) => keys,
+        None => return Ok(()),
+    };
+    let id = doc.get("_id");
+    let duplicated = docs
+        .filter(|d| d.get("_id") != id)
+        .any(|d| keys.iter().all(|k| path_value(d, k) == path_value(doc, k)));
+    if duplicated {
+        return Err(RepositoryError::Conflict(format!(
+            "duplicate key in {}: {}",
+            collection,
+            keys.join(", ")
+        )));
+    }
+    Ok(())
+}
+
+/// 按Patch修改文档，版本号加一，和MongoDB一样改名时原字段不存在就什么都不做
+fn apply_patch(doc: &mut Document, patch: &Patch) {
+    for (field, value) in patch.set.iter() {
+        if let Some((parent, key)) = path_parent(doc, field, true) {
+            parent.insert(key, to_bson(value));
+        }
+    }
+    for field in patch.unset.iter() {
+        if let Some((parent, key)) = path_parent(doc, field, false) {
+            parent.remove(key);
+        }
+    }
+    for (from, to) in patch.rename.iter() {
+        let value = match path_parent(doc, from, false).and_then(|(p, k)| p.remove(k)) {
+            Some(value) => value,
+            None => continue,
+        };
+        if let Some((parent, key)) = path_parent(doc, to, true) {
+            parent.insert(key, value);
+        }
+    }
+    let version = doc.get_i64("version").unwrap_or_default();
+    doc.insert("version", version + 1);
+}
+
+/// 路径最后一层所在的文档和键，create为true时补上缺失的中间文档
+fn path_parent<'a, 'p>(
+    doc: &'a mut Document,
+    path: &'p str,
+    create: bool,
+) -> Option<(&'a mut Document, &'p str)> {
+    let mut keys: Vec<&str> = path.split('.').collect();
+    let last = keys.pop()?;
+    let mut current = doc;
+    for key in keys {
+        if create && !matches!(current.get(key), Some(Bson::Document(_))) {
+            current.insert(key, Document::new());
+        }
+        current = match current.get_mut(key) {
+            Some(Bson::Document(d)) => d,
+            _ => return None,
+        };
+    }
+    Some((current, last))
+}
+
+#[async_trait]
+impl Storage for MemoryDB {
+    type UserRepo = UserRepo;
+    type WorkspaceRepo = WorkspaceRepo;
+    type CatalogRepo = CatalogRepo;
+    type TableRepo = TableRepo;
+    type RowRepo = RowRepo;
+    type MembershipRepo = MembershipRepo;
+    type ApiKeyRepo = ApiKeyRepo;
+
+    fn user_repo(&self) -> UserRepo {
+        MemoryRepo::new(self.clone())
+    }
+    fn workspace_repo(&self) -> WorkspaceRepo {
+        SoftDelete::new(MemoryRepo::new(self.clone()))
+    }
+    fn catalog_repo(&self) -> CatalogRepo {
+        SoftDelete::new(MemoryRepo::new(self.clone()))
+    }
+    fn table_repo(&self) -> TableRepo {
+        SoftDelete::new(MemoryRepo::new(self.clone()))
+    }
+    fn row_repo(&self) -> RowRepo {
+        MemoryRepo::new(self.clone())
+    }
+    fn membership_repo(&self) -> MembershipRepo {
+        MemoryRepo::new(self.clone())
+    }
+    fn api_key_repo(&self) -> ApiKeyRepo {
+        MemoryRepo::new(self.clone())
+    }
+
+    /// 内存中没有临时错误，不需要重试
+    async fn with_transaction<T, E, F>(&self, work: F) -> Result<T, E>
+    where
+        T: Send,
+        E: TransactionError,
+        F: for<'a> Fn(&'a Self) -> BoxFuture<'a, Result<T, E>> + Send + Sync,
+    {
+        if self.in_transaction {
+            return work(self).await;
+        }
+        let snapshot = self.collections.read().unwrap().clone();
+        let tx = MemoryDB {
+            collections: Arc::new(RwLock::new(snapshot.clone())),
+            in_transaction: true,
+        };
+        let value = work(&tx).await?;
+        self.merge(&snapshot, &tx)?;
+        Ok(value)
+    }
+}
+
+/// 判断文档是否满足条件的函数
+pub type Predicate = Box<dyn Fn(&Document) -> bool + Send + Sync>;
+/// 比较两个文档先后顺序的函数
+pub type Comparator = Box<dyn Fn(&Document, &Document) -> Ordering + Send + Sync>;
+
+/// 内存条件处理器，条件的语义和MongoDB保持一致
+pub struct MemoryConditionHandler {}
+
+impl ConditionHandler for MemoryConditionHandler {
+    type TransferResult = Predicate;
+    type TransferPageResult = (usize, usize);
+    type TransferSortResult = Comparator;
+
+    fn transfer_condition(condition: &Condition) -> Self::TransferResult {
+        match condition {
+            Condition::Empty => Box::new(|_| true),
+            Condition::Single(node) => {
+                let node = node.clone();
+                Box::new(move |doc| node_matches(&node, doc))
+            }
+            Condition::Complex { and, or, nor } => {
+                let transfer = |conds: &[Condition]| -> Vec<Predicate> {
+                    conds.iter().map(Self::transfer_condition).collect()
+                };
+                let (ands, ors, nors) = (transfer(and), transfer(or), transfer(nor));
+                Box::new(move |doc| {
+                    ands.iter().all(|p| p(doc))
+                        && (ors.is_empty() || ors.iter().any(|p| p(doc)))
+                        && !nors.iter().any(|p| p(doc))
+                })
+            }
+        }
+    }
+
+    fn transfer_page_options(page_option: &PageOption) -> Self::TransferPageResult {
+        // 页码从1开始，传入0时按第一页处理
+        let skip = page_option.page.saturating_sub(1) * page_option.size;
+        (skip, page_option.size)
+    }
+
+    fn transfer_sort(sorts: &[SortOption]) -> Self::TransferSortResult {
+        let keys = keyset_sorts(sorts);
+        Box::new(move |a, b| {
+            for key in keys.iter() {
+                let ordering = sort_compare(path_value(a, &key.field), path_value(b, &key.field));
+                let ordering = match key.order {
+                    SortOrder::Asc => ordering,
+                    SortOrder::Desc => ordering.reverse(),
+                };
+                if ordering != Ordering::Equal {
+                    return ordering;
+                }
+            }
+            Ordering::Equal
+        })
+    }
+}
+
+/// 按点分隔的路径取文档中的值
+fn path_value<'a>(doc: &'a Document, path: &str) -> Option<&'a Bson> {
+    let mut keys = path.split('.');
+    let mut value = keys.next().and_then(|k| doc.get(k));
+    for key in keys {
+        value = match value {
+            Some(Bson::Document(d)) => d.get(key),
+            _ => None,
+        };
+    }
+    value
+}
+
+/// 条件中的值转成Bson，时间和MongoDB中一样按RFC3339字符串处理
+fn to_bson(value: &ConditionValue) -> Bson {
+    use ConditionValue::*;
+    match value {
+        StringValue(v) => Bson::String(v.clone()),
+        Int32Value(v) => Bson::Int32(*v),
+        Int64Value(v) => Bson::Int64(*v),
+        DoubleValue(v) => Bson::Double(*v),
+        BooleanValue(v) => Bson::Boolean(*v),
+        DateTimeValue(v) => Bson::String(v.to_rfc3339_opts(SecondsFormat::Millis, true)),
+        ObjectIdValue(v) => Bson::ObjectId(*v),
+        NullValue => Bson::Null,
+        StringVecValue(v) => Bson::Array(v.iter().map(|x| Bson::String(x.clone())).collect()),
+        Int32VecValue(v) => Bson::Array(v.iter().map(|x| Bson::Int32(*x)).collect()),
+        Int64VecValue(v) => Bson::Array(v.iter().map(|x| Bson::Int64(*x)).collect()),
+        ObjectIdVecValue(v) => Bson::Array(v.iter().map(|x| Bson::ObjectId(*x)).collect()),
+    }
+}
+
+fn node_matches(node: &ConditionNode, doc: &Document) -> bool {
+    // 缺失的字段当作null
+    let actual = path_value(doc, &node.field).unwrap_or(&Bson::Null);
+    let expected = to_bson(&node.value);
+    match node.operate {
+        Operate::Eq => compare(actual, &expected) == Some(Ordering::Equal),
+        Operate::Ne => compare(actual, &expected) != Some(Ordering::Equal),
+        Operate::Lt => compare(actual, &expected) == Some(Ordering::Less),
+        Operate::Le => matches!(
+            compare(actual, &expected),
+            Some(Ordering::Less) | Some(Ordering::Equal)
+        ),
+        Operate::Gt => compare(actual, &expected) == Some(Ordering::Greater),
+        Operate::Ge => matches!(
+            compare(actual, &expected),
+            Some(Ordering::Greater) | Some(Ordering::Equal)
+        ),
+        Operate::In => match &expected {
+            Bson::Array(values) => values
+                .iter()
+                .any(|v| compare(actual, v) == Some(Ordering::Equal)),
+            _ => false,
+        },
+        Operate::Contains => match (actual, &expected) {
+            (Bson::String(a), Bson::String(e)) => a.contains(e.as_str()),
+            _ => false,
+        },
+    }
+}
+
+/// 同类型的值之间比较，类型不同时无法比较，和MongoDB的比较规则一样
+fn compare(a: &Bson, b: &Bson) -> Option<Ordering> {
+    match (a, b) {
+        (Bson::Null, Bson::Null) => Some(Ordering::Equal),
+        (Bson::String(a), Bson::String(b)) => Some(a.cmp(b)),
+        (Bson::Boolean(a), Bson::Boolean(b)) => Some(a.cmp(b)),
+        (Bson::ObjectId(a), Bson::ObjectId(b)) => Some(a.bytes().cmp(&b.bytes())),
+        _ => match (as_f64(a), as_f64(b)) {
+            (Some(a), Some(b)) => a.partial_cmp(&b),
+            _ => None,
+        },
+    }
+}
+
+fn as_f64(value: &Bson) -> Option<f64> {
+    match value {
+        Bson::Int32(v) => Some(*v as f64),
+        Bson::Int64(v) => Some(*v as f64),
+        Bson::Double(v) => Some(*v),
+        _ => None,
+    }
+}
+
+/// 排序时先按类型排，null最小，再比较同类型的值
+fn sort_compare(a: Option<&Bson>, b: Option<&Bson>) -> Ordering {
+    let a = a.unwrap_or(&Bson::Null);
+    let b = b.unwrap_or(&Bson::Null);
+    type_rank(a)
+        .cmp(&type_rank(b))
+        .then_with(|| compare(a, b).unwrap_or(Ordering::Equal))
+}
+
+fn type_rank(value: &Bson) -> u8 {
+    match value {
+        Bson::Null => 0,
+        Bson::Int32(_) | Bson::Int64(_) | Bson::Double(_) => 1,
+        Bson::String(_) => 2,
+        Bson::Document(_) => 3,
+        Bson::Array(_) => 4,
+        Bson::ObjectId(_) => 5,
+        Bson::Boolean(_) => 6,
+        _ => 7,
+    }
+}
+
+/// 标量的Bson转成条件中的值，用来生成游标
+fn to_condition_value(value: Option<&Bson>) -> ConditionValue {
+    match value {
+        Some(Bson::String(v)) => ConditionValue::StringValue(v.clone()),
+        Some(Bson::Int32(v)) => ConditionValue::Int32Value(*v),
+        Some(Bson::Int64(v)) => ConditionValue::Int64Value(*v),
+        Some(Bson::Double(v)) => ConditionValue::DoubleValue(*v),
+        Some(Bson::Boolean(v)) => ConditionValue::BooleanValue(*v),
+        Some(Bson::ObjectId(v)) => ConditionValue::ObjectIdValue(*v),
+        _ => ConditionValue::NullValue,
+    }
+}
+
+pub type UserRepo = MemoryRepo<po::User>;
+pub type WorkspaceRepo = SoftDelete<MemoryRepo<po::Workspace>>;
+pub type CatalogRepo = SoftDelete<MemoryRepo<po::Catalog>>;
+pub type TableRepo = SoftDelete<MemoryRepo<po::Table>>;
+pub type RowRepo = MemoryRepo<po::Row>;
+pub type MembershipRepo = MemoryRepo<po::Membership>;
+pub type ApiKeyRepo = MemoryRepo<po::ApiKey>;
+
+/// 内存中的Repo，所有实体共用，P是实体对应的持久化对象
+pub struct MemoryRepo<P> {
+    db: MemoryDB,
+    _persistent: PhantomData<fn() -> P>,
+}
+
+impl<P> Clone for MemoryRepo<P> {
+    fn clone(&self) -> Self {
+        MemoryRepo {
+            db: self.db.clone(),
+            _persistent: PhantomData,
+        }
+    }
+}
+
+impl<P: Persistent> MemoryRepo<P> {
+    pub fn new(db: MemoryDB) -> Self {
+        MemoryRepo {
+            db,
+            _persistent: PhantomData,
+        }
+    }
+
+    fn to_entities(docs: Vec<Document>) -> Result<Vec<P::Entity>, RepositoryError> {
+        docs.into_iter()
+            .map(|doc| Ok(bson::from_document::<P>(doc)?.into_entity()))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl<P: Persistent> CRUDRepository<P::Entity> for MemoryRepo<P> {
+    type Error = RepositoryError;
+
+    async fn count(&self, condition: &Condition) -> Result<u64, Self::Error> {
+        Ok(self.db.select(P::COLLECTION, condition).len() as u64)
+    }
+
+    async fn exist(&self, condition: &Condition) -> Result<bool, Self::Error> {
+        Ok(!self.db.select(P::COLLECTION, condition).is_empty())
+    }
+
+    async fn find_one(&self, condition: &Condition) -> Result<P::Entity, Self::Error> {
+        let doc = self
+            .db
+            .select(P::COLLECTION, condition)
+            .into_iter()
+            .next()
+            .ok_or(RepositoryError::DataNotFound)?;
+        Ok(bson::from_document::<P>(doc)?.into_entity())
+    }
+
+    async fn find(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+    ) -> Result<Vec<P::Entity>, Self::Error> {
+        Self::to_entities(self.db.select_sorted(P::COLLECTION, condition, sorts))
+    }
+
+    async fn create(&self, data: &P::Entity) -> Result<String, Self::Error> {
+        let doc = bson::to_document(&P::from_entity(data)?)?;
+        let oids = self.db.insert(P::COLLECTION, vec![doc])?;
+        Ok(oids[0].to_hex())
+    }
+
+    async fn update(&self, data: &P::Entity) -> Result<i64, Self::Error> {
+        let doc = bson::to_document(&P::from_entity(data)?)?;
+        let oid = match doc.get("_id") {
+            Some(Bson::ObjectId(oid)) => *oid,
+            _ => return Err(RepositoryError::DataNotFound),
+        };
+        self.db.replace(P::COLLECTION, oid, doc)
+    }
+
+    async fn patch(&self, condition: &Condition, patch: &Patch) -> Result<P::Entity, Self::Error> {
+        let doc = self.db.modify(P::COLLECTION, condition, patch)?;
+        Ok(bson::from_document::<P>(doc)?.into_entity())
+    }
+
+    async fn delete(&self, condition: &Condition) -> Result<bool, Self::Error> {
+        Ok(self.db.remove(P::COLLECTION, condition))
+    }
+
+    async fn create_many(&self, datas: &[P::Entity]) -> Result<Vec<String>, Self::Error> {
+        let docs = datas
+            .iter()
+            .map(|data| Ok(bson::to_document(&P::from_entity(data)?)?))
+            .collect::<Result<Vec<_>, RepositoryError>>()?;
+        let oids = self.db.insert(P::COLLECTION, docs)?;
+        Ok(oids.into_iter().map(|oid| oid.to_hex()).collect())
+    }
+
+    async fn update_many(&self, condition: &Condition, patch: &Patch) -> Result<u64, Self::Error> {
+        self.db.modify_all(P::COLLECTION, condition, patch)
+    }
+
+    async fn delete_many(&self, condition: &Condition) -> Result<u64, Self::Error> {
+        Ok(self.db.remove_all(P::COLLECTION, condition))
+    }
+}
+
+#[async_trait]
+impl<P: Persistent> PaginationRepository<P::Entity> for MemoryRepo<P> {
+    async fn find_page(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+        page_setting: &PageOption,
+        is_count_all: bool,
+    ) -> Result<PageResult<P::Entity>, Self::Error> {
+        let docs = self.db.select_sorted(P::COLLECTION, condition, sorts);
+        let total = docs.len();
+        let (skip, limit) = MemoryConditionHandler::transfer_page_options(page_setting);
+        let docs: Vec<Document> = docs.into_iter().skip(skip).take(limit).collect();
+        let count = if is_count_all { total } else { docs.len() };
+        Ok(PageResult {
+            datas: Self::to_entities(docs)?,
+            count: count as i64,
+        })
+    }
+
+    async fn find_after(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+        cursor_option: &CursorOption,
+    ) -> Result<CursorResult<P::Entity>, Self::Error> {
+        let keys = keyset_sorts(sorts);
+        let after = match &cursor_option.after {
+            Some(cursor) => cursor.condition(),
+            None => Condition::Empty,
+        };
+        let condition = Condition::all(vec![condition.clone(), after]);
+        let mut docs = self.db.select_sorted(P::COLLECTION, &condition, &keys);
+        // 和MongoDB一样，多出一条时才有下一页
+        let next_cursor = if docs.len() > cursor_option.size {
+            docs.truncate(cursor_option.size);
+            docs.last().map(|doc| Cursor {
+                keys: keys
+                    .iter()
+                    .map(|k| CursorKey {
+                        field: k.field.clone(),
+                        order: k.order.clone(),
+                        value: to_condition_value(path_value(doc, &k.field)),
+                    })
+                    .collect(),
+            })
+        } else {
+            None
+        };
+        Ok(CursorResult {
+            datas: Self::to_entities(docs)?,
+            next_cursor,
+        })
+    }
+}
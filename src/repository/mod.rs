@@ -1,7 +1,61 @@
 pub mod condition;
+pub mod memory;
 pub mod mongodb;
+pub mod postgres;
+pub mod soft_delete;
+pub mod sql;
+pub mod sqlite;
+#[cfg(test)]
+mod tests;
+use std::time::Duration;
+
 use async_trait::async_trait;
 use condition::*;
+use futures::future::BoxFuture;
+use soft_delete::SoftDeleteRepository;
+
+use crate::entity;
+
+/// 与具体存储无关的Repo错误
+#[derive(thiserror::Error, Debug)]
+pub enum RepositoryError {
+    #[error("data not found")]
+    DataNotFound,
+    #[error("invalid id: {0}")]
+    InvalidId(#[from] ::mongodb::bson::oid::Error),
+    #[error("conflict: {0}")]
+    Conflict(String),
+    #[error("version conflict")]
+    VersionConflict,
+    #[error(transparent)]
+    Backend(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl From<::mongodb::bson::de::Error> for RepositoryError {
+    fn from(err: ::mongodb::bson::de::Error) -> Self {
+        RepositoryError::Backend(Box::new(err))
+    }
+}
+
+impl From<::mongodb::bson::ser::Error> for RepositoryError {
+    fn from(err: ::mongodb::bson::ser::Error) -> Self {
+        RepositoryError::Backend(Box::new(err))
+    }
+}
+
+/// 事务中的错误，需要能取出其中的Repo错误，由存储后端判断是否可以重试
+pub trait TransactionError: From<RepositoryError> + Send {
+    fn repository_error(&self) -> Option<&RepositoryError>;
+}
+
+impl TransactionError for RepositoryError {
+    fn repository_error(&self) -> Option<&RepositoryError> {
+        Some(self)
+    }
+}
+
+/// 事务遇到临时错误时重试的最长时间
+pub const TRANSACTION_TIMEOUT: Duration = Duration::from_secs(120);
 
 #[async_trait]
 pub trait CRUDRepository<T> {
@@ -13,14 +67,31 @@ pub trait CRUDRepository<T> {
     async fn exist(&self, condition: &Condition) -> Result<bool, Self::Error>;
     /// 按条件查询单个
     async fn find_one(&self, condition: &Condition) -> Result<T, Self::Error>;
-    /// 按条件查询
-    async fn find(&self, condition: &Condition) -> Result<Vec<T>, Self::Error>;
+    /// 按条件查询，结果按sorts排序
+    async fn find(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+    ) -> Result<Vec<T>, Self::Error>;
     /// 创建数据
     async fn create(&self, data: &T) -> Result<String, Self::Error>;
-    /// 更想你数据
-    async fn update(&self, data: &T) -> Result<bool, Self::Error>;
-    /// 删除数据
+    /// 按id更新数据，返回更新后的版本号
+    ///
+    /// 存储中的版本号和data的版本号一致时才更新，数据不存在时返回DataNotFound，
+    /// 版本号不一致时返回VersionConflict
+    async fn update(&self, data: &T) -> Result<i64, Self::Error>;
+    /// 部分更新第一条满足条件的数据，同时将版本号加1，返回更新后的数据
+    ///
+    /// 查找和更新是一次原子操作，没有满足条件的数据时返回DataNotFound
+    async fn patch(&self, condition: &Condition, patch: &Patch) -> Result<T, Self::Error>;
+    /// 删除第一条满足条件的数据
     async fn delete(&self, condition: &Condition) -> Result<bool, Self::Error>;
+    /// 批量创建数据，返回的id和datas的顺序一致
+    async fn create_many(&self, datas: &[T]) -> Result<Vec<String>, Self::Error>;
+    /// 部分更新所有满足条件的数据，同时将版本号加1，返回更新的数量
+    async fn update_many(&self, condition: &Condition, patch: &Patch) -> Result<u64, Self::Error>;
+    /// 删除所有满足条件的数据，返回删除的数量
+    async fn delete_many(&self, condition: &Condition) -> Result<u64, Self::Error>;
 }
 
 pub struct PageResult<T> {
@@ -28,12 +99,70 @@ pub struct PageResult<T> {
     pub count: i64,
 }
 
+/// 游标分页的结果，next_cursor为None时表示没有下一页
+pub struct CursorResult<T> {
+    pub datas: Vec<T>,
+    pub next_cursor: Option<Cursor>,
+}
+
 #[async_trait]
 pub trait PaginationRepository<T>: CRUDRepository<T> {
     async fn find_page(
         &self,
         condition: &Condition,
+        sorts: &[SortOption],
         page_setting: &PageOption,
         is_count_all: bool,
     ) -> Result<PageResult<T>, Self::Error>;
+
+    /// 按游标分页，从cursor_option.after之后按排序取数据
+    async fn find_after(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+        cursor_option: &CursorOption,
+    ) -> Result<CursorResult<T>, Self::Error>;
+}
+
+/// 服务层使用的Repo，错误统一为RepositoryError
+pub trait Repository<T>:
+    PaginationRepository<T, Error = RepositoryError> + Clone + Send + Sync + 'static
+{
+}
+
+impl<T, R> Repository<T> for R where
+    R: PaginationRepository<T, Error = RepositoryError> + Clone + Send + Sync + 'static
+{
+}
+
+/// 存储后端，提供各个实体的Repo
+///
+/// 工作区、目录和表是软删除的，删除后可以在回收站中恢复
+#[async_trait]
+pub trait Storage: Clone + Send + Sync + 'static {
+    type UserRepo: Repository<entity::User>;
+    type WorkspaceRepo: SoftDeleteRepository<entity::Workspace>;
+    type CatalogRepo: SoftDeleteRepository<entity::Catalog>;
+    type TableRepo: SoftDeleteRepository<entity::Table>;
+    type RowRepo: Repository<entity::Row>;
+    type MembershipRepo: Repository<entity::Membership>;
+    type ApiKeyRepo: Repository<entity::ApiKey>;
+
+    fn user_repo(&self) -> Self::UserRepo;
+    fn workspace_repo(&self) -> Self::WorkspaceRepo;
+    fn catalog_repo(&self) -> Self::CatalogRepo;
+    fn table_repo(&self) -> Self::TableRepo;
+    fn row_repo(&self) -> Self::RowRepo;
+    fn membership_repo(&self) -> Self::MembershipRepo;
+    fn api_key_repo(&self) -> Self::ApiKeyRepo;
+
+    /// 在一个事务中执行work，work成功时提交，出错时回滚
+    ///
+    /// work中通过传入的Storage取得的Repo都参与这个事务。遇到可以重试的临时错误时
+    /// 整个work会重新执行，所以work可能被调用多次
+    async fn with_transaction<T, E, F>(&self, work: F) -> Result<T, E>
+    where
+        T: Send,
+        E: TransactionError,
+        F: for<'a> Fn(&'a Self) -> BoxFuture<'a, Result<T, E>> + Send + Sync;
 }
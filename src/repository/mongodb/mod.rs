@@ -1,15 +1,24 @@
+use std::sync::Arc;
+
 use crate::repository::condition::*;
 
+use chrono::SecondsFormat;
+use futures::TryStreamExt;
+use tokio::sync::Mutex;
+
 use mongodb::{
-    bson::{self, Bson, Document},
-    Collection,
+    bson::{doc, Bson, Document, Regex},
+    ClientSession, Collection, Database,
 };
 use mongodb::{
-    options::{ClientOptions, CountOptions, FindOptions},
+    error::{ErrorKind, WriteFailure, TRANSIENT_TRANSACTION_ERROR},
+    options::{ClientOptions, CountOptions, FindOneAndUpdateOptions, FindOptions},
     Client,
 };
-pub mod user;
-pub mod workspace;
+
+use super::RepositoryError;
+
+pub mod repo;
 
 const APP_NAME: &str = "table-toy";
 const DB_NAME: &str = "tabletoydb";
@@ -28,22 +37,369 @@ pub enum MongodbError {
     BsonOidError(#[from] mongodb::bson::oid::Error),
 }
 
+/// MongoDB中违反唯一索引时的错误码
+const DUPLICATE_KEY_CODE: i32 = 11000;
+
+impl From<mongodb::error::Error> for RepositoryError {
+    fn from(err: mongodb::error::Error) -> Self {
+        let duplicated = match err.kind.as_ref() {
+            ErrorKind::Write(WriteFailure::WriteError(e)) => e.code == DUPLICATE_KEY_CODE,
+            ErrorKind::BulkWrite(e) => e
+                .write_errors
+                .iter()
+                .flatten()
+                .any(|e| e.code == DUPLICATE_KEY_CODE),
+            _ => false,
+        };
+        if duplicated {
+            RepositoryError::Conflict(err.to_string())
+        } else {
+            RepositoryError::Backend(Box::new(err))
+        }
+    }
+}
+
+/// 事务中可以重试的临时错误，例如事务之间的写冲突和主节点切换
+fn is_transient(err: &RepositoryError) -> bool {
+    match err {
+        RepositoryError::Backend(e) => e
+            .downcast_ref::<mongodb::error::Error>()
+            .map_or(false, |e| e.contains_label(TRANSIENT_TRANSACTION_ERROR)),
+        _ => false,
+    }
+}
+
+/// MongoDB连接
+///
+/// 在事务中时带着事务的会话，从它取得的Repo的所有操作都使用这个会话
 #[derive(Clone, Debug)]
 pub struct MongoDB {
     client: Client,
+    database: Database,
+    session: Option<Arc<Mutex<ClientSession>>>,
 }
 
 impl MongoDB {
     pub async fn init(uri: &str) -> Result<Self, MongodbError> {
+        Self::init_database(uri, DB_NAME).await
+    }
+
+    /// 使用指定的数据库，测试时每个用例使用单独的数据库
+    pub async fn init_database(uri: &str, db_name: &str) -> Result<Self, MongodbError> {
         let mut client_options = ClientOptions::parse(&uri).await?;
         client_options.app_name = Some(APP_NAME.to_string());
-        Ok(Self {
-            client: Client::with_options(client_options)?,
-        })
+        let client = Client::with_options(client_options)?;
+        let db = Self {
+            database: client.database(db_name),
+            client,
+            session: None,
+        };
+        db.ensure_indexes().await?;
+        db.normalize_datetimes().await?;
+        Ok(db)
+    }
+
+    /// 删除整个数据库，只在测试中使用
+    #[cfg(test)]
+    pub async fn drop_database(&self) -> Result<(), MongodbError> {
+        self.database.drop(None).await?;
+        Ok(())
+    }
+
+    /// 创建需要的索引，索引已经存在时不会重复创建
+    async fn ensure_indexes(&self) -> Result<(), MongodbError> {
+        self.database
+            .run_command(
+                doc! {
+                    "createIndexes": "users",
+                    "indexes": [
+                        { "key": { "username": 1 }, "name": "username_unique", "unique": true },
+                    ],
+                },
+                None,
+            )
+            .await?;
+        self.database
+            .run_command(
+                doc! {
+                    "createIndexes": "memberships",
+                    "indexes": [
+                        {
+                            "key": { "workspaceId": 1, "userId": 1 },
+                            "name": "workspace_user_unique",
+                            "unique": true,
+                        },
+                        { "key": { "userId": 1 }, "name": "user_id" },
+                    ],
+                },
+                None,
+            )
+            .await?;
+        self.database
+            .run_command(
+                doc! {
+                    "createIndexes": "apiKeys",
+                    "indexes": [
+                        { "key": { "keyHash": 1 }, "name": "key_hash_unique", "unique": true },
+                        { "key": { "userId": 1 }, "name": "user_id" },
+                    ],
+                },
+                None,
+            )
+            .await?;
+        Ok(())
+    }
+
+    /// 旧版本按chrono默认的格式保存时间，精度不固定，和查询条件中的时间按字符串比较时顺序不对。
+    /// 启动时把这样的时间改成精确到毫秒的格式，已经是这个格式的不修改
+    async fn normalize_datetimes(&self) -> Result<(), MongodbError> {
+        const FIELDS: &[(&str, &[&str])] = &[
+            ("workspaces", &["createdAt", "updatedAt", "deletedAt"]),
+            ("catalogs", &["createdAt", "updatedAt", "deletedAt"]),
+            ("tables", &["createdAt", "updatedAt", "deletedAt"]),
+            ("rows", &["createdAt", "updatedAt"]),
+            ("memberships", &["createdAt", "updatedAt"]),
+            (
+                "apiKeys",
+                &["createdAt", "updatedAt", "expiresAt", "lastUsedAt"],
+            ),
+        ];
+        let millis = Regex {
+            pattern: String::from(r"^\d{4}-\d{2}-\d{2}T\d{2}:\d{2}:\d{2}\.\d{3}Z$"),
+            options: String::new(),
+        };
+        for (collection, fields) in FIELDS {
+            for field in fields.iter() {
+                self.database
+                    .run_command(
+                        doc! {
+                            "update": *collection,
+                            "updates": [{
+                                "q": { *field: { "$type": "string", "$not": millis.clone() } },
+                                "u": [{ "$set": { *field: { "$dateToString": {
+                                    "date": { "$toDate": format!("${}", field) },
+                                    "format": "%Y-%m-%dT%H:%M:%S.%LZ",
+                                } } } }],
+                                "multi": true,
+                            }],
+                        },
+                        None,
+                    )
+                    .await?;
+            }
+        }
+        Ok(())
     }
 
     fn get_collection(&self, collection_name: &str) -> Collection {
-        self.client.database(DB_NAME).collection(collection_name)
+        self.database.collection(collection_name)
+    }
+
+    async fn count_documents(
+        &self,
+        collection: &str,
+        filter: Document,
+        options: Option<CountOptions>,
+    ) -> Result<u64, RepositoryError> {
+        let collection = self.get_collection(collection);
+        let result = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .count_documents_with_session(filter, options, &mut session)
+                    .await?
+            }
+            None => collection.count_documents(filter, options).await?,
+        };
+        Ok(result)
+    }
+
+    async fn find_documents(
+        &self,
+        collection: &str,
+        filter: Document,
+        options: FindOptions,
+    ) -> Result<Vec<Document>, RepositoryError> {
+        let collection = self.get_collection(collection);
+        let docs = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                let mut cursor = collection
+                    .find_with_session(filter, options, &mut session)
+                    .await?;
+                cursor.stream(&mut session).try_collect().await?
+            }
+            None => {
+                collection
+                    .find(filter, options)
+                    .await?
+                    .try_collect()
+                    .await?
+            }
+        };
+        Ok(docs)
+    }
+
+    async fn find_one_document(
+        &self,
+        collection: &str,
+        filter: Document,
+    ) -> Result<Option<Document>, RepositoryError> {
+        let collection = self.get_collection(collection);
+        let doc = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .find_one_with_session(filter, None, &mut session)
+                    .await?
+            }
+            None => collection.find_one(filter, None).await?,
+        };
+        Ok(doc)
+    }
+
+    /// 插入文档，返回生成的_id
+    async fn insert_document(
+        &self,
+        collection: &str,
+        doc: Document,
+    ) -> Result<Bson, RepositoryError> {
+        let collection = self.get_collection(collection);
+        let result = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .insert_one_with_session(doc, None, &mut session)
+                    .await?
+            }
+            None => collection.insert_one(doc, None).await?,
+        };
+        Ok(result.inserted_id)
+    }
+
+    /// 替换第一个满足条件的文档，返回匹配到的数量
+    async fn replace_document(
+        &self,
+        collection: &str,
+        filter: Document,
+        doc: Document,
+    ) -> Result<u64, RepositoryError> {
+        let collection = self.get_collection(collection);
+        let result = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .replace_one_with_session(filter, doc, None, &mut session)
+                    .await?
+            }
+            None => collection.replace_one(filter, doc, None).await?,
+        };
+        Ok(result.matched_count)
+    }
+
+    async fn find_one_and_update_document(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+        options: FindOneAndUpdateOptions,
+    ) -> Result<Option<Document>, RepositoryError> {
+        let collection = self.get_collection(collection);
+        let doc = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .find_one_and_update_with_session(filter, update, options, &mut session)
+                    .await?
+            }
+            None => {
+                collection
+                    .find_one_and_update(filter, update, options)
+                    .await?
+            }
+        };
+        Ok(doc)
+    }
+
+    /// 批量插入文档，返回按顺序生成的_id
+    async fn insert_documents(
+        &self,
+        collection: &str,
+        docs: Vec<Document>,
+    ) -> Result<Vec<Bson>, RepositoryError> {
+        let count = docs.len();
+        let collection = self.get_collection(collection);
+        let result = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .insert_many_with_session(docs, None, &mut session)
+                    .await?
+            }
+            None => collection.insert_many(docs, None).await?,
+        };
+        let mut inserted_ids = result.inserted_ids;
+        Ok((0..count)
+            .map(|i| inserted_ids.remove(&i).unwrap_or(Bson::Null))
+            .collect())
+    }
+
+    /// 更新所有满足条件的文档，返回更新的数量
+    async fn update_documents(
+        &self,
+        collection: &str,
+        filter: Document,
+        update: Document,
+    ) -> Result<u64, RepositoryError> {
+        let collection = self.get_collection(collection);
+        let result = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .update_many_with_session(filter, update, None, &mut session)
+                    .await?
+            }
+            None => collection.update_many(filter, update, None).await?,
+        };
+        Ok(result.modified_count)
+    }
+
+    /// 删除所有满足条件的文档，返回删除的数量
+    async fn delete_documents(
+        &self,
+        collection: &str,
+        filter: Document,
+    ) -> Result<u64, RepositoryError> {
+        let collection = self.get_collection(collection);
+        let result = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .delete_many_with_session(filter, None, &mut session)
+                    .await?
+            }
+            None => collection.delete_many(filter, None).await?,
+        };
+        Ok(result.deleted_count)
+    }
+
+    /// 删除第一个满足条件的文档，返回删除的数量
+    async fn delete_document(
+        &self,
+        collection: &str,
+        filter: Document,
+    ) -> Result<u64, RepositoryError> {
+        let collection = self.get_collection(collection);
+        let result = match &self.session {
+            Some(session) => {
+                let mut session = session.lock().await;
+                collection
+                    .delete_one_with_session(filter, None, &mut session)
+                    .await?
+            }
+            None => collection.delete_one(filter, None).await?,
+        };
+        Ok(result.deleted_count)
     }
 }
 
@@ -53,6 +409,7 @@ pub struct MongoDBConditionHandler {}
 impl ConditionHandler for MongoDBConditionHandler {
     type TransferResult = Document;
     type TransferPageResult = (FindOptions, CountOptions);
+    type TransferSortResult = Document;
 
     fn transfer_condition(condition: &Condition) -> Self::TransferResult {
         match condition {
@@ -67,7 +424,8 @@ impl ConditionHandler for MongoDBConditionHandler {
     }
 
     fn transfer_page_options(page_option: &PageOption) -> Self::TransferPageResult {
-        let skip = (page_option.page - 1) * page_option.size;
+        // 页码从1开始，传入0时按第一页处理
+        let skip = page_option.page.saturating_sub(1) * page_option.size;
         let find_options = FindOptions::builder()
             .skip(skip as u64)
             .limit(page_option.size as i64)
@@ -78,6 +436,117 @@ impl ConditionHandler for MongoDBConditionHandler {
             .build();
         (find_options, count_options)
     }
+
+    fn transfer_sort(sorts: &[SortOption]) -> Self::TransferSortResult {
+        let mut doc = Document::new();
+        for sort in sorts {
+            let order = match sort.order {
+                SortOrder::Asc => 1,
+                SortOrder::Desc => -1,
+            };
+            doc.insert(&sort.field, order);
+        }
+        // 最后按_id排序，保证排序字段相同时翻页顺序也是稳定的
+        if !doc.contains_key("_id") {
+            doc.insert("_id", 1);
+        }
+        doc
+    }
+}
+
+/// 只带排序的查询选项
+fn sort_find_options(sorts: &[SortOption]) -> FindOptions {
+    FindOptions::builder()
+        .sort(MongoDBConditionHandler::transfer_sort(sorts))
+        .build()
+}
+
+/// 分页查询，返回当前页的文档和数量
+///
+/// is_count_all为true时数量是满足条件的总数，否则只是当前页的数量
+async fn find_page_documents(
+    db: &MongoDB,
+    collection: &str,
+    condition: &Condition,
+    sorts: &[SortOption],
+    page_option: &PageOption,
+    is_count_all: bool,
+) -> Result<(Vec<Document>, i64), RepositoryError> {
+    let filter = MongoDBConditionHandler::transfer_condition(condition);
+    let (mut find_options, count_options) =
+        MongoDBConditionHandler::transfer_page_options(page_option);
+    find_options.sort = Some(MongoDBConditionHandler::transfer_sort(sorts));
+    let count = if is_count_all {
+        db.count_documents(collection, filter.clone(), None).await?
+    } else {
+        db.count_documents(collection, filter.clone(), Some(count_options))
+            .await?
+    };
+    let docs = db.find_documents(collection, filter, find_options).await?;
+    Ok((docs, count as i64))
+}
+
+/// 游标分页查询，返回当前页的文档和下一页的游标
+///
+/// 多取一条来判断后面还有没有数据，没有时不返回游标
+async fn find_after_documents(
+    db: &MongoDB,
+    collection: &str,
+    condition: &Condition,
+    sorts: &[SortOption],
+    cursor_option: &CursorOption,
+) -> Result<(Vec<Document>, Option<Cursor>), RepositoryError> {
+    let keys = keyset_sorts(sorts);
+    let after = match &cursor_option.after {
+        Some(cursor) => cursor.condition(),
+        None => Condition::Empty,
+    };
+    let filter = MongoDBConditionHandler::transfer_condition(&Condition::all(vec![
+        condition.clone(),
+        after,
+    ]));
+    let find_options = FindOptions::builder()
+        .sort(MongoDBConditionHandler::transfer_sort(&keys))
+        .limit((cursor_option.size + 1) as i64)
+        .build();
+    let mut docs = db.find_documents(collection, filter, find_options).await?;
+    let next_cursor = if docs.len() > cursor_option.size {
+        docs.truncate(cursor_option.size);
+        docs.last().map(|doc| Cursor {
+            keys: keys
+                .iter()
+                .map(|k| CursorKey {
+                    field: k.field.clone(),
+                    order: k.order.clone(),
+                    value: document_value(doc, &k.field),
+                })
+                .collect(),
+        })
+    } else {
+        None
+    };
+    Ok((docs, next_cursor))
+}
+
+/// 按点分隔的路径取文档中的值，取不到或不是标量时当作null
+fn document_value(doc: &Document, path: &str) -> ConditionValue {
+    let mut keys = path.split('.');
+    let mut value = keys.next().and_then(|k| doc.get(k));
+    for key in keys {
+        value = match value {
+            Some(Bson::Document(d)) => d.get(key),
+            _ => None,
+        };
+    }
+    match value {
+        Some(Bson::String(v)) => ConditionValue::StringValue(v.clone()),
+        Some(Bson::Int32(v)) => ConditionValue::Int32Value(*v),
+        Some(Bson::Int64(v)) => ConditionValue::Int64Value(*v),
+        Some(Bson::Double(v)) => ConditionValue::DoubleValue(*v),
+        Some(Bson::Boolean(v)) => ConditionValue::BooleanValue(*v),
+        Some(Bson::ObjectId(v)) => ConditionValue::ObjectIdValue(*v),
+        _ => ConditionValue::NullValue,
+    }
 }
 
 /// 简单条件转成Document
@@ -108,10 +577,17 @@ fn complex_condition_to_doc(
         .iter()
         .map(|x| MongoDBConditionHandler::transfer_condition(x))
         .collect();
+    // MongoDB不接受空的$and/$or/$nor数组，所以只插入非空的部分
     let mut doc = Document::new();
-    doc.insert("$and", and_documents);
-    doc.insert("$or", or_documents);
-    doc.insert("$nor", nor_documents);
+    if !and_documents.is_empty() {
+        doc.insert("$and", and_documents);
+    }
+    if !or_documents.is_empty() {
+        doc.insert("$or", or_documents);
+    }
+    if !nor_documents.is_empty() {
+        doc.insert("$nor", nor_documents);
+    }
     doc
 }
 
@@ -123,20 +599,26 @@ fn operate_value_to_doc(operate: &Operate, value: &ConditionValue) -> Document {
         Eq => "$eq",
         Ne => "$ne",
         Lt => "$lt",
-        Le => "$le",
+        Le => "$lte",
         Gt => "$gt",
-        Ge => "$ge",
+        Ge => "$gte",
         In => "$in",
         Contains => "$regex",
     };
-    let val: Bson = match value {
-        StringValue(v) => {
-            if &op == &"$regex" {
-                Bson::String(format!(".*{}.*", v))
-            } else {
-                Bson::String(v.to_string())
-            }
-        }
+    let val = match value {
+        StringValue(v) if op == "$regex" => Bson::String(escape_regex(v)),
+        _ => to_bson(value),
+    };
+    let mut doc = Document::new();
+    doc.insert(op, val);
+    doc
+}
+
+/// 条件中的值转成Bson
+fn to_bson(value: &ConditionValue) -> Bson {
+    use ConditionValue::*;
+    match value {
+        StringValue(v) => Bson::String(v.to_string()),
         StringVecValue(v) => {
             let vs = v.iter().map(|x| Bson::String(x.to_string())).collect();
             Bson::Array(vs)
@@ -151,11 +633,60 @@ fn operate_value_to_doc(operate: &Operate, value: &ConditionValue) -> Document {
             let vs = v.iter().map(|x| Bson::Int64(*x)).collect();
             Bson::Array(vs)
         }
+        DoubleValue(v) => Bson::Double(*v),
         BooleanValue(v) => Bson::Boolean(*v),
-        DateTimeValue(v) => Bson::DateTime(bson::DateTime::from_millis(v.timestamp())),
+        // 时间以精确到毫秒的RFC3339字符串保存（见po::millis），用同样格式的字符串比较
+        DateTimeValue(v) => Bson::String(v.to_rfc3339_opts(SecondsFormat::Millis, true)),
         ObjectIdValue(v) => Bson::ObjectId(v.clone()),
-    };
-    let mut doc = Document::new();
-    doc.insert(op, val);
+        ObjectIdVecValue(v) => Bson::Array(v.iter().map(|x| Bson::ObjectId(*x)).collect()),
+        NullValue => Bson::Null,
+    }
+}
+
+/// 按版本号匹配的过滤条件，没有version字段的旧数据当作版本0
+fn version_filter(version: i64) -> Bson {
+    if version == 0 {
+        Bson::Document(doc! {"$in": [0_i64, Bson::Null]})
+    } else {
+        Bson::Int64(version)
+    }
+}
+
+/// 部分更新转成$set、$unset、$rename和版本号的$inc，MongoDB不接受空的操作符，所以只插入非空的部分
+fn patch_to_doc(patch: &Patch) -> Document {
+    let mut set = Document::new();
+    for (field, value) in patch.set.iter() {
+        set.insert(field, to_bson(value));
+    }
+    let mut unset = Document::new();
+    for field in patch.unset.iter() {
+        unset.insert(field, "");
+    }
+    let mut rename = Document::new();
+    for (from, to) in patch.rename.iter() {
+        rename.insert(from, to);
+    }
+    let mut doc = doc! {"$inc": {"version": 1_i64}};
+    if !set.is_empty() {
+        doc.insert("$set", set);
+    }
+    if !unset.is_empty() {
+        doc.insert("$unset", unset);
+    }
+    if !rename.is_empty() {
+        doc.insert("$rename", rename);
+    }
     doc
 }
+
+/// 转义正则中的特殊字符，包含查询按字面匹配
+fn escape_regex(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if "\\.+*?()|[]{}^$".contains(c) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
@@ -0,0 +1,368 @@
+use std::{marker::PhantomData, sync::Arc, time::Instant};
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use mongodb::{
+    bson::{self, doc, Bson, Document},
+    error::{TRANSIENT_TRANSACTION_ERROR, UNKNOWN_TRANSACTION_COMMIT_RESULT},
+    options::{FindOneAndUpdateOptions, ReturnDocument},
+};
+use tokio::sync::Mutex;
+
+use crate::{
+    po::{self, Persistent},
+    repository::{
+        condition::{Condition, ConditionHandler, CursorOption, PageOption, Patch, SortOption},
+        soft_delete::SoftDelete,
+        CRUDRepository, CursorResult, PageResult, PaginationRepository, RepositoryError, Storage,
+        TransactionError, TRANSACTION_TIMEOUT,
+    },
+};
+
+use super::{
+    find_after_documents, find_page_documents, is_transient, patch_to_doc, sort_find_options,
+    version_filter, MongoDB, MongoDBConditionHandler,
+};
+
+/// 插入后返回的_id，没有生成ObjectId时返回错误
+fn inserted_hex(id: Bson) -> Result<String, RepositoryError> {
+    match id {
+        Bson::ObjectId(oid) => Ok(oid.to_hex()),
+        other => Err(RepositoryError::Backend(
+            format!("unexpected inserted id: {}", other).into(),
+        )),
+    }
+}
+
+pub type UserRepo = MongoRepo<po::User>;
+pub type WorkspaceRepo = SoftDelete<MongoRepo<po::Workspace>>;
+pub type CatalogRepo = SoftDelete<MongoRepo<po::Catalog>>;
+pub type TableRepo = SoftDelete<MongoRepo<po::Table>>;
+pub type RowRepo = MongoRepo<po::Row>;
+pub type MembershipRepo = MongoRepo<po::Membership>;
+pub type ApiKeyRepo = MongoRepo<po::ApiKey>;
+
+/// MongoDB的Repo，所有实体共用，P是实体对应的持久化对象
+pub struct MongoRepo<P> {
+    db: MongoDB,
+    _persistent: PhantomData<fn() -> P>,
+}
+
+impl<P> Clone for MongoRepo<P> {
+    fn clone(&self) -> Self {
+        MongoRepo {
+            db: self.db.clone(),
+            _persistent: PhantomData,
+        }
+    }
+}
+
+impl<P: Persistent> MongoRepo<P> {
+    pub fn new(db: MongoDB) -> Self {
+        MongoRepo {
+            db,
+            _persistent: PhantomData,
+        }
+    }
+
+    fn to_entities(docs: Vec<Document>) -> Result<Vec<P::Entity>, RepositoryError> {
+        docs.into_iter()
+            .map(|doc| Ok(bson::from_document::<P>(doc)?.into_entity()))
+            .collect()
+    }
+}
+
+#[async_trait]
+impl Storage for MongoDB {
+    type UserRepo = UserRepo;
+    type WorkspaceRepo = WorkspaceRepo;
+    type CatalogRepo = CatalogRepo;
+    type TableRepo = TableRepo;
+    type RowRepo = RowRepo;
+    type MembershipRepo = MembershipRepo;
+    type ApiKeyRepo = ApiKeyRepo;
+
+    fn user_repo(&self) -> UserRepo {
+        MongoRepo::new(self.clone())
+    }
+    fn workspace_repo(&self) -> WorkspaceRepo {
+        SoftDelete::new(MongoRepo::new(self.clone()))
+    }
+    fn catalog_repo(&self) -> CatalogRepo {
+        SoftDelete::new(MongoRepo::new(self.clone()))
+    }
+    fn table_repo(&self) -> TableRepo {
+        SoftDelete::new(MongoRepo::new(self.clone()))
+    }
+    fn row_repo(&self) -> RowRepo {
+        MongoRepo::new(self.clone())
+    }
+    fn membership_repo(&self) -> MembershipRepo {
+        MongoRepo::new(self.clone())
+    }
+    fn api_key_repo(&self) -> ApiKeyRepo {
+        MongoRepo::new(self.clone())
+    }
+
+    /// 按MongoDB驱动规范中withTransaction的方式重试：work遇到临时错误时重新执行整个事务，
+    /// 提交结果未知时只重试提交。已经在事务中时直接加入当前事务
+    async fn with_transaction<T, E, F>(&self, work: F) -> Result<T, E>
+    where
+        T: Send,
+        E: TransactionError,
+        F: for<'a> Fn(&'a Self) -> BoxFuture<'a, Result<T, E>> + Send + Sync,
+    {
+        if self.session.is_some() {
+            return work(self).await;
+        }
+        let session = self
+            .client
+            .start_session(None)
+            .await
+            .map_err(RepositoryError::from)?;
+        let session = Arc::new(Mutex::new(session));
+        let tx = MongoDB {
+            client: self.client.clone(),
+            database: self.database.clone(),
+            session: Some(session.clone()),
+        };
+        let deadline = Instant::now() + TRANSACTION_TIMEOUT;
+        'transaction: loop {
+            session
+                .lock()
+                .await
+                .start_transaction(None)
+                .await
+                .map_err(RepositoryError::from)?;
+            let value = match work(&tx).await {
+                Ok(value) => value,
+                Err(e) => {
+                    if let Err(abort) = session.lock().await.abort_transaction().await {
+                        log::warn!("failed to abort mongodb transaction: {}", abort);
+                    }
+                    if Instant::now() < deadline && e.repository_error().map_or(false, is_transient)
+                    {
+                        continue 'transaction;
+                    }
+                    return Err(e);
+                }
+            };
+            loop {
+                let result = session.lock().await.commit_transaction().await;
+                match result {
+                    Ok(()) => return Ok(value),
+                    Err(e)
+                        if Instant::now() < deadline
+                            && e.contains_label(UNKNOWN_TRANSACTION_COMMIT_RESULT) =>
+                    {
+                        continue
+                    }
+                    Err(e)
+                        if Instant::now() < deadline
+                            && e.contains_label(TRANSIENT_TRANSACTION_ERROR) =>
+                    {
+                        continue 'transaction
+                    }
+                    Err(e) => return Err(RepositoryError::from(e).into()),
+                }
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<P: Persistent> CRUDRepository<P::Entity> for MongoRepo<P> {
+    type Error = RepositoryError;
+
+    async fn count(&self, condition: &Condition) -> Result<u64, Self::Error> {
+        self.db
+            .count_documents(
+                P::COLLECTION,
+                MongoDBConditionHandler::transfer_condition(condition),
+                None,
+            )
+            .await
+    }
+
+    async fn exist(&self, condition: &Condition) -> Result<bool, Self::Error> {
+        let result = self
+            .db
+            .count_documents(
+                P::COLLECTION,
+                MongoDBConditionHandler::transfer_condition(condition),
+                None,
+            )
+            .await?;
+        Ok(result != 0)
+    }
+
+    async fn find_one(&self, condition: &Condition) -> Result<P::Entity, Self::Error> {
+        let doc = self
+            .db
+            .find_one_document(
+                P::COLLECTION,
+                MongoDBConditionHandler::transfer_condition(condition),
+            )
+            .await?
+            .ok_or(RepositoryError::DataNotFound)?;
+        Ok(bson::from_document::<P>(doc)?.into_entity())
+    }
+
+    async fn find(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+    ) -> Result<Vec<P::Entity>, Self::Error> {
+        let docs = self
+            .db
+            .find_documents(
+                P::COLLECTION,
+                MongoDBConditionHandler::transfer_condition(condition),
+                sort_find_options(sorts),
+            )
+            .await?;
+        Self::to_entities(docs)
+    }
+
+    async fn create(&self, data: &P::Entity) -> Result<String, Self::Error> {
+        let doc = bson::to_document(&P::from_entity(data)?)?;
+        let inserted_id = self.db.insert_document(P::COLLECTION, doc).await?;
+        inserted_hex(inserted_id)
+    }
+
+    async fn update(&self, data: &P::Entity) -> Result<i64, Self::Error> {
+        let mut doc = bson::to_document(&P::from_entity(data)?)?;
+        let oid = match doc.get("_id") {
+            Some(Bson::ObjectId(oid)) => *oid,
+            _ => return Err(RepositoryError::DataNotFound),
+        };
+        let version = doc.get_i64("version").unwrap_or_default();
+        doc.insert("version", version + 1);
+        let matched_count = self
+            .db
+            .replace_document(
+                P::COLLECTION,
+                doc! {"_id": oid, "version": version_filter(version)},
+                doc,
+            )
+            .await?;
+        if matched_count == 1 {
+            Ok(version + 1)
+        } else if self
+            .db
+            .count_documents(P::COLLECTION, doc! {"_id": oid}, None)
+            .await?
+            == 0
+        {
+            Err(RepositoryError::DataNotFound)
+        } else {
+            Err(RepositoryError::VersionConflict)
+        }
+    }
+
+    async fn patch(&self, condition: &Condition, patch: &Patch) -> Result<P::Entity, Self::Error> {
+        let options = FindOneAndUpdateOptions::builder()
+            .return_document(ReturnDocument::After)
+            .build();
+        let doc = self
+            .db
+            .find_one_and_update_document(
+                P::COLLECTION,
+                MongoDBConditionHandler::transfer_condition(condition),
+                patch_to_doc(patch),
+                options,
+            )
+            .await?
+            .ok_or(RepositoryError::DataNotFound)?;
+        Ok(bson::from_document::<P>(doc)?.into_entity())
+    }
+
+    async fn delete(&self, condition: &Condition) -> Result<bool, Self::Error> {
+        let deleted_count = self
+            .db
+            .delete_document(
+                P::COLLECTION,
+                MongoDBConditionHandler::transfer_condition(condition),
+            )
+            .await?;
+        Ok(deleted_count == 1)
+    }
+
+    async fn create_many(&self, datas: &[P::Entity]) -> Result<Vec<String>, Self::Error> {
+        // MongoDB不接受空的insert_many
+        if datas.is_empty() {
+            return Ok(vec![]);
+        }
+        let docs = datas
+            .iter()
+            .map(|data| Ok(bson::to_document(&P::from_entity(data)?)?))
+            .collect::<Result<Vec<_>, RepositoryError>>()?;
+        // insert_many遇到重复键时会留下前面已经插入的文档，放在事务中保证全部插入或全部不插入
+        let inserted_ids = self
+            .db
+            .with_transaction(move |tx| {
+                let docs = docs.clone();
+                Box::pin(async move { tx.insert_documents(P::COLLECTION, docs).await })
+            })
+            .await?;
+        inserted_ids.into_iter().map(inserted_hex).collect()
+    }
+
+    async fn update_many(&self, condition: &Condition, patch: &Patch) -> Result<u64, Self::Error> {
+        self.db
+            .update_documents(
+                P::COLLECTION,
+                MongoDBConditionHandler::transfer_condition(condition),
+                patch_to_doc(patch),
+            )
+            .await
+    }
+
+    async fn delete_many(&self, condition: &Condition) -> Result<u64, Self::Error> {
+        self.db
+            .delete_documents(
+                P::COLLECTION,
+                MongoDBConditionHandler::transfer_condition(condition),
+            )
+            .await
+    }
+}
+
+#[async_trait]
+impl<P: Persistent> PaginationRepository<P::Entity> for MongoRepo<P> {
+    async fn find_page(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+        page_setting: &PageOption,
+        is_count_all: bool,
+    ) -> Result<PageResult<P::Entity>, Self::Error> {
+        let (docs, count) = find_page_documents(
+            &self.db,
+            P::COLLECTION,
+            condition,
+            sorts,
+            page_setting,
+            is_count_all,
+        )
+        .await?;
+        Ok(PageResult {
+            datas: Self::to_entities(docs)?,
+            count,
+        })
+    }
+
+    async fn find_after(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+        cursor_option: &CursorOption,
+    ) -> Result<CursorResult<P::Entity>, Self::Error> {
+        let (docs, next_cursor) =
+            find_after_documents(&self.db, P::COLLECTION, condition, sorts, cursor_option).await?;
+        Ok(CursorResult {
+            datas: Self::to_entities(docs)?,
+            next_cursor,
+        })
+    }
+}
@@ -0,0 +1,506 @@
+use std::sync::Arc;
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use serde_json::{json, Value};
+use tokio_postgres::{
+    error::SqlState,
+    types::{ToSql, Type},
+    Client, NoTls, Row,
+};
+
+use crate::repository::condition::*;
+
+use super::{
+    sql::{self, quote_ident, to_column_name},
+    RepositoryError,
+};
+
+pub mod repo;
+
+/// 按版本顺序执行的迁移脚本
+const MIGRATIONS: &[(i32, &str)] = &[
+    (
+        1,
+        include_str!("../../../migrations/postgres/0001_init.sql"),
+    ),
+    (
+        2,
+        include_str!("../../../migrations/postgres/0002_unique_username.sql"),
+    ),
+    (
+        3,
+        include_str!("../../../migrations/postgres/0003_memberships.sql"),
+    ),
+    (
+        4,
+        include_str!("../../../migrations/postgres/0004_api_keys.sql"),
+    ),
+    (
+        5,
+        include_str!("../../../migrations/postgres/0005_versions.sql"),
+    ),
+    (
+        6,
+        include_str!("../../../migrations/postgres/0006_soft_delete.sql"),
+    ),
+    (
+        7,
+        include_str!("../../../migrations/postgres/0007_display_name.sql"),
+    ),
+];
+
+/// 执行迁移时使用的advisory lock，避免多个实例同时迁移
+const MIGRATION_LOCK_ID: i64 = 7_361_001;
+
+impl From<tokio_postgres::Error> for RepositoryError {
+    fn from(err: tokio_postgres::Error) -> Self {
+        if err.code() == Some(&SqlState::UNIQUE_VIOLATION) {
+            RepositoryError::Conflict(err.to_string())
+        } else {
+            RepositoryError::Backend(Box::new(err))
+        }
+    }
+}
+
+/// 事务中可以重试的临时错误：序列化失败和死锁
+fn is_transient(err: &RepositoryError) -> bool {
+    match err {
+        RepositoryError::Backend(e) => e
+            .downcast_ref::<tokio_postgres::Error>()
+            .and_then(|e| e.code())
+            .map_or(false, |code| {
+                code == &SqlState::T_R_SERIALIZATION_FAILURE
+                    || code == &SqlState::T_R_DEADLOCK_DETECTED
+            }),
+        _ => false,
+    }
+}
+
+/// PostgreSQL连接
+///
+/// 事务使用单独的连接，避免同时处理的其他请求的语句混进事务
+#[derive(Clone)]
+pub struct Postgres {
+    client: Arc<Client>,
+    uri: Arc<str>,
+    in_transaction: bool,
+}
+
+impl Postgres {
+    /// 连接数据库并执行还没有执行过的迁移
+    pub async fn init(uri: &str) -> Result<Self, tokio_postgres::Error> {
+        let mut client = connect(uri).await?;
+        migrate(&mut client).await?;
+        Ok(Self {
+            client: Arc::new(client),
+            uri: Arc::from(uri),
+            in_transaction: false,
+        })
+    }
+
+    /// 打开一个新连接并开始事务
+    async fn begin(&self) -> Result<Self, RepositoryError> {
+        let client = connect(&self.uri).await?;
+        client.batch_execute("BEGIN").await?;
+        Ok(Self {
+            client: Arc::new(client),
+            uri: self.uri.clone(),
+            in_transaction: true,
+        })
+    }
+
+    async fn commit(&self) -> Result<(), RepositoryError> {
+        self.client.batch_execute("COMMIT").await?;
+        Ok(())
+    }
+
+    /// 回滚失败时连接关闭后事务也会被回滚，所以只记录日志
+    async fn rollback(&self) {
+        if let Err(e) = self.client.batch_execute("ROLLBACK").await {
+            log::warn!("failed to roll back postgres transaction: {}", e);
+        }
+    }
+
+    async fn query(&self, sql: Sql) -> Result<Vec<Row>, RepositoryError> {
+        let text = sql.numbered();
+        let result = self.client.query(text.as_str(), &param_refs(&sql)).await?;
+        Ok(result)
+    }
+
+    async fn execute(&self, sql: Sql) -> Result<u64, RepositoryError> {
+        let text = sql.numbered();
+        let result = self
+            .client
+            .execute(text.as_str(), &param_refs(&sql))
+            .await?;
+        Ok(result)
+    }
+}
+
+async fn connect(uri: &str) -> Result<Client, tokio_postgres::Error> {
+    let (client, connection) = tokio_postgres::connect(uri, NoTls).await?;
+    tokio::spawn(async move {
+        if let Err(e) = connection.await {
+            log::error!("postgres connection error: {}", e);
+        }
+    });
+    Ok(client)
+}
+
+async fn migrate(client: &mut Client) -> Result<(), tokio_postgres::Error> {
+    let transaction = client.transaction().await?;
+    transaction
+        .execute("SELECT pg_advisory_xact_lock($1)", &[&MIGRATION_LOCK_ID])
+        .await?;
+    transaction
+        .batch_execute(
+            "CREATE TABLE IF NOT EXISTS schema_migrations (
+                version INTEGER PRIMARY KEY,
+                applied_at TIMESTAMPTZ NOT NULL DEFAULT now()
+            )",
+        )
+        .await?;
+    for (version, script) in MIGRATIONS {
+        let applied = transaction
+            .query_opt(
+                "SELECT 1 FROM schema_migrations WHERE version = $1",
+                &[version],
+            )
+            .await?
+            .is_some();
+        if applied {
+            continue;
+        }
+        log::info!("applying postgres migration {}", version);
+        transaction.batch_execute(script).await?;
+        transaction
+            .execute(
+                "INSERT INTO schema_migrations (version) VALUES ($1)",
+                &[version],
+            )
+            .await?;
+    }
+    transaction.commit().await
+}
+
+pub type SqlParam = Box<dyn ToSql + Send + Sync>;
+pub type Sql = sql::Sql<SqlParam>;
+
+fn param_refs(sql: &Sql) -> Vec<&(dyn ToSql + Sync)> {
+    sql.params()
+        .iter()
+        .map(|p| p.as_ref() as &(dyn ToSql + Sync))
+        .collect()
+}
+
+/// Postgres条件处理器
+///
+/// 条件的语义尽量和MongoDB一致：缺失的字段和null相同，比较只在同类型的值之间进行
+pub struct PostgresConditionHandler {}
+
+impl ConditionHandler for PostgresConditionHandler {
+    type TransferResult = Sql;
+    type TransferPageResult = Sql;
+    type TransferSortResult = Sql;
+
+    fn transfer_condition(condition: &Condition) -> Self::TransferResult {
+        match condition {
+            Condition::Empty => Sql::new("TRUE"),
+            Condition::Single(node) => node_to_sql(node),
+            Condition::Complex { and, or, nor } => {
+                let mut parts = vec![];
+                if !and.is_empty() {
+                    parts.push(join_conditions(and, " AND "));
+                }
+                if !or.is_empty() {
+                    parts.push(join_conditions(or, " OR "));
+                }
+                if !nor.is_empty() {
+                    let mut sql = Sql::new("NOT ");
+                    sql.push(join_conditions(nor, " OR "));
+                    parts.push(sql);
+                }
+                if parts.is_empty() {
+                    return Sql::new("TRUE");
+                }
+                let mut sql = Sql::new("(");
+                for (i, part) in parts.into_iter().enumerate() {
+                    if i > 0 {
+                        sql.push_str(" AND ");
+                    }
+                    sql.push(part);
+                }
+                sql.push_str(")");
+                sql
+            }
+        }
+    }
+
+    fn transfer_page_options(page_option: &PageOption) -> Self::TransferPageResult {
+        // 页码从1开始，传入0时按第一页处理
+        let skip = page_option.page.saturating_sub(1) * page_option.size;
+        let mut sql = Sql::new("LIMIT ");
+        sql.push_param(Box::new(page_option.size as i64))
+            .push_str(" OFFSET ")
+            .push_param(Box::new(skip as i64));
+        sql
+    }
+
+    /// 排序时null最小，和MongoDB一样，最后按id排序
+    fn transfer_sort(sorts: &[SortOption]) -> Self::TransferSortResult {
+        let mut sql = Sql::new("");
+        for (i, sort) in keyset_sorts(sorts).iter().enumerate() {
+            if i > 0 {
+                sql.push_str(", ");
+            }
+            sql.push(field_expr(&sort.field).0);
+            sql.push_str(match sort.order {
+                SortOrder::Asc => " ASC NULLS FIRST",
+                SortOrder::Desc => " DESC NULLS LAST",
+            });
+        }
+        sql
+    }
+}
+
+fn join_conditions(conditions: &[Condition], separator: &str) -> Sql {
+    let mut sql = Sql::new("(");
+    for (i, condition) in conditions.iter().enumerate() {
+        if i > 0 {
+            sql.push_str(separator);
+        }
+        sql.push(PostgresConditionHandler::transfer_condition(condition));
+    }
+    sql.push_str(")");
+    sql
+}
+
+/// 字段路径对应的SQL表达式，返回的bool表示是否是jsonb中的值
+///
+/// 带点的路径取jsonb列中的值，例如`data.name`取data列中的name，JSON的null当作SQL的NULL
+fn field_expr(path: &str) -> (Sql, bool) {
+    let mut segments = path.split('.');
+    let column = quote_ident(&to_column_name(segments.next().unwrap_or_default()));
+    let keys: Vec<String> = segments.map(String::from).collect();
+    if keys.is_empty() {
+        return (Sql::new(&column), false);
+    }
+    let mut sql = Sql::new(&format!("NULLIF({} #> ", column));
+    sql.push_param(Box::new(keys)).push_str(", 'null'::jsonb)");
+    (sql, true)
+}
+
+/// 条件中的值转成普通列的参数
+fn to_param(value: &ConditionValue) -> SqlParam {
+    use ConditionValue::*;
+    match value {
+        StringValue(v) => Box::new(v.clone()),
+        StringVecValue(v) => Box::new(v.clone()),
+        Int32Value(v) => Box::new(*v),
+        Int32VecValue(v) => Box::new(v.clone()),
+        Int64Value(v) => Box::new(*v),
+        Int64VecValue(v) => Box::new(v.clone()),
+        DoubleValue(v) => Box::new(*v),
+        BooleanValue(v) => Box::new(*v),
+        DateTimeValue(v) => Box::new(*v),
+        ObjectIdValue(v) => Box::new(v.to_hex()),
+        ObjectIdVecValue(v) => Box::new(v.iter().map(|x| x.to_hex()).collect::<Vec<_>>()),
+        NullValue => Box::new(Option::<String>::None),
+    }
+}
+
+/// 条件中的值转成jsonb参数，时间和行数据中保存的一样是RFC3339字符串
+fn to_json(value: &ConditionValue) -> Value {
+    use ConditionValue::*;
+    match value {
+        StringValue(v) => json!(v),
+        StringVecValue(v) => json!(v),
+        Int32Value(v) => json!(v),
+        Int32VecValue(v) => json!(v),
+        Int64Value(v) => json!(v),
+        Int64VecValue(v) => json!(v),
+        DoubleValue(v) => json!(v),
+        BooleanValue(v) => json!(v),
+        DateTimeValue(v) => json!(v.to_rfc3339_opts(SecondsFormat::Millis, true)),
+        ObjectIdValue(v) => json!(v.to_hex()),
+        ObjectIdVecValue(v) => json!(v.iter().map(|x| x.to_hex()).collect::<Vec<_>>()),
+        NullValue => Value::Null,
+    }
+}
+
+/// 部分更新的SET子句，字段名转成列名，删除的字段设为NULL，同时将版本号加1
+///
+/// JSON列中的键用jsonb_set等函数修改，同一列的修改嵌套成一个表达式
+fn patch_to_sql(patch: &Patch) -> Sql {
+    let parts = sql::split_patch(patch);
+    let mut sql = Sql::new("version = version + 1");
+    for (field, value) in parts.fields {
+        sql.push_str(&format!(", {} = ", quote_ident(&to_column_name(field))));
+        match value {
+            // NULL不作为参数传递，否则参数的类型可能和列的类型不一致
+            ConditionValue::NullValue => sql.push_str("NULL"),
+            _ => sql.push_param(to_param(value)),
+        };
+    }
+    for (field, edits) in parts.json {
+        let column = quote_ident(&to_column_name(field));
+        sql.push_str(&format!(", {} = ", column))
+            .push(json_edit_expr(&column, &edits));
+    }
+    sql
+}
+
+/// 依次执行edits后的JSON列的值，改名时原来的值从更新前的列中取，原来的键不存在时不改名
+fn json_edit_expr(column: &str, edits: &[sql::JsonEdit]) -> Sql {
+    let (edit, before) = match edits.split_last() {
+        Some(last) => last,
+        None => return Sql::new(column),
+    };
+    let mut expr = Sql::new("");
+    match edit {
+        sql::JsonEdit::Set(key, value) => {
+            expr.push_str("jsonb_set(")
+                .push(json_edit_expr(column, before))
+                .push_str(", ")
+                .push_param(Box::new(vec![key.to_string()]))
+                .push_str("::text[], ")
+                .push_param(Box::new(to_json(value)))
+                .push_str("::jsonb)");
+        }
+        sql::JsonEdit::Unset(key) => {
+            expr.push_str("(")
+                .push(json_edit_expr(column, before))
+                .push_str(" - ")
+                .push_param(Box::new(key.to_string()))
+                .push_str("::text)");
+        }
+        sql::JsonEdit::Rename(from, to) => {
+            expr.push_str(&format!("CASE WHEN jsonb_exists({}, ", column))
+                .push_param(Box::new(from.to_string()))
+                .push_str("::text) THEN jsonb_set(")
+                .push(json_edit_expr(column, before))
+                .push_str(" - ")
+                .push_param(Box::new(from.to_string()))
+                .push_str("::text, ")
+                .push_param(Box::new(vec![to.to_string()]))
+                .push_str(&format!("::text[], {} -> ", column))
+                .push_param(Box::new(from.to_string()))
+                .push_str("::text) ELSE ")
+                .push(json_edit_expr(column, before))
+                .push_str(" END");
+        }
+    }
+    expr
+}
+
+fn node_to_sql(node: &ConditionNode) -> Sql {
+    let (expr, is_json) = field_expr(&node.field);
+    let param = |value: &ConditionValue| -> SqlParam {
+        if is_json {
+            Box::new(to_json(value))
+        } else {
+            to_param(value)
+        }
+    };
+    let mut sql = Sql::new("");
+    match (&node.operate, &node.value) {
+        (Operate::Eq, ConditionValue::NullValue) => {
+            sql.push(expr).push_str(" IS NULL");
+        }
+        (Operate::Ne, ConditionValue::NullValue) => {
+            sql.push(expr).push_str(" IS NOT NULL");
+        }
+        (_, ConditionValue::NullValue) => {
+            sql.push_str("FALSE");
+        }
+        (Operate::Eq, value) => {
+            sql.push(expr).push_str(" = ").push_param(param(value));
+        }
+        // 和MongoDB的$ne一样，null也算不相等
+        (Operate::Ne, value) => {
+            sql.push(expr)
+                .push_str(" IS DISTINCT FROM ")
+                .push_param(param(value));
+        }
+        (Operate::In, value) => {
+            let value: SqlParam = match (is_json, value) {
+                (true, v) => match to_json(v) {
+                    Value::Array(items) => Box::new(items),
+                    item => Box::new(vec![item]),
+                },
+                (false, v) => to_param(v),
+            };
+            sql.push(expr)
+                .push_str(" = ANY(")
+                .push_param(value)
+                .push_str(")");
+        }
+        (Operate::Contains, ConditionValue::StringValue(v)) => {
+            if is_json {
+                sql.push_str("(jsonb_typeof(")
+                    .push(field_expr(&node.field).0)
+                    .push_str(") = 'string' AND strpos(")
+                    .push(expr)
+                    .push_str(" #>> '{}', ")
+                    .push_param(Box::new(v.clone()))
+                    .push_str(") > 0)");
+            } else {
+                sql.push_str("strpos(")
+                    .push(expr)
+                    .push_str(", ")
+                    .push_param(Box::new(v.clone()))
+                    .push_str(") > 0");
+            }
+        }
+        (Operate::Contains, _) => {
+            sql.push_str("FALSE");
+        }
+        (operate, value) => {
+            let op = match operate {
+                Operate::Lt => " < ",
+                Operate::Le => " <= ",
+                Operate::Gt => " > ",
+                _ => " >= ",
+            };
+            if is_json {
+                // jsonb不同类型之间也有大小关系，这里限定只比较同类型的值
+                sql.push_str("(jsonb_typeof(")
+                    .push(field_expr(&node.field).0)
+                    .push_str(") = jsonb_typeof(")
+                    .push_param(param(value))
+                    .push_str(") AND ")
+                    .push(expr)
+                    .push_str(op)
+                    .push_param(param(value))
+                    .push_str(")");
+            } else {
+                sql.push(expr).push_str(op).push_param(param(value));
+            }
+        }
+    }
+    sql
+}
+
+/// 读取查询结果中的一列，转成条件中的值，用来生成游标
+fn column_value(row: &Row, index: usize) -> Result<ConditionValue, RepositoryError> {
+    let column_type = row.columns()[index].type_().clone();
+    let value = if column_type == Type::TIMESTAMPTZ {
+        row.try_get::<_, Option<DateTime<Utc>>>(index)?
+            .map(ConditionValue::DateTimeValue)
+    } else if column_type == Type::JSONB {
+        row.try_get::<_, Option<Value>>(index)?
+            .and_then(|v| match v {
+                Value::String(s) => Some(ConditionValue::StringValue(s)),
+                Value::Bool(b) => Some(ConditionValue::BooleanValue(b)),
+                Value::Number(n) => match n.as_i64() {
+                    Some(i) => Some(ConditionValue::Int64Value(i)),
+                    None => n.as_f64().map(ConditionValue::DoubleValue),
+                },
+                _ => None,
+            })
+    } else {
+        row.try_get::<_, Option<String>>(index)?
+            .map(ConditionValue::StringValue)
+    };
+    Ok(value.unwrap_or(ConditionValue::NullValue))
+}
@@ -0,0 +1,722 @@
+use std::{marker::PhantomData, str::FromStr, time::Instant};
+
+use async_trait::async_trait;
+use futures::future::BoxFuture;
+use mongodb::bson::oid::ObjectId;
+use serde_json::Value;
+use tokio_postgres::Row;
+
+use crate::{
+    entity,
+    repository::{
+        condition::*,
+        soft_delete::SoftDelete,
+        sql::{quote_ident, INSERT_BATCH_ROWS},
+        CRUDRepository, CursorResult, PageResult, PaginationRepository, RepositoryError, Storage,
+        TransactionError, TRANSACTION_TIMEOUT,
+    },
+};
+
+use super::{
+    column_value, is_transient, patch_to_sql, Postgres, PostgresConditionHandler, Sql, SqlParam,
+};
+
+pub type UserRepo = PostgresRepo<entity::User>;
+pub type WorkspaceRepo = SoftDelete<PostgresRepo<entity::Workspace>>;
+pub type CatalogRepo = SoftDelete<PostgresRepo<entity::Catalog>>;
+pub type TableRepo = SoftDelete<PostgresRepo<entity::Table>>;
+pub type RowRepo = PostgresRepo<entity::Row>;
+pub type MembershipRepo = PostgresRepo<entity::Membership>;
+pub type ApiKeyRepo = PostgresRepo<entity::ApiKey>;
+
+/// 实体和表中一行之间的转换
+pub trait PostgresEntity: Send + Sync + Sized {
+    /// 存储时使用的表名
+    const TABLE: &'static str;
+    /// 除id外的列，顺序和params一致
+    const COLUMNS: &'static [&'static str];
+
+    fn id(&self) -> &str;
+    /// 读取时的版本号，更新时用来检查数据有没有被修改过
+    fn version(&self) -> i64;
+    /// 除id外各列的参数
+    fn params(&self) -> Result<Vec<SqlParam>, RepositoryError>;
+    fn from_row(row: &Row) -> Result<Self, RepositoryError>;
+}
+
+/// 创建者只保存id，保存前校验id格式
+fn creator_param(creator: &entity::UserSummary) -> Result<SqlParam, RepositoryError> {
+    Ok(Box::new(ObjectId::from_str(&creator.id)?.to_hex()))
+}
+
+/// 只有id的创建者，用户名和显示名由服务层批量查询后填充
+fn creator_entity(id: String) -> entity::UserSummary {
+    entity::UserSummary {
+        id,
+        username: String::new(),
+        display_name: None,
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<SqlParam, RepositoryError> {
+    let value = serde_json::to_value(value).map_err(|e| RepositoryError::Backend(Box::new(e)))?;
+    Ok(Box::new(value))
+}
+
+fn from_json<T: serde::de::DeserializeOwned>(value: Value) -> Result<T, RepositoryError> {
+    serde_json::from_value(value).map_err(|e| RepositoryError::Backend(Box::new(e)))
+}
+
+impl PostgresEntity for entity::User {
+    const TABLE: &'static str = "users";
+    const COLUMNS: &'static [&'static str] = &["username", "password_hash", "display_name"];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<SqlParam>, RepositoryError> {
+        Ok(vec![
+            Box::new(self.username.clone()),
+            Box::new(self.password_hash.clone()),
+            Box::new(self.display_name.clone()),
+        ])
+    }
+
+    fn from_row(row: &Row) -> Result<Self, RepositoryError> {
+        Ok(entity::User {
+            id: row.try_get("id")?,
+            username: row.try_get("username")?,
+            password_hash: row.try_get("password_hash")?,
+            display_name: row.try_get("display_name")?,
+            version: row.try_get("version")?,
+        })
+    }
+}
+
+impl PostgresEntity for entity::Workspace {
+    const TABLE: &'static str = "workspaces";
+    const COLUMNS: &'static [&'static str] = &[
+        "name",
+        "description",
+        "creator",
+        "created_at",
+        "updated_at",
+        "deleted_at",
+        "deleted_by",
+    ];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<SqlParam>, RepositoryError> {
+        Ok(vec![
+            Box::new(self.name.clone()),
+            Box::new(self.description.clone()),
+            creator_param(&self.creator)?,
+            Box::new(self.created_at),
+            Box::new(self.updated_at),
+            Box::new(self.deleted_at),
+            Box::new(self.deleted_by.clone()),
+        ])
+    }
+
+    fn from_row(row: &Row) -> Result<Self, RepositoryError> {
+        Ok(entity::Workspace {
+            id: row.try_get("id")?,
+            name: row.try_get("name")?,
+            description: row.try_get("description")?,
+            creator: creator_entity(row.try_get("creator")?),
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+            version: row.try_get("version")?,
+            deleted_at: row.try_get("deleted_at")?,
+            deleted_by: row.try_get("deleted_by")?,
+        })
+    }
+}
+
+impl PostgresEntity for entity::Catalog {
+    const TABLE: &'static str = "catalogs";
+    const COLUMNS: &'static [&'static str] = &[
+        "workspace_id",
+        "name",
+        "description",
+        "creator",
+        "created_at",
+        "updated_at",
+        "deleted_at",
+        "deleted_by",
+    ];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<SqlParam>, RepositoryError> {
+        Ok(vec![
+            Box::new(self.workspace_id.clone()),
+            Box::new(self.name.clone()),
+            Box::new(self.description.clone()),
+            creator_param(&self.creator)?,
+            Box::new(self.created_at),
+            Box::new(self.updated_at),
+            Box::new(self.deleted_at),
+            Box::new(self.deleted_by.clone()),
+        ])
+    }
+
+    fn from_row(row: &Row) -> Result<Self, RepositoryError> {
+        Ok(entity::Catalog {
+            id: row.try_get("id")?,
+            workspace_id: row.try_get("workspace_id")?,
+            name: row.try_get("name")?,
+            description: row.try_get("description")?,
+            creator: creator_entity(row.try_get("creator")?),
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+            version: row.try_get("version")?,
+            deleted_at: row.try_get("deleted_at")?,
+            deleted_by: row.try_get("deleted_by")?,
+        })
+    }
+}
+
+impl PostgresEntity for entity::Table {
+    const TABLE: &'static str = "tables";
+    const COLUMNS: &'static [&'static str] = &[
+        "catalog_id",
+        "name",
+        "description",
+        "columns",
+        "creator",
+        "created_at",
+        "updated_at",
+        "deleted_at",
+        "deleted_by",
+    ];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<SqlParam>, RepositoryError> {
+        Ok(vec![
+            Box::new(self.catalog_id.clone()),
+            Box::new(self.name.clone()),
+            Box::new(self.description.clone()),
+            to_json(&self.columns)?,
+            creator_param(&self.creator)?,
+            Box::new(self.created_at),
+            Box::new(self.updated_at),
+            Box::new(self.deleted_at),
+            Box::new(self.deleted_by.clone()),
+        ])
+    }
+
+    fn from_row(row: &Row) -> Result<Self, RepositoryError> {
+        Ok(entity::Table {
+            id: row.try_get("id")?,
+            catalog_id: row.try_get("catalog_id")?,
+            name: row.try_get("name")?,
+            description: row.try_get("description")?,
+            columns: from_json(row.try_get("columns")?)?,
+            creator: creator_entity(row.try_get("creator")?),
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+            version: row.try_get("version")?,
+            deleted_at: row.try_get("deleted_at")?,
+            deleted_by: row.try_get("deleted_by")?,
+        })
+    }
+}
+
+impl PostgresEntity for entity::Row {
+    const TABLE: &'static str = "rows";
+    const COLUMNS: &'static [&'static str] = &["table_id", "data", "created_at", "updated_at"];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<SqlParam>, RepositoryError> {
+        Ok(vec![
+            Box::new(self.table_id.clone()),
+            to_json(&self.data)?,
+            Box::new(self.created_at),
+            Box::new(self.updated_at),
+        ])
+    }
+
+    fn from_row(row: &Row) -> Result<Self, RepositoryError> {
+        Ok(entity::Row {
+            id: row.try_get("id")?,
+            table_id: row.try_get("table_id")?,
+            data: from_json(row.try_get("data")?)?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+            version: row.try_get("version")?,
+        })
+    }
+}
+
+fn parse_role(role: &str) -> Result<entity::Role, RepositoryError> {
+    role.parse()
+        .map_err(|e: String| RepositoryError::Backend(e.into()))
+}
+
+impl PostgresEntity for entity::Membership {
+    const TABLE: &'static str = "memberships";
+    const COLUMNS: &'static [&'static str] = &[
+        "workspace_id",
+        "user_id",
+        "role",
+        "created_at",
+        "updated_at",
+    ];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<SqlParam>, RepositoryError> {
+        Ok(vec![
+            Box::new(self.workspace_id.clone()),
+            Box::new(self.user_id.clone()),
+            Box::new(self.role.as_str()),
+            Box::new(self.created_at),
+            Box::new(self.updated_at),
+        ])
+    }
+
+    fn from_row(row: &Row) -> Result<Self, RepositoryError> {
+        Ok(entity::Membership {
+            id: row.try_get("id")?,
+            workspace_id: row.try_get("workspace_id")?,
+            user_id: row.try_get("user_id")?,
+            role: parse_role(row.try_get("role")?)?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+            version: row.try_get("version")?,
+        })
+    }
+}
+
+impl PostgresEntity for entity::ApiKey {
+    const TABLE: &'static str = "api_keys";
+    const COLUMNS: &'static [&'static str] = &[
+        "user_id",
+        "name",
+        "prefix",
+        "key_hash",
+        "scope",
+        "expires_at",
+        "last_used_at",
+        "created_at",
+        "updated_at",
+    ];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<SqlParam>, RepositoryError> {
+        Ok(vec![
+            Box::new(self.user_id.clone()),
+            Box::new(self.name.clone()),
+            Box::new(self.prefix.clone()),
+            Box::new(self.key_hash.clone()),
+            to_json(&self.scope)?,
+            Box::new(self.expires_at),
+            Box::new(self.last_used_at),
+            Box::new(self.created_at),
+            Box::new(self.updated_at),
+        ])
+    }
+
+    fn from_row(row: &Row) -> Result<Self, RepositoryError> {
+        Ok(entity::ApiKey {
+            id: row.try_get("id")?,
+            user_id: row.try_get("user_id")?,
+            name: row.try_get("name")?,
+            prefix: row.try_get("prefix")?,
+            key_hash: row.try_get("key_hash")?,
+            scope: from_json(row.try_get("scope")?)?,
+            expires_at: row.try_get("expires_at")?,
+            last_used_at: row.try_get("last_used_at")?,
+            created_at: row.try_get("created_at")?,
+            updated_at: row.try_get("updated_at")?,
+            version: row.try_get("version")?,
+        })
+    }
+}
+
+/// Postgres的Repo，所有实体共用
+pub struct PostgresRepo<E> {
+    db: Postgres,
+    _entity: PhantomData<fn() -> E>,
+}
+
+impl<E> Clone for PostgresRepo<E> {
+    fn clone(&self) -> Self {
+        PostgresRepo {
+            db: self.db.clone(),
+            _entity: PhantomData,
+        }
+    }
+}
+
+impl<E: PostgresEntity> PostgresRepo<E> {
+    pub fn new(db: Postgres) -> Self {
+        PostgresRepo {
+            db,
+            _entity: PhantomData,
+        }
+    }
+
+    /// 查询语句的SELECT和FROM部分
+    fn select() -> Sql {
+        Sql::new(&format!("SELECT * FROM {}", quote_ident(E::TABLE)))
+    }
+
+    fn to_entities(rows: Vec<Row>) -> Result<Vec<E>, RepositoryError> {
+        rows.iter().map(E::from_row).collect()
+    }
+}
+
+#[async_trait]
+impl Storage for Postgres {
+    type UserRepo = UserRepo;
+    type WorkspaceRepo = WorkspaceRepo;
+    type CatalogRepo = CatalogRepo;
+    type TableRepo = TableRepo;
+    type RowRepo = RowRepo;
+    type MembershipRepo = MembershipRepo;
+    type ApiKeyRepo = ApiKeyRepo;
+
+    fn user_repo(&self) -> UserRepo {
+        PostgresRepo::new(self.clone())
+    }
+    fn workspace_repo(&self) -> WorkspaceRepo {
+        SoftDelete::new(PostgresRepo::new(self.clone()))
+    }
+    fn catalog_repo(&self) -> CatalogRepo {
+        SoftDelete::new(PostgresRepo::new(self.clone()))
+    }
+    fn table_repo(&self) -> TableRepo {
+        SoftDelete::new(PostgresRepo::new(self.clone()))
+    }
+    fn row_repo(&self) -> RowRepo {
+        PostgresRepo::new(self.clone())
+    }
+    fn membership_repo(&self) -> MembershipRepo {
+        PostgresRepo::new(self.clone())
+    }
+    fn api_key_repo(&self) -> ApiKeyRepo {
+        PostgresRepo::new(self.clone())
+    }
+
+    /// 序列化失败和死锁时重新执行整个事务，已经在事务中时直接加入当前事务
+    async fn with_transaction<T, E, F>(&self, work: F) -> Result<T, E>
+    where
+        T: Send,
+        E: TransactionError,
+        F: for<'a> Fn(&'a Self) -> BoxFuture<'a, Result<T, E>> + Send + Sync,
+    {
+        if self.in_transaction {
+            return work(self).await;
+        }
+        let deadline = Instant::now() + TRANSACTION_TIMEOUT;
+        loop {
+            let tx = self.begin().await?;
+            let result = match work(&tx).await {
+                Ok(value) => tx.commit().await.map(|_| value).map_err(E::from),
+                Err(e) => {
+                    tx.rollback().await;
+                    Err(e)
+                }
+            };
+            match result {
+                Err(e)
+                    if Instant::now() < deadline
+                        && e.repository_error().map_or(false, is_transient) =>
+                {
+                    continue
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<E: PostgresEntity> CRUDRepository<E> for PostgresRepo<E> {
+    type Error = RepositoryError;
+
+    async fn count(&self, condition: &Condition) -> Result<u64, Self::Error> {
+        let mut sql = Sql::new(&format!(
+            "SELECT count(*) FROM {} WHERE ",
+            quote_ident(E::TABLE)
+        ));
+        sql.push(PostgresConditionHandler::transfer_condition(condition));
+        let rows = self.db.query(sql).await?;
+        let count: i64 = rows[0].try_get(0)?;
+        Ok(count as u64)
+    }
+
+    async fn exist(&self, condition: &Condition) -> Result<bool, Self::Error> {
+        let mut sql = Sql::new(&format!(
+            "SELECT EXISTS (SELECT 1 FROM {} WHERE ",
+            quote_ident(E::TABLE)
+        ));
+        sql.push(PostgresConditionHandler::transfer_condition(condition))
+            .push_str(")");
+        let rows = self.db.query(sql).await?;
+        Ok(rows[0].try_get(0)?)
+    }
+
+    async fn find_one(&self, condition: &Condition) -> Result<E, Self::Error> {
+        let mut sql = Self::select();
+        sql.push_str(" WHERE ")
+            .push(PostgresConditionHandler::transfer_condition(condition))
+            .push_str(" LIMIT 1");
+        let rows = self.db.query(sql).await?;
+        let row = rows.first().ok_or(RepositoryError::DataNotFound)?;
+        E::from_row(row)
+    }
+
+    async fn find(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+    ) -> Result<Vec<E>, Self::Error> {
+        let mut sql = Self::select();
+        sql.push_str(" WHERE ")
+            .push(PostgresConditionHandler::transfer_condition(condition))
+            .push_str(" ORDER BY ")
+            .push(PostgresConditionHandler::transfer_sort(sorts));
+        Self::to_entities(self.db.query(sql).await?)
+    }
+
+    async fn create(&self, data: &E) -> Result<String, Self::Error> {
+        let id = ObjectId::new().to_hex();
+        let mut sql = Sql::new(&format!(
+            "INSERT INTO {} (id, {}) VALUES (",
+            quote_ident(E::TABLE),
+            E::COLUMNS.join(", ")
+        ));
+        sql.push_param(Box::new(id.clone()));
+        for param in data.params()? {
+            sql.push_str(", ").push_param(param);
+        }
+        sql.push_str(")");
+        self.db.execute(sql).await?;
+        Ok(id)
+    }
+
+    async fn update(&self, data: &E) -> Result<i64, Self::Error> {
+        if data.id().is_empty() {
+            return Err(RepositoryError::DataNotFound);
+        }
+        let mut sql = Sql::new(&format!(
+            "UPDATE {} SET version = version + 1",
+            quote_ident(E::TABLE)
+        ));
+        for (column, param) in E::COLUMNS.iter().zip(data.params()?) {
+            sql.push_str(&format!(", {} = ", column)).push_param(param);
+        }
+        sql.push_str(" WHERE id = ")
+            .push_param(Box::new(data.id().to_string()))
+            .push_str(" AND version = ")
+            .push_param(Box::new(data.version()));
+        if self.db.execute(sql).await? == 1 {
+            return Ok(data.version() + 1);
+        }
+        let exist = self
+            .exist(&Condition::single(
+                String::from("_id"),
+                Operate::Eq,
+                ConditionValue::StringValue(data.id().to_string()),
+            ))
+            .await?;
+        if exist {
+            Err(RepositoryError::VersionConflict)
+        } else {
+            Err(RepositoryError::DataNotFound)
+        }
+    }
+
+    /// 和MongoDB的find_one_and_update一样，只更新第一条匹配的数据
+    async fn patch(&self, condition: &Condition, patch: &Patch) -> Result<E, Self::Error> {
+        let table = quote_ident(E::TABLE);
+        let mut sql = Sql::new(&format!("UPDATE {} SET ", table));
+        sql.push(patch_to_sql(patch))
+            .push_str(&format!(" WHERE id IN (SELECT id FROM {} WHERE ", table))
+            .push(PostgresConditionHandler::transfer_condition(condition))
+            .push_str(" LIMIT 1) RETURNING *");
+        let rows = self.db.query(sql).await?;
+        let row = rows.first().ok_or(RepositoryError::DataNotFound)?;
+        E::from_row(row)
+    }
+
+    /// 和MongoDB的delete_one一样，只删除第一条匹配的数据
+    async fn delete(&self, condition: &Condition) -> Result<bool, Self::Error> {
+        let table = quote_ident(E::TABLE);
+        let mut sql = Sql::new(&format!(
+            "DELETE FROM {0} WHERE id IN (SELECT id FROM {0} WHERE ",
+            table
+        ));
+        sql.push(PostgresConditionHandler::transfer_condition(condition))
+            .push_str(" LIMIT 1)");
+        let result = self.db.execute(sql).await?;
+        Ok(result == 1)
+    }
+
+    /// 每INSERT_BATCH_ROWS行一条INSERT语句，需要全部成功或全部失败时在事务中调用
+    async fn create_many(&self, datas: &[E]) -> Result<Vec<String>, Self::Error> {
+        let mut ids = Vec::with_capacity(datas.len());
+        for chunk in datas.chunks(INSERT_BATCH_ROWS) {
+            let mut sql = Sql::new(&format!(
+                "INSERT INTO {} (id, {}) VALUES ",
+                quote_ident(E::TABLE),
+                E::COLUMNS.join(", ")
+            ));
+            for (i, data) in chunk.iter().enumerate() {
+                let id = ObjectId::new().to_hex();
+                sql.push_str(if i == 0 { "(" } else { ", (" })
+                    .push_param(Box::new(id.clone()));
+                for param in data.params()? {
+                    sql.push_str(", ").push_param(param);
+                }
+                sql.push_str(")");
+                ids.push(id);
+            }
+            self.db.execute(sql).await?;
+        }
+        Ok(ids)
+    }
+
+    async fn update_many(&self, condition: &Condition, patch: &Patch) -> Result<u64, Self::Error> {
+        let mut sql = Sql::new(&format!("UPDATE {} SET ", quote_ident(E::TABLE)));
+        sql.push(patch_to_sql(patch))
+            .push_str(" WHERE ")
+            .push(PostgresConditionHandler::transfer_condition(condition));
+        self.db.execute(sql).await
+    }
+
+    async fn delete_many(&self, condition: &Condition) -> Result<u64, Self::Error> {
+        let mut sql = Sql::new(&format!("DELETE FROM {} WHERE ", quote_ident(E::TABLE)));
+        sql.push(PostgresConditionHandler::transfer_condition(condition));
+        self.db.execute(sql).await
+    }
+}
+
+#[async_trait]
+impl<E: PostgresEntity> PaginationRepository<E> for PostgresRepo<E> {
+    async fn find_page(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+        page_setting: &PageOption,
+        is_count_all: bool,
+    ) -> Result<PageResult<E>, Self::Error> {
+        let mut sql = Self::select();
+        sql.push_str(" WHERE ")
+            .push(PostgresConditionHandler::transfer_condition(condition))
+            .push_str(" ORDER BY ")
+            .push(PostgresConditionHandler::transfer_sort(sorts))
+            .push_str(" ")
+            .push(PostgresConditionHandler::transfer_page_options(
+                page_setting,
+            ));
+        let datas = Self::to_entities(self.db.query(sql).await?)?;
+        let count = if is_count_all {
+            self.count(condition).await? as i64
+        } else {
+            datas.len() as i64
+        };
+        Ok(PageResult { datas, count })
+    }
+
+    async fn find_after(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+        cursor_option: &CursorOption,
+    ) -> Result<CursorResult<E>, Self::Error> {
+        let keys = keyset_sorts(sorts);
+        let after = match &cursor_option.after {
+            Some(cursor) => cursor.condition(),
+            None => Condition::Empty,
+        };
+        let condition = Condition::all(vec![condition.clone(), after]);
+        // 排序字段的值单独查出来，放在实体列之后，用来生成下一页的游标
+        let mut sql = Sql::new("SELECT *");
+        for (i, key) in keys.iter().enumerate() {
+            sql.push_str(", ")
+                .push(super::field_expr(&key.field).0)
+                .push_str(&format!(" AS __cursor_{}", i));
+        }
+        sql.push_str(&format!(" FROM {} WHERE ", quote_ident(E::TABLE)))
+            .push(PostgresConditionHandler::transfer_condition(&condition))
+            .push_str(" ORDER BY ")
+            .push(PostgresConditionHandler::transfer_sort(&keys))
+            .push_str(" LIMIT ")
+            .push_param(Box::new(cursor_option.size as i64 + 1));
+        let mut rows = self.db.query(sql).await?;
+        // 多出一条时才有下一页
+        let next_cursor = if rows.len() > cursor_option.size {
+            rows.truncate(cursor_option.size);
+            match rows.last() {
+                Some(row) => {
+                    let offset = row.len() - keys.len();
+                    let mut cursor_keys = vec![];
+                    for (i, key) in keys.iter().enumerate() {
+                        cursor_keys.push(CursorKey {
+                            field: key.field.clone(),
+                            order: key.order.clone(),
+                            value: column_value(row, offset + i)?,
+                        });
+                    }
+                    Some(Cursor { keys: cursor_keys })
+                }
+                None => None,
+            }
+        } else {
+            None
+        };
+        Ok(CursorResult {
+            datas: Self::to_entities(rows)?,
+            next_cursor,
+        })
+    }
+}
@@ -0,0 +1,275 @@
+use async_trait::async_trait;
+use chrono::{DateTime, SubsecRound, Utc};
+
+use super::{
+    condition::*, CRUDRepository, CursorResult, PageResult, PaginationRepository, Repository,
+    RepositoryError,
+};
+
+/// 删除标记字段，记录删除的时间和删除的用户
+pub const DELETED_AT: &str = "deletedAt";
+pub const DELETED_BY: &str = "deletedBy";
+
+/// 一次删除的时间和删除的用户
+///
+/// 级联删除时下级和上级使用同一个Deletion，恢复上级时按删除时间找到随它一起删除的下级。
+/// 时间只保留到毫秒，在各个存储中保存后都可以按相等比较
+#[derive(Clone, Debug)]
+pub struct Deletion {
+    pub at: DateTime<Utc>,
+    pub by: String,
+}
+
+impl Deletion {
+    pub fn new(deleted_by: &str) -> Self {
+        Deletion {
+            at: Utc::now().trunc_subsecs(3),
+            by: deleted_by.to_string(),
+        }
+    }
+
+    /// 在同一次删除中被删除的数据
+    pub fn deleted_at(at: DateTime<Utc>) -> Condition {
+        Condition::single(
+            String::from(DELETED_AT),
+            Operate::Eq,
+            ConditionValue::DateTimeValue(at),
+        )
+    }
+
+    fn patch(&self) -> Patch {
+        Patch::new()
+            .set(DELETED_AT, ConditionValue::DateTimeValue(self.at))
+            .set(DELETED_BY, ConditionValue::StringValue(self.by.clone()))
+    }
+}
+
+/// 支持软删除的Repo
+///
+/// 删除只是标记deletedAt和deletedBy，普通的查询自动排除已删除的数据，
+/// 已删除的数据只能通过这里的方法查询、恢复和彻底删除
+#[async_trait]
+pub trait SoftDeleteRepository<T>: Repository<T> {
+    /// 标记第一条满足条件的数据为已删除
+    async fn soft_delete(
+        &self,
+        condition: &Condition,
+        deletion: &Deletion,
+    ) -> Result<bool, RepositoryError>;
+    /// 标记所有满足条件的数据为已删除，返回标记的数量
+    async fn soft_delete_many(
+        &self,
+        condition: &Condition,
+        deletion: &Deletion,
+    ) -> Result<u64, RepositoryError>;
+    /// 按条件查询已删除的数据，结果按sorts排序
+    async fn find_deleted(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+    ) -> Result<Vec<T>, RepositoryError>;
+    /// 按条件查询，包括已删除和没有删除的数据
+    async fn find_with_deleted(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+    ) -> Result<Vec<T>, RepositoryError>;
+    /// 恢复第一条满足条件的已删除数据，返回恢复后的数据
+    async fn restore(&self, condition: &Condition) -> Result<T, RepositoryError>;
+    /// 恢复所有满足条件的已删除数据，返回恢复的数量
+    async fn restore_many(&self, condition: &Condition) -> Result<u64, RepositoryError>;
+    /// 彻底删除第一条满足条件的数据，不管是否已删除
+    ///
+    /// 级联删除时已删除的上级中可能还有没有删除的下级
+    async fn purge(&self, condition: &Condition) -> Result<bool, RepositoryError>;
+}
+
+/// 给任意Repo加上软删除，和具体存储无关
+#[derive(Clone)]
+pub struct SoftDelete<R> {
+    inner: R,
+}
+
+impl<R> SoftDelete<R> {
+    pub fn new(inner: R) -> Self {
+        SoftDelete { inner }
+    }
+}
+
+/// 只匹配没有删除的数据，旧数据没有deletedAt字段时也算没有删除
+fn live(condition: &Condition) -> Condition {
+    Condition::all(vec![
+        condition.clone(),
+        Condition::single(
+            String::from(DELETED_AT),
+            Operate::Eq,
+            ConditionValue::NullValue,
+        ),
+    ])
+}
+
+/// 只匹配已删除的数据
+fn deleted(condition: &Condition) -> Condition {
+    Condition::all(vec![
+        condition.clone(),
+        Condition::single(
+            String::from(DELETED_AT),
+            Operate::Ne,
+            ConditionValue::NullValue,
+        ),
+    ])
+}
+
+#[async_trait]
+impl<T, R> CRUDRepository<T> for SoftDelete<R>
+where
+    T: Send + Sync + 'static,
+    R: Repository<T>,
+{
+    type Error = RepositoryError;
+
+    async fn count(&self, condition: &Condition) -> Result<u64, Self::Error> {
+        self.inner.count(&live(condition)).await
+    }
+
+    async fn exist(&self, condition: &Condition) -> Result<bool, Self::Error> {
+        self.inner.exist(&live(condition)).await
+    }
+
+    async fn find_one(&self, condition: &Condition) -> Result<T, Self::Error> {
+        self.inner.find_one(&live(condition)).await
+    }
+
+    async fn find(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+    ) -> Result<Vec<T>, Self::Error> {
+        self.inner.find(&live(condition), sorts).await
+    }
+
+    async fn create(&self, data: &T) -> Result<String, Self::Error> {
+        self.inner.create(data).await
+    }
+
+    /// 按id更新，调用方需要先确认数据没有被删除
+    async fn update(&self, data: &T) -> Result<i64, Self::Error> {
+        self.inner.update(data).await
+    }
+
+    async fn patch(&self, condition: &Condition, patch: &Patch) -> Result<T, Self::Error> {
+        self.inner.patch(&live(condition), patch).await
+    }
+
+    /// 不知道删除的用户，deletedBy为空，需要记录删除的用户时使用soft_delete
+    async fn delete(&self, condition: &Condition) -> Result<bool, Self::Error> {
+        let patch = Patch::new().set(DELETED_AT, ConditionValue::DateTimeValue(Utc::now()));
+        match self.inner.patch(&live(condition), &patch).await {
+            Ok(_) => Ok(true),
+            Err(RepositoryError::DataNotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn create_many(&self, datas: &[T]) -> Result<Vec<String>, Self::Error> {
+        self.inner.create_many(datas).await
+    }
+
+    async fn update_many(&self, condition: &Condition, patch: &Patch) -> Result<u64, Self::Error> {
+        self.inner.update_many(&live(condition), patch).await
+    }
+
+    async fn delete_many(&self, condition: &Condition) -> Result<u64, Self::Error> {
+        let patch = Patch::new().set(DELETED_AT, ConditionValue::DateTimeValue(Utc::now()));
+        self.inner.update_many(&live(condition), &patch).await
+    }
+}
+
+#[async_trait]
+impl<T, R> PaginationRepository<T> for SoftDelete<R>
+where
+    T: Send + Sync + 'static,
+    R: Repository<T>,
+{
+    async fn find_page(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+        page_setting: &PageOption,
+        is_count_all: bool,
+    ) -> Result<PageResult<T>, Self::Error> {
+        self.inner
+            .find_page(&live(condition), sorts, page_setting, is_count_all)
+            .await
+    }
+
+    async fn find_after(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+        cursor_option: &CursorOption,
+    ) -> Result<CursorResult<T>, Self::Error> {
+        self.inner
+            .find_after(&live(condition), sorts, cursor_option)
+            .await
+    }
+}
+
+#[async_trait]
+impl<T, R> SoftDeleteRepository<T> for SoftDelete<R>
+where
+    T: Send + Sync + 'static,
+    R: Repository<T>,
+{
+    async fn soft_delete(
+        &self,
+        condition: &Condition,
+        deletion: &Deletion,
+    ) -> Result<bool, RepositoryError> {
+        match self.inner.patch(&live(condition), &deletion.patch()).await {
+            Ok(_) => Ok(true),
+            Err(RepositoryError::DataNotFound) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn soft_delete_many(
+        &self,
+        condition: &Condition,
+        deletion: &Deletion,
+    ) -> Result<u64, RepositoryError> {
+        self.inner
+            .update_many(&live(condition), &deletion.patch())
+            .await
+    }
+
+    async fn find_deleted(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+    ) -> Result<Vec<T>, RepositoryError> {
+        self.inner.find(&deleted(condition), sorts).await
+    }
+
+    async fn find_with_deleted(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+    ) -> Result<Vec<T>, RepositoryError> {
+        self.inner.find(condition, sorts).await
+    }
+
+    async fn restore(&self, condition: &Condition) -> Result<T, RepositoryError> {
+        let patch = Patch::new().unset(DELETED_AT).unset(DELETED_BY);
+        self.inner.patch(&deleted(condition), &patch).await
+    }
+
+    async fn restore_many(&self, condition: &Condition) -> Result<u64, RepositoryError> {
+        let patch = Patch::new().unset(DELETED_AT).unset(DELETED_BY);
+        self.inner.update_many(&deleted(condition), &patch).await
+    }
+
+    async fn purge(&self, condition: &Condition) -> Result<bool, RepositoryError> {
+        self.inner.delete(condition).await
+    }
+}
@@ -0,0 +1,141 @@
+//! 关系型数据库后端共用的SQL拼接工具
+
+use super::condition::{ConditionValue, Patch};
+
+/// 参数化的SQL片段，P是具体数据库的参数类型
+///
+/// 值都通过参数传递，不会拼进SQL中。片段中的占位符统一写成`?`，
+/// 需要编号占位符的数据库在拼接完成后再调用numbered编号
+pub struct Sql<P> {
+    text: String,
+    params: Vec<P>,
+}
+
+impl<P> Sql<P> {
+    pub fn new(text: &str) -> Self {
+        Sql {
+            text: text.to_string(),
+            params: vec![],
+        }
+    }
+
+    /// 追加一段不带参数的SQL
+    pub fn push_str(&mut self, text: &str) -> &mut Self {
+        self.text.push_str(text);
+        self
+    }
+
+    /// 追加一个参数占位符
+    pub fn push_param(&mut self, param: P) -> &mut Self {
+        self.text.push('?');
+        self.params.push(param);
+        self
+    }
+
+    pub fn push(&mut self, other: Sql<P>) -> &mut Self {
+        self.text.push_str(&other.text);
+        self.params.extend(other.params);
+        self
+    }
+
+    pub fn params(&self) -> &[P] {
+        &self.params
+    }
+
+    pub fn into_parts(self) -> (String, Vec<P>) {
+        (self.text, self.params)
+    }
+
+    /// 占位符按顺序编号成`$1`、`$2`…
+    pub fn numbered(&self) -> String {
+        let mut text = String::with_capacity(self.text.len());
+        let mut index = 0;
+        for c in self.text.chars() {
+            if c == '?' {
+                index += 1;
+                text.push_str(&format!("${}", index));
+            } else {
+                text.push(c);
+            }
+        }
+        text
+    }
+}
+
+/// 批量插入时每条INSERT语句最多包含的行数，避免超出数据库的参数数量上限
+pub const INSERT_BATCH_ROWS: usize = 500;
+
+/// 列名加上双引号
+pub fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// 驼峰的字段名转成下划线的列名，`_id`对应id列
+pub fn to_column_name(field: &str) -> String {
+    if field == "_id" {
+        return String::from("id");
+    }
+    let mut name = String::with_capacity(field.len() + 4);
+    for c in field.chars() {
+        if c.is_ascii_uppercase() {
+            name.push('_');
+            name.push(c.to_ascii_lowercase());
+        } else {
+            name.push(c);
+        }
+    }
+    name
+}
+
+/// 删除顶层字段即设为NULL
+static NULL_VALUE: ConditionValue = ConditionValue::NullValue;
+
+/// JSON列中一个键的修改
+pub enum JsonEdit<'a> {
+    Set(&'a str, &'a ConditionValue),
+    Unset(&'a str),
+    Rename(&'a str, &'a str),
+}
+
+/// 部分更新拆成的SET子句内容
+pub struct PatchParts<'a> {
+    /// 顶层字段的新值，删除的字段为NullValue
+    pub fields: Vec<(&'a str, &'a ConditionValue)>,
+    /// 每个JSON字段上按set、unset、rename的顺序执行的修改
+    pub json: Vec<(&'a str, Vec<JsonEdit<'a>>)>,
+}
+
+/// 拆分部分更新，带点的字段是JSON字段中的键，同一列的修改需要合成一个表达式
+pub fn split_patch(patch: &Patch) -> PatchParts<'_> {
+    let mut parts = PatchParts {
+        fields: vec![],
+        json: vec![],
+    };
+    for (field, value) in patch.set.iter() {
+        match field.split_once('.') {
+            Some((column, key)) => parts.push_json(column, JsonEdit::Set(key, value)),
+            None => parts.fields.push((field, value)),
+        }
+    }
+    for field in patch.unset.iter() {
+        match field.split_once('.') {
+            Some((column, key)) => parts.push_json(column, JsonEdit::Unset(key)),
+            None => parts.fields.push((field, &NULL_VALUE)),
+        }
+    }
+    for (from, to) in patch.rename.iter() {
+        if let (Some((column, from)), Some((_, to))) = (from.split_once('.'), to.split_once('.')) {
+            parts.push_json(column, JsonEdit::Rename(from, to));
+        }
+    }
+    parts
+}
+
+impl<'a> PatchParts<'a> {
+    fn push_json(&mut self, column: &'a str, edit: JsonEdit<'a>) {
+        match self.json.iter_mut().find(|(c, _)| *c == column) {
+            Some((_, edits)) => edits.push(edit),
+            None => self.json.push((column, vec![edit])),
+        }
+    }
+}
@@ -0,0 +1,590 @@
+use std::sync::{Arc, Mutex};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use rusqlite::{ffi, params_from_iter, types::Value, Connection, ErrorCode, TransactionBehavior};
+use serde::de::DeserializeOwned;
+
+use crate::repository::condition::*;
+
+use super::{
+    sql::{self, quote_ident, to_column_name},
+    RepositoryError,
+};
+
+pub mod repo;
+
+/// 按版本顺序执行的迁移脚本，已执行的版本记录在`PRAGMA user_version`中
+const MIGRATIONS: &[(i32, &str)] = &[
+    (1, include_str!("../../../migrations/sqlite/0001_init.sql")),
+    (
+        2,
+        include_str!("../../../migrations/sqlite/0002_unique_username.sql"),
+    ),
+    (
+        3,
+        include_str!("../../../migrations/sqlite/0003_memberships.sql"),
+    ),
+    (
+        4,
+        include_str!("../../../migrations/sqlite/0004_api_keys.sql"),
+    ),
+    (
+        5,
+        include_str!("../../../migrations/sqlite/0005_versions.sql"),
+    ),
+    (
+        6,
+        include_str!("../../../migrations/sqlite/0006_soft_delete.sql"),
+    ),
+    (
+        7,
+        include_str!("../../../migrations/sqlite/0007_display_name.sql"),
+    ),
+];
+
+impl From<rusqlite::Error> for RepositoryError {
+    fn from(err: rusqlite::Error) -> Self {
+        match &err {
+            rusqlite::Error::SqliteFailure(e, _)
+                if e.code == ErrorCode::ConstraintViolation
+                    && (e.extended_code == ffi::SQLITE_CONSTRAINT_UNIQUE
+                        || e.extended_code == ffi::SQLITE_CONSTRAINT_PRIMARYKEY) =>
+            {
+                RepositoryError::Conflict(err.to_string())
+            }
+            _ => RepositoryError::Backend(Box::new(err)),
+        }
+    }
+}
+
+pub type Sql = sql::Sql<Value>;
+
+/// 事务中可以重试的临时错误：数据库被其他连接锁住
+fn is_transient(err: &RepositoryError) -> bool {
+    match err {
+        RepositoryError::Backend(e) => match e.downcast_ref::<rusqlite::Error>() {
+            Some(rusqlite::Error::SqliteFailure(e, _)) => e.code == ErrorCode::DatabaseBusy,
+            _ => false,
+        },
+        _ => false,
+    }
+}
+
+/// SQLite数据库，所有Repo共用一个连接
+///
+/// rusqlite是同步的，查询都放到阻塞线程池中执行。事务使用同一个文件的单独连接，
+/// 避免同时处理的其他请求的语句混进事务
+#[derive(Clone)]
+pub struct Sqlite {
+    conn: Arc<Mutex<Connection>>,
+    path: Arc<str>,
+    in_transaction: bool,
+}
+
+impl Sqlite {
+    /// 打开数据库文件并执行还没有执行过的迁移，文件不存在时会自动创建
+    pub async fn init(path: &str) -> Result<Self, rusqlite::Error> {
+        let owned_path = path.to_string();
+        let conn = tokio::task::spawn_blocking(move || {
+            let mut conn = open(&owned_path)?;
+            migrate(&mut conn)?;
+            Ok::<_, rusqlite::Error>(conn)
+        })
+        .await
+        .expect("sqlite init task panicked")?;
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+            path: Arc::from(path),
+            in_transaction: false,
+        })
+    }
+
+    /// 打开一个新连接并开始事务，立即取得写锁
+    async fn begin(&self) -> Result<Self, RepositoryError> {
+        let path = self.path.clone();
+        let conn = tokio::task::spawn_blocking(move || open(&path))
+            .await
+            .map_err(|e| RepositoryError::Backend(Box::new(e)))??;
+        let tx = Self {
+            conn: Arc::new(Mutex::new(conn)),
+            path: self.path.clone(),
+            in_transaction: true,
+        };
+        tx.run(|conn| conn.execute_batch("BEGIN IMMEDIATE")).await?;
+        Ok(tx)
+    }
+
+    async fn commit(&self) -> Result<(), RepositoryError> {
+        self.run(|conn| conn.execute_batch("COMMIT")).await
+    }
+
+    /// 回滚失败时连接关闭后事务也会被回滚，所以只记录日志
+    async fn rollback(&self) {
+        if let Err(e) = self.run(|conn| conn.execute_batch("ROLLBACK")).await {
+            log::warn!("failed to roll back sqlite transaction: {}", e);
+        }
+    }
+
+    async fn run<T, F>(&self, f: F) -> Result<T, RepositoryError>
+    where
+        T: Send + 'static,
+        F: FnOnce(&Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let result = tokio::task::spawn_blocking(move || {
+            let conn = conn.lock().unwrap_or_else(|e| e.into_inner());
+            f(&conn)
+        })
+        .await
+        .map_err(|e| RepositoryError::Backend(Box::new(e)))??;
+        Ok(result)
+    }
+
+    async fn query(&self, sql: Sql) -> Result<Vec<Record>, RepositoryError> {
+        self.run(move |conn| {
+            let (text, params) = sql.into_parts();
+            let mut stmt = conn.prepare_cached(&text)?;
+            let columns: Arc<Vec<String>> =
+                Arc::new(stmt.column_names().into_iter().map(String::from).collect());
+            let rows = stmt.query_map(params_from_iter(params), |row| {
+                (0..columns.len())
+                    .map(|i| row.get::<_, Value>(i))
+                    .collect::<Result<Vec<_>, _>>()
+            })?;
+            rows.map(|values| {
+                Ok(Record {
+                    columns: columns.clone(),
+                    values: values?,
+                })
+            })
+            .collect()
+        })
+        .await
+    }
+
+    async fn execute(&self, sql: Sql) -> Result<u64, RepositoryError> {
+        self.run(move |conn| {
+            let (text, params) = sql.into_parts();
+            let mut stmt = conn.prepare_cached(&text)?;
+            let changed = stmt.execute(params_from_iter(params))?;
+            Ok(changed as u64)
+        })
+        .await
+    }
+}
+
+fn open(path: &str) -> Result<Connection, rusqlite::Error> {
+    let conn = Connection::open(path)?;
+    conn.execute_batch("PRAGMA journal_mode = WAL; PRAGMA busy_timeout = 5000;")?;
+    Ok(conn)
+}
+
+fn migrate(conn: &mut Connection) -> Result<(), rusqlite::Error> {
+    let transaction = conn.transaction_with_behavior(TransactionBehavior::Immediate)?;
+    let current: i32 = transaction.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (version, script) in MIGRATIONS {
+        if *version <= current {
+            continue;
+        }
+        log::info!("applying sqlite migration {}", version);
+        transaction.execute_batch(script)?;
+        transaction.execute_batch(&format!("PRAGMA user_version = {}", version))?;
+    }
+    transaction.commit()
+}
+
+/// 查询结果中的一行，连接锁释放前就要读出来
+pub struct Record {
+    columns: Arc<Vec<String>>,
+    values: Vec<Value>,
+}
+
+impl Record {
+    pub fn len(&self) -> usize {
+        self.values.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.values.is_empty()
+    }
+
+    fn value(&self, name: &str) -> Result<&Value, RepositoryError> {
+        self.columns
+            .iter()
+            .position(|c| c == name)
+            .map(|i| &self.values[i])
+            .ok_or_else(|| RepositoryError::Backend(format!("missing column: {}", name).into()))
+    }
+
+    pub fn integer(&self, name: &str) -> Result<i64, RepositoryError> {
+        match self.value(name)? {
+            Value::Integer(v) => Ok(*v),
+            _ => Err(invalid_column(name)),
+        }
+    }
+
+    pub fn text(&self, name: &str) -> Result<String, RepositoryError> {
+        match self.value(name)? {
+            Value::Text(v) => Ok(v.clone()),
+            _ => Err(invalid_column(name)),
+        }
+    }
+
+    pub fn datetime(&self, name: &str) -> Result<DateTime<Utc>, RepositoryError> {
+        let text = self.text(name)?;
+        DateTime::parse_from_rfc3339(&text)
+            .map(|t| t.with_timezone(&Utc))
+            .map_err(|e| RepositoryError::Backend(Box::new(e)))
+    }
+
+    /// 可以为NULL的时间列
+    pub fn optional_datetime(&self, name: &str) -> Result<Option<DateTime<Utc>>, RepositoryError> {
+        match self.value(name)? {
+            Value::Null => Ok(None),
+            _ => self.datetime(name).map(Some),
+        }
+    }
+
+    /// 可以为NULL的文本列
+    pub fn optional_text(&self, name: &str) -> Result<Option<String>, RepositoryError> {
+        match self.value(name)? {
+            Value::Null => Ok(None),
+            _ => self.text(name).map(Some),
+        }
+    }
+
+    /// 以JSON文本保存的列
+    pub fn json<T: DeserializeOwned>(&self, name: &str) -> Result<T, RepositoryError> {
+        let text = self.text(name)?;
+        serde_json::from_str(&text).map_err(|e| RepositoryError::Backend(Box::new(e)))
+    }
+}
+
+fn invalid_column(name: &str) -> RepositoryError {
+    RepositoryError::Backend(format!("invalid value in column: {}", name).into())
+}
+
+/// 时间统一保存为UTC的RFC3339字符串，固定精确到毫秒，保证字符串顺序和时间顺序一致
+pub fn datetime_text(value: &DateTime<Utc>) -> String {
+    value.to_rfc3339_opts(SecondsFormat::Millis, true)
+}
+
+/// SQLite条件处理器
+///
+/// 条件的语义尽量和MongoDB一致：缺失的字段和null相同，JSON中的值只和同类型的值比较
+pub struct SqliteConditionHandler {}
+
+impl ConditionHandler for SqliteConditionHandler {
+    type TransferResult = Sql;
+    type TransferPageResult = Sql;
+    type TransferSortResult = Sql;
+
+    fn transfer_condition(condition: &Condition) -> Self::TransferResult {
+        match condition {
+            Condition::Empty => Sql::new("TRUE"),
+            Condition::Single(node) => node_to_sql(node),
+            Condition::Complex { and, or, nor } => {
+                let mut parts = vec![];
+                if !and.is_empty() {
+                    parts.push(join_conditions(and, " AND "));
+                }
+                if !or.is_empty() {
+                    parts.push(join_conditions(or, " OR "));
+                }
+                if !nor.is_empty() {
+                    let mut sql = Sql::new("NOT ");
+                    sql.push(join_conditions(nor, " OR "));
+                    parts.push(sql);
+                }
+                if parts.is_empty() {
+                    return Sql::new("TRUE");
+                }
+                let mut sql = Sql::new("(");
+                for (i, part) in parts.into_iter().enumerate() {
+                    if i > 0 {
+                        sql.push_str(" AND ");
+                    }
+                    sql.push(part);
+                }
+                sql.push_str(")");
+                sql
+            }
+        }
+    }
+
+    fn transfer_page_options(page_option: &PageOption) -> Self::TransferPageResult {
+        // 页码从1开始，传入0时按第一页处理
+        let skip = page_option.page.saturating_sub(1) * page_option.size;
+        let mut sql = Sql::new("LIMIT ");
+        sql.push_param(Value::Integer(page_option.size as i64))
+            .push_str(" OFFSET ")
+            .push_param(Value::Integer(skip as i64));
+        sql
+    }
+
+    /// 排序时null最小，和MongoDB一样，最后按id排序
+    fn transfer_sort(sorts: &[SortOption]) -> Self::TransferSortResult {
+        let mut sql = Sql::new("");
+        for (i, sort) in keyset_sorts(sorts).iter().enumerate() {
+            if i > 0 {
+                sql.push_str(", ");
+            }
+            sql.push(FieldPath::parse(&sort.field).expr());
+            sql.push_str(match sort.order {
+                SortOrder::Asc => " ASC NULLS FIRST",
+                SortOrder::Desc => " DESC NULLS LAST",
+            });
+        }
+        sql
+    }
+}
+
+fn join_conditions(conditions: &[Condition], separator: &str) -> Sql {
+    let mut sql = Sql::new("(");
+    for (i, condition) in conditions.iter().enumerate() {
+        if i > 0 {
+            sql.push_str(separator);
+        }
+        sql.push(SqliteConditionHandler::transfer_condition(condition));
+    }
+    sql.push_str(")");
+    sql
+}
+
+/// 字段路径，带点的路径取JSON列中的值，例如`data.name`取data列中的name
+struct FieldPath {
+    column: String,
+    json_path: Option<String>,
+}
+
+impl FieldPath {
+    fn parse(path: &str) -> Self {
+        let mut segments = path.split('.');
+        let column = quote_ident(&to_column_name(segments.next().unwrap_or_default()));
+        let keys: Vec<String> = segments.map(json_key).collect();
+        let json_path = if keys.is_empty() {
+            None
+        } else {
+            Some(format!("${}", keys.concat()))
+        };
+        FieldPath { column, json_path }
+    }
+
+    /// 取值的表达式，JSON的null和缺失的键都是NULL
+    fn expr(&self) -> Sql {
+        match &self.json_path {
+            None => Sql::new(&self.column),
+            Some(path) => {
+                let mut sql = Sql::new(&format!("json_extract({}, ", self.column));
+                sql.push_param(Value::Text(path.clone())).push_str(")");
+                sql
+            }
+        }
+    }
+
+    /// 限定JSON值的类型，普通列不需要限定
+    fn typed(&self, value: &ConditionValue, body: Sql) -> Sql {
+        match &self.json_path {
+            None => body,
+            Some(path) => {
+                let mut sql = Sql::new(&format!("COALESCE(json_type({}, ", self.column));
+                sql.push_param(Value::Text(path.clone()))
+                    .push_str(&format!(") IN ({}) AND ", json_types(value)))
+                    .push(body)
+                    .push_str(", FALSE)");
+                sql
+            }
+        }
+    }
+}
+
+/// JSON路径中的一层键，键加上双引号
+fn json_key(key: &str) -> String {
+    format!(".\"{}\"", key.replace('"', "\\\""))
+}
+
+/// 条件中的值对应的json_type结果
+fn json_types(value: &ConditionValue) -> &'static str {
+    use ConditionValue::*;
+    match value {
+        Int32Value(_) | Int32VecValue(_) | Int64Value(_) | Int64VecValue(_) | DoubleValue(_) => {
+            "'integer', 'real'"
+        }
+        BooleanValue(_) => "'true', 'false'",
+        NullValue => "'null'",
+        _ => "'text'",
+    }
+}
+
+/// 条件中的值转成参数，数组展开成多个参数
+fn to_params(value: &ConditionValue) -> Vec<Value> {
+    use ConditionValue::*;
+    match value {
+        StringValue(v) => vec![Value::Text(v.clone())],
+        StringVecValue(v) => v.iter().cloned().map(Value::Text).collect(),
+        Int32Value(v) => vec![Value::Integer(*v as i64)],
+        Int32VecValue(v) => v.iter().map(|i| Value::Integer(*i as i64)).collect(),
+        Int64Value(v) => vec![Value::Integer(*v)],
+        Int64VecValue(v) => v.iter().cloned().map(Value::Integer).collect(),
+        DoubleValue(v) => vec![Value::Real(*v)],
+        BooleanValue(v) => vec![Value::Integer(*v as i64)],
+        DateTimeValue(v) => vec![Value::Text(datetime_text(v))],
+        ObjectIdValue(v) => vec![Value::Text(v.to_hex())],
+        ObjectIdVecValue(v) => v.iter().map(|x| Value::Text(x.to_hex())).collect(),
+        NullValue => vec![Value::Null],
+    }
+}
+
+fn to_param(value: &ConditionValue) -> Value {
+    to_params(value).into_iter().next().unwrap_or(Value::Null)
+}
+
+/// 部分更新的SET子句，字段名转成列名，删除的字段设为NULL，同时将版本号加1
+///
+/// JSON列中的键用json_set等函数修改，同一列的修改嵌套成一个表达式
+fn patch_to_sql(patch: &Patch) -> Sql {
+    let parts = sql::split_patch(patch);
+    let mut sql = Sql::new("version = version + 1");
+    for (field, value) in parts.fields {
+        sql.push_str(&format!(", {} = ", quote_ident(&to_column_name(field))));
+        match value {
+            ConditionValue::NullValue => sql.push_str("NULL"),
+            _ => sql.push_param(to_param(value)),
+        };
+    }
+    for (field, edits) in parts.json {
+        let column = quote_ident(&to_column_name(field));
+        sql.push_str(&format!(", {} = ", column))
+            .push(json_edit_expr(&column, &edits));
+    }
+    sql
+}
+
+/// 依次执行edits后的JSON列的值，改名时原来的值从更新前的列中取，原来的键不存在时不改名
+fn json_edit_expr(column: &str, edits: &[sql::JsonEdit]) -> Sql {
+    let (edit, before) = match edits.split_last() {
+        Some(last) => last,
+        None => return Sql::new(column),
+    };
+    let path = |key: &str| Value::Text(format!("${}", json_key(key)));
+    let mut expr = Sql::new("");
+    match edit {
+        sql::JsonEdit::Set(key, value) => {
+            expr.push_str("json_set(")
+                .push(json_edit_expr(column, before))
+                .push_str(", ")
+                .push_param(path(key));
+            match value {
+                // 布尔值作为参数时是0和1，需要转成JSON的true和false
+                ConditionValue::BooleanValue(b) => expr
+                    .push_str(", json(")
+                    .push_param(Value::Text(b.to_string()))
+                    .push_str("))"),
+                _ => expr
+                    .push_str(", ")
+                    .push_param(to_param(value))
+                    .push_str(")"),
+            };
+        }
+        sql::JsonEdit::Unset(key) => {
+            expr.push_str("json_remove(")
+                .push(json_edit_expr(column, before))
+                .push_str(", ")
+                .push_param(path(key))
+                .push_str(")");
+        }
+        sql::JsonEdit::Rename(from, to) => {
+            expr.push_str(&format!("CASE WHEN json_type({}, ", column))
+                .push_param(path(from))
+                .push_str(") IS NOT NULL THEN json_set(json_remove(")
+                .push(json_edit_expr(column, before))
+                .push_str(", ")
+                .push_param(path(from))
+                .push_str("), ")
+                .push_param(path(to))
+                .push_str(&format!(", json({} -> ", column))
+                .push_param(path(from))
+                .push_str(")) ELSE ")
+                .push(json_edit_expr(column, before))
+                .push_str(" END");
+        }
+    }
+    expr
+}
+
+fn node_to_sql(node: &ConditionNode) -> Sql {
+    let field = FieldPath::parse(&node.field);
+    let mut sql = Sql::new("");
+    match (&node.operate, &node.value) {
+        (Operate::Eq, ConditionValue::NullValue) => {
+            sql.push(field.expr()).push_str(" IS NULL");
+        }
+        (Operate::Ne, ConditionValue::NullValue) => {
+            sql.push(field.expr()).push_str(" IS NOT NULL");
+        }
+        (_, ConditionValue::NullValue) => {
+            sql.push_str("FALSE");
+        }
+        (Operate::Eq, value) => {
+            let mut body = field.expr();
+            body.push_str(" = ").push_param(to_param(value));
+            sql.push(field.typed(value, body));
+        }
+        // 和MongoDB的$ne一样，null也算不相等
+        (Operate::Ne, value) => {
+            let mut body = field.expr();
+            body.push_str(" = ").push_param(to_param(value));
+            sql.push_str("NOT ").push(field.typed(value, body));
+        }
+        (Operate::In, value) => {
+            let params = to_params(value);
+            if params.is_empty() {
+                sql.push_str("FALSE");
+            } else {
+                let mut body = field.expr();
+                body.push_str(" IN (");
+                for (i, param) in params.into_iter().enumerate() {
+                    if i > 0 {
+                        body.push_str(", ");
+                    }
+                    body.push_param(param);
+                }
+                body.push_str(")");
+                sql.push(field.typed(value, body));
+            }
+        }
+        (Operate::Contains, ConditionValue::StringValue(v)) => {
+            let mut body = Sql::new("instr(");
+            body.push(field.expr())
+                .push_str(", ")
+                .push_param(Value::Text(v.clone()))
+                .push_str(") > 0");
+            sql.push(field.typed(&node.value, body));
+        }
+        (Operate::Contains, _) => {
+            sql.push_str("FALSE");
+        }
+        (operate, value) => {
+            let op = match operate {
+                Operate::Lt => " < ",
+                Operate::Le => " <= ",
+                Operate::Gt => " > ",
+                _ => " >= ",
+            };
+            let mut body = field.expr();
+            body.push_str(op).push_param(to_param(value));
+            sql.push(field.typed(value, body));
+        }
+    }
+    sql
+}
+
+/// 读取查询结果中的一列，转成条件中的值，用来生成游标
+fn column_value(record: &Record, index: usize) -> ConditionValue {
+    match &record.values[index] {
+        Value::Integer(v) => ConditionValue::Int64Value(*v),
+        Value::Real(v) => ConditionValue::DoubleValue(*v),
+        Value::Text(v) => ConditionValue::StringValue(v.clone()),
+        _ => ConditionValue::NullValue,
+    }
+}
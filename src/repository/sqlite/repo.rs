@@ -0,0 +1,729 @@
+use std::{marker::PhantomData, str::FromStr, time::Instant};
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use mongodb::bson::oid::ObjectId;
+use rusqlite::types::Value;
+
+use crate::{
+    entity,
+    repository::{
+        condition::*,
+        soft_delete::SoftDelete,
+        sql::{quote_ident, INSERT_BATCH_ROWS},
+        CRUDRepository, CursorResult, PageResult, PaginationRepository, RepositoryError, Storage,
+        TransactionError, TRANSACTION_TIMEOUT,
+    },
+};
+
+use super::{
+    column_value, datetime_text, is_transient, patch_to_sql, FieldPath, Record, Sql, Sqlite,
+    SqliteConditionHandler,
+};
+
+pub type UserRepo = SqliteRepo<entity::User>;
+pub type WorkspaceRepo = SoftDelete<SqliteRepo<entity::Workspace>>;
+pub type CatalogRepo = SoftDelete<SqliteRepo<entity::Catalog>>;
+pub type TableRepo = SoftDelete<SqliteRepo<entity::Table>>;
+pub type RowRepo = SqliteRepo<entity::Row>;
+pub type MembershipRepo = SqliteRepo<entity::Membership>;
+pub type ApiKeyRepo = SqliteRepo<entity::ApiKey>;
+
+/// 实体和表中一行之间的转换
+pub trait SqliteEntity: Send + Sync + Sized + 'static {
+    /// 存储时使用的表名
+    const TABLE: &'static str;
+    /// 除id外的列，顺序和params一致
+    const COLUMNS: &'static [&'static str];
+
+    fn id(&self) -> &str;
+    /// 读取时的版本号，更新时用来检查数据有没有被修改过
+    fn version(&self) -> i64;
+    /// 除id外各列的参数
+    fn params(&self) -> Result<Vec<Value>, RepositoryError>;
+    fn from_record(record: &Record) -> Result<Self, RepositoryError>;
+}
+
+/// 创建者只保存id，保存前校验id格式
+fn creator_param(creator: &entity::UserSummary) -> Result<Value, RepositoryError> {
+    Ok(Value::Text(ObjectId::from_str(&creator.id)?.to_hex()))
+}
+
+/// 只有id的创建者，用户名和显示名由服务层批量查询后填充
+fn creator_entity(id: String) -> entity::UserSummary {
+    entity::UserSummary {
+        id,
+        username: String::new(),
+        display_name: None,
+    }
+}
+
+fn to_json<T: serde::Serialize>(value: &T) -> Result<Value, RepositoryError> {
+    let text = serde_json::to_string(value).map_err(|e| RepositoryError::Backend(Box::new(e)))?;
+    Ok(Value::Text(text))
+}
+
+impl SqliteEntity for entity::User {
+    const TABLE: &'static str = "users";
+    const COLUMNS: &'static [&'static str] = &["username", "password_hash", "display_name"];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<Value>, RepositoryError> {
+        Ok(vec![
+            Value::Text(self.username.clone()),
+            Value::Text(self.password_hash.clone()),
+            optional_text(&self.display_name),
+        ])
+    }
+
+    fn from_record(record: &Record) -> Result<Self, RepositoryError> {
+        Ok(entity::User {
+            id: record.text("id")?,
+            username: record.text("username")?,
+            password_hash: record.text("password_hash")?,
+            display_name: record.optional_text("display_name")?,
+            version: record.integer("version")?,
+        })
+    }
+}
+
+impl SqliteEntity for entity::Workspace {
+    const TABLE: &'static str = "workspaces";
+    const COLUMNS: &'static [&'static str] = &[
+        "name",
+        "description",
+        "creator",
+        "created_at",
+        "updated_at",
+        "deleted_at",
+        "deleted_by",
+    ];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<Value>, RepositoryError> {
+        Ok(vec![
+            Value::Text(self.name.clone()),
+            Value::Text(self.description.clone()),
+            creator_param(&self.creator)?,
+            Value::Text(datetime_text(&self.created_at)),
+            Value::Text(datetime_text(&self.updated_at)),
+            optional_datetime(&self.deleted_at),
+            optional_text(&self.deleted_by),
+        ])
+    }
+
+    fn from_record(record: &Record) -> Result<Self, RepositoryError> {
+        Ok(entity::Workspace {
+            id: record.text("id")?,
+            name: record.text("name")?,
+            description: record.text("description")?,
+            creator: creator_entity(record.text("creator")?),
+            created_at: record.datetime("created_at")?,
+            updated_at: record.datetime("updated_at")?,
+            version: record.integer("version")?,
+            deleted_at: record.optional_datetime("deleted_at")?,
+            deleted_by: record.optional_text("deleted_by")?,
+        })
+    }
+}
+
+impl SqliteEntity for entity::Catalog {
+    const TABLE: &'static str = "catalogs";
+    const COLUMNS: &'static [&'static str] = &[
+        "workspace_id",
+        "name",
+        "description",
+        "creator",
+        "created_at",
+        "updated_at",
+        "deleted_at",
+        "deleted_by",
+    ];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<Value>, RepositoryError> {
+        Ok(vec![
+            Value::Text(self.workspace_id.clone()),
+            Value::Text(self.name.clone()),
+            Value::Text(self.description.clone()),
+            creator_param(&self.creator)?,
+            Value::Text(datetime_text(&self.created_at)),
+            Value::Text(datetime_text(&self.updated_at)),
+            optional_datetime(&self.deleted_at),
+            optional_text(&self.deleted_by),
+        ])
+    }
+
+    fn from_record(record: &Record) -> Result<Self, RepositoryError> {
+        Ok(entity::Catalog {
+            id: record.text("id")?,
+            workspace_id: record.text("workspace_id")?,
+            name: record.text("name")?,
+            description: record.text("description")?,
+            creator: creator_entity(record.text("creator")?),
+            created_at: record.datetime("created_at")?,
+            updated_at: record.datetime("updated_at")?,
+            version: record.integer("version")?,
+            deleted_at: record.optional_datetime("deleted_at")?,
+            deleted_by: record.optional_text("deleted_by")?,
+        })
+    }
+}
+
+impl SqliteEntity for entity::Table {
+    const TABLE: &'static str = "tables";
+    const COLUMNS: &'static [&'static str] = &[
+        "catalog_id",
+        "name",
+        "description",
+        "columns",
+        "creator",
+        "created_at",
+        "updated_at",
+        "deleted_at",
+        "deleted_by",
+    ];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<Value>, RepositoryError> {
+        Ok(vec![
+            Value::Text(self.catalog_id.clone()),
+            Value::Text(self.name.clone()),
+            Value::Text(self.description.clone()),
+            to_json(&self.columns)?,
+            creator_param(&self.creator)?,
+            Value::Text(datetime_text(&self.created_at)),
+            Value::Text(datetime_text(&self.updated_at)),
+            optional_datetime(&self.deleted_at),
+            optional_text(&self.deleted_by),
+        ])
+    }
+
+    fn from_record(record: &Record) -> Result<Self, RepositoryError> {
+        Ok(entity::Table {
+            id: record.text("id")?,
+            catalog_id: record.text("catalog_id")?,
+            name: record.text("name")?,
+            description: record.text("description")?,
+            columns: record.json("columns")?,
+            creator: creator_entity(record.text("creator")?),
+            created_at: record.datetime("created_at")?,
+            updated_at: record.datetime("updated_at")?,
+            version: record.integer("version")?,
+            deleted_at: record.optional_datetime("deleted_at")?,
+            deleted_by: record.optional_text("deleted_by")?,
+        })
+    }
+}
+
+impl SqliteEntity for entity::Row {
+    const TABLE: &'static str = "rows";
+    const COLUMNS: &'static [&'static str] = &["table_id", "data", "created_at", "updated_at"];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<Value>, RepositoryError> {
+        Ok(vec![
+            Value::Text(self.table_id.clone()),
+            to_json(&self.data)?,
+            Value::Text(datetime_text(&self.created_at)),
+            Value::Text(datetime_text(&self.updated_at)),
+        ])
+    }
+
+    fn from_record(record: &Record) -> Result<Self, RepositoryError> {
+        Ok(entity::Row {
+            id: record.text("id")?,
+            table_id: record.text("table_id")?,
+            data: record.json("data")?,
+            created_at: record.datetime("created_at")?,
+            updated_at: record.datetime("updated_at")?,
+            version: record.integer("version")?,
+        })
+    }
+}
+
+impl SqliteEntity for entity::Membership {
+    const TABLE: &'static str = "memberships";
+    const COLUMNS: &'static [&'static str] = &[
+        "workspace_id",
+        "user_id",
+        "role",
+        "created_at",
+        "updated_at",
+    ];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<Value>, RepositoryError> {
+        Ok(vec![
+            Value::Text(self.workspace_id.clone()),
+            Value::Text(self.user_id.clone()),
+            Value::Text(self.role.to_string()),
+            Value::Text(datetime_text(&self.created_at)),
+            Value::Text(datetime_text(&self.updated_at)),
+        ])
+    }
+
+    fn from_record(record: &Record) -> Result<Self, RepositoryError> {
+        Ok(entity::Membership {
+            id: record.text("id")?,
+            workspace_id: record.text("workspace_id")?,
+            user_id: record.text("user_id")?,
+            role: record
+                .text("role")?
+                .parse()
+                .map_err(|e: String| RepositoryError::Backend(e.into()))?,
+            created_at: record.datetime("created_at")?,
+            updated_at: record.datetime("updated_at")?,
+            version: record.integer("version")?,
+        })
+    }
+}
+
+/// 可以为NULL的文本
+fn optional_text(value: &Option<String>) -> Value {
+    match value {
+        Some(v) => Value::Text(v.clone()),
+        None => Value::Null,
+    }
+}
+
+/// 可以为NULL的时间
+fn optional_datetime(value: &Option<DateTime<Utc>>) -> Value {
+    match value {
+        Some(v) => Value::Text(datetime_text(v)),
+        None => Value::Null,
+    }
+}
+
+impl SqliteEntity for entity::ApiKey {
+    const TABLE: &'static str = "api_keys";
+    const COLUMNS: &'static [&'static str] = &[
+        "user_id",
+        "name",
+        "prefix",
+        "key_hash",
+        "scope",
+        "expires_at",
+        "last_used_at",
+        "created_at",
+        "updated_at",
+    ];
+
+    fn id(&self) -> &str {
+        &self.id
+    }
+
+    fn version(&self) -> i64 {
+        self.version
+    }
+
+    fn params(&self) -> Result<Vec<Value>, RepositoryError> {
+        Ok(vec![
+            Value::Text(self.user_id.clone()),
+            Value::Text(self.name.clone()),
+            Value::Text(self.prefix.clone()),
+            Value::Text(self.key_hash.clone()),
+            to_json(&self.scope)?,
+            optional_datetime(&self.expires_at),
+            optional_datetime(&self.last_used_at),
+            Value::Text(datetime_text(&self.created_at)),
+            Value::Text(datetime_text(&self.updated_at)),
+        ])
+    }
+
+    fn from_record(record: &Record) -> Result<Self, RepositoryError> {
+        Ok(entity::ApiKey {
+            id: record.text("id")?,
+            user_id: record.text("user_id")?,
+            name: record.text("name")?,
+            prefix: record.text("prefix")?,
+            key_hash: record.text("key_hash")?,
+            scope: record.json("scope")?,
+            expires_at: record.optional_datetime("expires_at")?,
+            last_used_at: record.optional_datetime("last_used_at")?,
+            created_at: record.datetime("created_at")?,
+            updated_at: record.datetime("updated_at")?,
+            version: record.integer("version")?,
+        })
+    }
+}
+
+/// SQLite的Repo，所有实体共用
+pub struct SqliteRepo<E> {
+    db: Sqlite,
+    _entity: PhantomData<fn() -> E>,
+}
+
+impl<E> Clone for SqliteRepo<E> {
+    fn clone(&self) -> Self {
+        SqliteRepo {
+            db: self.db.clone(),
+            _entity: PhantomData,
+        }
+    }
+}
+
+impl<E: SqliteEntity> SqliteRepo<E> {
+    pub fn new(db: Sqlite) -> Self {
+        SqliteRepo {
+            db,
+            _entity: PhantomData,
+        }
+    }
+
+    /// 查询语句的SELECT和FROM部分
+    fn select() -> Sql {
+        Sql::new(&format!("SELECT * FROM {}", quote_ident(E::TABLE)))
+    }
+
+    fn to_entities(records: Vec<Record>) -> Result<Vec<E>, RepositoryError> {
+        records.iter().map(E::from_record).collect()
+    }
+}
+
+#[async_trait]
+impl Storage for Sqlite {
+    type UserRepo = UserRepo;
+    type WorkspaceRepo = WorkspaceRepo;
+    type CatalogRepo = CatalogRepo;
+    type TableRepo = TableRepo;
+    type RowRepo = RowRepo;
+    type MembershipRepo = MembershipRepo;
+    type ApiKeyRepo = ApiKeyRepo;
+
+    fn user_repo(&self) -> UserRepo {
+        SqliteRepo::new(self.clone())
+    }
+    fn workspace_repo(&self) -> WorkspaceRepo {
+        SoftDelete::new(SqliteRepo::new(self.clone()))
+    }
+    fn catalog_repo(&self) -> CatalogRepo {
+        SoftDelete::new(SqliteRepo::new(self.clone()))
+    }
+    fn table_repo(&self) -> TableRepo {
+        SoftDelete::new(SqliteRepo::new(self.clone()))
+    }
+    fn row_repo(&self) -> RowRepo {
+        SqliteRepo::new(self.clone())
+    }
+    fn membership_repo(&self) -> MembershipRepo {
+        SqliteRepo::new(self.clone())
+    }
+    fn api_key_repo(&self) -> ApiKeyRepo {
+        SqliteRepo::new(self.clone())
+    }
+
+    /// 数据库被其他连接锁住时重新执行整个事务，已经在事务中时直接加入当前事务
+    async fn with_transaction<T, E, F>(&self, work: F) -> Result<T, E>
+    where
+        T: Send,
+        E: TransactionError,
+        F: for<'a> Fn(&'a Self) -> BoxFuture<'a, Result<T, E>> + Send + Sync,
+    {
+        if self.in_transaction {
+            return work(self).await;
+        }
+        let deadline = Instant::now() + TRANSACTION_TIMEOUT;
+        loop {
+            let tx = self.begin().await?;
+            let result = match work(&tx).await {
+                Ok(value) => tx.commit().await.map(|_| value).map_err(E::from),
+                Err(e) => {
+                    tx.rollback().await;
+                    Err(e)
+                }
+            };
+            match result {
+                Err(e)
+                    if Instant::now() < deadline
+                        && e.repository_error().map_or(false, is_transient) =>
+                {
+                    continue
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+#[async_trait]
+impl<E: SqliteEntity> CRUDRepository<E> for SqliteRepo<E> {
+    type Error = RepositoryError;
+
+    async fn count(&self, condition: &Condition) -> Result<u64, Self::Error> {
+        let mut sql = Sql::new(&format!(
+            "SELECT count(*) AS count FROM {} WHERE ",
+            quote_ident(E::TABLE)
+        ));
+        sql.push(SqliteConditionHandler::transfer_condition(condition));
+        let records = self.db.query(sql).await?;
+        Ok(records[0].integer("count")? as u64)
+    }
+
+    async fn exist(&self, condition: &Condition) -> Result<bool, Self::Error> {
+        let mut sql = Sql::new(&format!(
+            "SELECT EXISTS (SELECT 1 FROM {} WHERE ",
+            quote_ident(E::TABLE)
+        ));
+        sql.push(SqliteConditionHandler::transfer_condition(condition))
+            .push_str(") AS found");
+        let records = self.db.query(sql).await?;
+        Ok(records[0].integer("found")? != 0)
+    }
+
+    async fn find_one(&self, condition: &Condition) -> Result<E, Self::Error> {
+        let mut sql = Self::select();
+        sql.push_str(" WHERE ")
+            .push(SqliteConditionHandler::transfer_condition(condition))
+            .push_str(" LIMIT 1");
+        let records = self.db.query(sql).await?;
+        let record = records.first().ok_or(RepositoryError::DataNotFound)?;
+        E::from_record(record)
+    }
+
+    async fn find(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+    ) -> Result<Vec<E>, Self::Error> {
+        let mut sql = Self::select();
+        sql.push_str(" WHERE ")
+            .push(SqliteConditionHandler::transfer_condition(condition))
+            .push_str(" ORDER BY ")
+            .push(SqliteConditionHandler::transfer_sort(sorts));
+        Self::to_entities(self.db.query(sql).await?)
+    }
+
+    async fn create(&self, data: &E) -> Result<String, Self::Error> {
+        let id = ObjectId::new().to_hex();
+        let mut sql = Sql::new(&format!(
+            "INSERT INTO {} (id, {}) VALUES (",
+            quote_ident(E::TABLE),
+            E::COLUMNS.join(", ")
+        ));
+        sql.push_param(Value::Text(id.clone()));
+        for param in data.params()? {
+            sql.push_str(", ").push_param(param);
+        }
+        sql.push_str(")");
+        self.db.execute(sql).await?;
+        Ok(id)
+    }
+
+    async fn update(&self, data: &E) -> Result<i64, Self::Error> {
+        if data.id().is_empty() {
+            return Err(RepositoryError::DataNotFound);
+        }
+        let mut sql = Sql::new(&format!(
+            "UPDATE {} SET version = version + 1",
+            quote_ident(E::TABLE)
+        ));
+        for (column, param) in E::COLUMNS.iter().zip(data.params()?) {
+            sql.push_str(&format!(", {} = ", column)).push_param(param);
+        }
+        sql.push_str(" WHERE id = ")
+            .push_param(Value::Text(data.id().to_string()))
+            .push_str(" AND version = ")
+            .push_param(Value::Integer(data.version()));
+        if self.db.execute(sql).await? == 1 {
+            return Ok(data.version() + 1);
+        }
+        let exist = self
+            .exist(&Condition::single(
+                String::from("_id"),
+                Operate::Eq,
+                ConditionValue::StringValue(data.id().to_string()),
+            ))
+            .await?;
+        if exist {
+            Err(RepositoryError::VersionConflict)
+        } else {
+            Err(RepositoryError::DataNotFound)
+        }
+    }
+
+    /// 和MongoDB的find_one_and_update一样，只更新第一条匹配的数据
+    async fn patch(&self, condition: &Condition, patch: &Patch) -> Result<E, Self::Error> {
+        let table = quote_ident(E::TABLE);
+        let mut sql = Sql::new(&format!("UPDATE {} SET ", table));
+        sql.push(patch_to_sql(patch))
+            .push_str(&format!(" WHERE id IN (SELECT id FROM {} WHERE ", table))
+            .push(SqliteConditionHandler::transfer_condition(condition))
+            .push_str(" LIMIT 1) RETURNING *");
+        let records = self.db.query(sql).await?;
+        let record = records.first().ok_or(RepositoryError::DataNotFound)?;
+        E::from_record(record)
+    }
+
+    /// 和MongoDB的delete_one一样，只删除第一条匹配的数据
+    async fn delete(&self, condition: &Condition) -> Result<bool, Self::Error> {
+        let table = quote_ident(E::TABLE);
+        let mut sql = Sql::new(&format!(
+            "DELETE FROM {0} WHERE id IN (SELECT id FROM {0} WHERE ",
+            table
+        ));
+        sql.push(SqliteConditionHandler::transfer_condition(condition))
+            .push_str(" LIMIT 1)");
+        let result = self.db.execute(sql).await?;
+        Ok(result == 1)
+    }
+
+    /// 每INSERT_BATCH_ROWS行一条INSERT语句，需要全部成功或全部失败时在事务中调用
+    async fn create_many(&self, datas: &[E]) -> Result<Vec<String>, Self::Error> {
+        let mut ids = Vec::with_capacity(datas.len());
+        for chunk in datas.chunks(INSERT_BATCH_ROWS) {
+            let mut sql = Sql::new(&format!(
+                "INSERT INTO {} (id, {}) VALUES ",
+                quote_ident(E::TABLE),
+                E::COLUMNS.join(", ")
+            ));
+            for (i, data) in chunk.iter().enumerate() {
+                let id = ObjectId::new().to_hex();
+                sql.push_str(if i == 0 { "(" } else { ", (" })
+                    .push_param(Value::Text(id.clone()));
+                for param in data.params()? {
+                    sql.push_str(", ").push_param(param);
+                }
+                sql.push_str(")");
+                ids.push(id);
+            }
+            self.db.execute(sql).await?;
+        }
+        Ok(ids)
+    }
+
+    async fn update_many(&self, condition: &Condition, patch: &Patch) -> Result<u64, Self::Error> {
+        let mut sql = Sql::new(&format!("UPDATE {} SET ", quote_ident(E::TABLE)));
+        sql.push(patch_to_sql(patch))
+            .push_str(" WHERE ")
+            .push(SqliteConditionHandler::transfer_condition(condition));
+        self.db.execute(sql).await
+    }
+
+    async fn delete_many(&self, condition: &Condition) -> Result<u64, Self::Error> {
+        let mut sql = Sql::new(&format!("DELETE FROM {} WHERE ", quote_ident(E::TABLE)));
+        sql.push(SqliteConditionHandler::transfer_condition(condition));
+        self.db.execute(sql).await
+    }
+}
+
+#[async_trait]
+impl<E: SqliteEntity> PaginationRepository<E> for SqliteRepo<E> {
+    async fn find_page(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+        page_setting: &PageOption,
+        is_count_all: bool,
+    ) -> Result<PageResult<E>, Self::Error> {
+        let mut sql = Self::select();
+        sql.push_str(" WHERE ")
+            .push(SqliteConditionHandler::transfer_condition(condition))
+            .push_str(" ORDER BY ")
+            .push(SqliteConditionHandler::transfer_sort(sorts))
+            .push_str(" ")
+            .push(SqliteConditionHandler::transfer_page_options(page_setting));
+        let datas = Self::to_entities(self.db.query(sql).await?)?;
+        let count = if is_count_all {
+            self.count(condition).await? as i64
+        } else {
+            datas.len() as i64
+        };
+        Ok(PageResult { datas, count })
+    }
+
+    async fn find_after(
+        &self,
+        condition: &Condition,
+        sorts: &[SortOption],
+        cursor_option: &CursorOption,
+    ) -> Result<CursorResult<E>, Self::Error> {
+        let keys = keyset_sorts(sorts);
+        let after = match &cursor_option.after {
+            Some(cursor) => cursor.condition(),
+            None => Condition::Empty,
+        };
+        let condition = Condition::all(vec![condition.clone(), after]);
+        // 排序字段的值单独查出来，放在实体列之后，用来生成下一页的游标
+        let mut sql = Sql::new("SELECT *");
+        for (i, key) in keys.iter().enumerate() {
+            sql.push_str(", ")
+                .push(FieldPath::parse(&key.field).expr())
+                .push_str(&format!(" AS __cursor_{}", i));
+        }
+        sql.push_str(&format!(" FROM {} WHERE ", quote_ident(E::TABLE)))
+            .push(SqliteConditionHandler::transfer_condition(&condition))
+            .push_str(" ORDER BY ")
+            .push(SqliteConditionHandler::transfer_sort(&keys))
+            .push_str(" LIMIT ")
+            .push_param(Value::Integer(cursor_option.size as i64 + 1));
+        let mut records = self.db.query(sql).await?;
+        // 多出一条时才有下一页
+        let next_cursor = if records.len() > cursor_option.size {
+            records.truncate(cursor_option.size);
+            records.last().map(|record| {
+                let offset = record.len() - keys.len();
+                Cursor {
+                    keys: keys
+                        .iter()
+                        .enumerate()
+                        .map(|(i, key)| CursorKey {
+                            field: key.field.clone(),
+                            order: key.order.clone(),
+                            value: column_value(record, offset + i),
+                        })
+                        .collect(),
+                }
+            })
+        } else {
+            None
+        };
+        Ok(CursorResult {
+            datas: Self::to_entities(records)?,
+            next_cursor,
+        })
+    }
+}
@@ -0,0 +1,925 @@
+//! 和具体存储无关的Repo测试，每个用例都在内存、SQLite、MongoDB和PostgreSQL上执行
+//!
+//! MongoDB和PostgreSQL的用例默认忽略，设置了MONGODB_URI或POSTGRES_URI后
+//! 用`cargo test -- --ignored`执行，每个用例使用单独的数据库或schema
+
+use std::str::FromStr;
+
+use chrono::{Duration, TimeZone, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde_json::{json, Value};
+
+use crate::entity::{self, Role};
+
+use super::{
+    condition::*, mongodb::MongoDB, postgres::Postgres, sqlite::Sqlite, CRUDRepository,
+    PaginationRepository, RepositoryError, Storage,
+};
+
+/// 每个用例生成内存、SQLite、MongoDB和PostgreSQL四个测试
+macro_rules! backend_tests {
+    ($($name:ident),* $(,)?) => {
+        mod memory_db {
+            use crate::repository::memory::MemoryDB;
+
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    super::$name(MemoryDB::new()).await;
+                }
+            )*
+        }
+
+        mod sqlite_db {
+            $(
+                #[tokio::test]
+                async fn $name() {
+                    let db = super::TempSqlite::open().await;
+                    super::$name(db.storage.clone()).await;
+                }
+            )*
+        }
+
+        mod mongodb_db {
+            $(
+                #[tokio::test]
+                #[ignore = "requires MONGODB_URI"]
+                async fn $name() {
+                    if let Some(db) = super::TempMongo::open().await {
+                        super::$name(db.storage.clone()).await;
+                        db.close().await;
+                    }
+                }
+            )*
+        }
+
+        mod postgres_db {
+            $(
+                #[tokio::test]
+                #[ignore = "requires POSTGRES_URI"]
+                async fn $name() {
+                    if let Some(db) = super::TempPostgres::open().await {
+                        super::$name(db.storage.clone()).await;
+                        db.close().await;
+                    }
+                }
+            )*
+        }
+    };
+}
+
+backend_tests!(
+    operates,
+    nested_conditions,
+    sorting,
+    find_page,
+    find_after,
+    version_conflict,
+    create_many,
+    update_many,
+    rename_and_unset,
+    delete_many,
+    unique_keys,
+    datetimes,
+    display_name,
+);
+
+/// 临时文件中的SQLite数据库，测试结束后删除文件
+struct TempSqlite {
+    path: String,
+    storage: Sqlite,
+}
+
+impl TempSqlite {
+    async fn open() -> Self {
+        let path = std::env::temp_dir()
+            .join(format!("crud-toy-test-{}.db", ObjectId::new().to_hex()))
+            .to_string_lossy()
+            .into_owned();
+        let storage = Sqlite::init(&path).await.unwrap();
+        TempSqlite { path, storage }
+    }
+}
+
+impl Drop for TempSqlite {
+    fn drop(&mut self) {
+        for suffix in ["", "-wal", "-shm", "-journal"].iter() {
+            let _ = std::fs::remove_file(format!("{}{}", self.path, suffix));
+        }
+    }
+}
+
+/// 环境变量中的数据库地址，没有设置时跳过用例
+fn env_uri(name: &str) -> Option<String> {
+    let uri = std::env::var(name).ok();
+    if uri.is_none() {
+        eprintln!("{} is not set, skipped", name);
+    }
+    uri
+}
+
+/// 测试数据库和schema的名字，每个用例不同
+fn temp_name() -> String {
+    format!("crud_toy_test_{}", ObjectId::new().to_hex())
+}
+
+/// 单独的MongoDB数据库，用例结束后删除
+///
+/// 事务需要副本集，MONGODB_URI要指向副本集或分片集群
+struct TempMongo {
+    storage: MongoDB,
+}
+
+impl TempMongo {
+    async fn open() -> Option<Self> {
+        let uri = env_uri("MONGODB_URI")?;
+        let storage = MongoDB::init_database(&uri, &temp_name()).await.unwrap();
+        Some(TempMongo { storage })
+    }
+
+    /// 用例失败时不会执行，数据库留给排查问题
+    async fn close(self) {
+        self.storage.drop_database().await.unwrap();
+    }
+}
+
+/// 单独的PostgreSQL schema，连接时通过search_path使用它，用例结束后删除
+struct TempPostgres {
+    admin: tokio_postgres::Client,
+    schema: String,
+    storage: Postgres,
+}
+
+impl TempPostgres {
+    async fn open() -> Option<Self> {
+        let uri = env_uri("POSTGRES_URI")?;
+        let (admin, connection) = tokio_postgres::connect(&uri, tokio_postgres::NoTls)
+            .await
+            .unwrap();
+        tokio::spawn(connection);
+        let schema = temp_name();
+        admin
+            .batch_execute(&format!("CREATE SCHEMA {}", schema))
+            .await
+            .unwrap();
+        let separator = if uri.contains('?') { '&' } else { '?' };
+        let uri = format!("{}{}options=-c%20search_path%3D{}", uri, separator, schema);
+        let storage = Postgres::init(&uri).await.unwrap();
+        Some(TempPostgres {
+            admin,
+            schema,
+            storage,
+        })
+    }
+
+    /// 用例失败时不会执行，schema留给排查问题
+    async fn close(self) {
+        self.admin
+            .batch_execute(&format!("DROP SCHEMA {} CASCADE", self.schema))
+            .await
+            .unwrap();
+    }
+}
+
+const TABLE: &str = "t1";
+const OTHER_TABLE: &str = "t2";
+
+fn row(table_id: &str, data: Value) -> entity::Row {
+    entity::Row {
+        id: String::new(),
+        table_id: table_id.to_string(),
+        data: data.as_object().unwrap().clone(),
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        version: 0,
+    }
+}
+
+/// 创建测试数据，返回的id和下面的顺序一致，alphabet没有score
+async fn seed<S: Storage>(db: &S) -> Vec<String> {
+    let rows = [
+        row(
+            TABLE,
+            json!({"name": "alpha", "group": "a", "n": 1, "score": 10}),
+        ),
+        row(
+            TABLE,
+            json!({"name": "beta", "group": "b", "n": 2, "score": 20}),
+        ),
+        row(
+            TABLE,
+            json!({"name": "gamma", "group": "a", "n": 3, "score": 30}),
+        ),
+        row(TABLE, json!({"name": "alphabet", "group": "b", "n": 4})),
+        row(
+            OTHER_TABLE,
+            json!({"name": "alpha", "group": "a", "n": 5, "score": 50}),
+        ),
+    ];
+    db.row_repo().create_many(&rows).await.unwrap()
+}
+
+fn cond(field: &str, operate: Operate, value: ConditionValue) -> Condition {
+    Condition::single(field.to_string(), operate, value)
+}
+
+fn text(value: &str) -> ConditionValue {
+    ConditionValue::StringValue(value.to_string())
+}
+
+fn texts(values: &[&str]) -> ConditionValue {
+    ConditionValue::StringVecValue(values.iter().map(|v| v.to_string()).collect())
+}
+
+fn in_table(condition: Condition) -> Condition {
+    Condition::all(vec![cond("tableId", Operate::Eq, text(TABLE)), condition])
+}
+
+fn oid(id: &str) -> ObjectId {
+    ObjectId::from_str(id).unwrap()
+}
+
+fn name(row: &entity::Row) -> String {
+    row.data["name"].as_str().unwrap().to_string()
+}
+
+fn names(rows: &[entity::Row]) -> Vec<String> {
+    rows.iter().map(name).collect()
+}
+
+/// 表中满足条件的行的name，按name排序
+async fn find_names<S: Storage>(db: &S, condition: Condition) -> Vec<String> {
+    let sorts = [SortOption::new("data.name", SortOrder::Asc)];
+    let rows = db
+        .row_repo()
+        .find(&in_table(condition), &sorts)
+        .await
+        .unwrap();
+    names(&rows)
+}
+
+async fn sorted_names<S: Storage>(db: &S, sorts: &[SortOption]) -> Vec<String> {
+    let rows = db
+        .row_repo()
+        .find(&in_table(Condition::Empty), sorts)
+        .await
+        .unwrap();
+    names(&rows)
+}
+
+async fn operates<S: Storage>(db: S) {
+    let ids = seed(&db).await;
+    let cases = [
+        (cond("data.name", Operate::Eq, text("alpha")), vec!["alpha"]),
+        (
+            cond("data.name", Operate::Ne, text("alpha")),
+            vec!["alphabet", "beta", "gamma"],
+        ),
+        (
+            cond("data.n", Operate::Lt, ConditionValue::Int64Value(2)),
+            vec!["alpha"],
+        ),
+        (
+            cond("data.n", Operate::Le, ConditionValue::Int64Value(2)),
+            vec!["alpha", "beta"],
+        ),
+        (
+            cond("data.n", Operate::Gt, ConditionValue::Int32Value(3)),
+            vec!["alphabet"],
+        ),
+        (
+            cond("data.n", Operate::Ge, ConditionValue::Int32Value(3)),
+            vec!["alphabet", "gamma"],
+        ),
+        (
+            cond("data.score", Operate::Gt, ConditionValue::DoubleValue(15.0)),
+            vec!["beta", "gamma"],
+        ),
+        (
+            cond(
+                "data.name",
+                Operate::In,
+                texts(&["alpha", "gamma", "delta"]),
+            ),
+            vec!["alpha", "gamma"],
+        ),
+        (
+            cond(
+                "data.n",
+                Operate::In,
+                ConditionValue::Int64VecValue(vec![2, 4]),
+            ),
+            vec!["alphabet", "beta"],
+        ),
+        (
+            cond("data.n", Operate::In, ConditionValue::Int64VecValue(vec![])),
+            vec![],
+        ),
+        (
+            cond("data.name", Operate::Contains, text("alpha")),
+            vec!["alpha", "alphabet"],
+        ),
+        (
+            cond("data.score", Operate::Eq, ConditionValue::NullValue),
+            vec!["alphabet"],
+        ),
+        (
+            cond("data.score", Operate::Ne, ConditionValue::NullValue),
+            vec!["alpha", "beta", "gamma"],
+        ),
+        (
+            cond(
+                "_id",
+                Operate::In,
+                ConditionValue::ObjectIdVecValue(vec![oid(&ids[0]), oid(&ids[2])]),
+            ),
+            vec!["alpha", "gamma"],
+        ),
+        (
+            cond(
+                "_id",
+                Operate::Eq,
+                ConditionValue::ObjectIdValue(oid(&ids[1])),
+            ),
+            vec!["beta"],
+        ),
+        (
+            cond("version", Operate::Eq, ConditionValue::Int64Value(0)),
+            vec!["alpha", "alphabet", "beta", "gamma"],
+        ),
+    ];
+    for (condition, expected) in cases.iter() {
+        let found = find_names(&db, condition.clone()).await;
+        assert_eq!(found, *expected, "condition: {:?}", condition);
+    }
+
+    let repo = db.row_repo();
+    let other = cond("tableId", Operate::Eq, text(OTHER_TABLE));
+    assert_eq!(repo.count(&other).await.unwrap(), 1);
+    assert!(repo.exist(&other).await.unwrap());
+    let missing = cond("tableId", Operate::Eq, text("t3"));
+    assert!(!repo.exist(&missing).await.unwrap());
+    assert!(matches!(
+        repo.find_one(&missing).await,
+        Err(RepositoryError::DataNotFound)
+    ));
+}
+
+async fn nested_conditions<S: Storage>(db: S) {
+    seed(&db).await;
+    let cases = [
+        (
+            Condition::or(vec![
+                (String::from("data.name"), Operate::Eq, text("alpha")),
+                (
+                    String::from("data.n"),
+                    Operate::Ge,
+                    ConditionValue::Int64Value(4),
+                ),
+            ]),
+            vec!["alpha", "alphabet"],
+        ),
+        (
+            Condition::nor(vec![(String::from("data.group"), Operate::Eq, text("a"))]),
+            vec!["alphabet", "beta"],
+        ),
+        // 或中嵌套且
+        (
+            Condition::Complex {
+                and: Box::new(vec![]),
+                or: Box::new(vec![
+                    Condition::and(vec![
+                        (String::from("data.group"), Operate::Eq, text("a")),
+                        (
+                            String::from("data.n"),
+                            Operate::Gt,
+                            ConditionValue::Int64Value(1),
+                        ),
+                    ]),
+                    Condition::and(vec![
+                        (String::from("data.group"), Operate::Eq, text("b")),
+                        (
+                            String::from("data.n"),
+                            Operate::Lt,
+                            ConditionValue::Int64Value(3),
+                        ),
+                    ]),
+                ]),
+                nor: Box::new(vec![]),
+            },
+            vec!["beta", "gamma"],
+        ),
+        // 非中嵌套或
+        (
+            Condition::Complex {
+                and: Box::new(vec![cond(
+                    "data.n",
+                    Operate::Ge,
+                    ConditionValue::Int64Value(2),
+                )]),
+                or: Box::new(vec![]),
+                nor: Box::new(vec![Condition::or(vec![
+                    (String::from("data.name"), Operate::Eq, text("beta")),
+                    (String::from("data.name"), Operate::Eq, text("gamma")),
+                ])]),
+            },
+            vec!["alphabet"],
+        ),
+        // 且、或、非同时出现
+        (
+            Condition::Complex {
+                and: Box::new(vec![cond("data.group", Operate::Eq, text("b"))]),
+                or: Box::new(vec![
+                    cond("data.n", Operate::Eq, ConditionValue::Int64Value(2)),
+                    cond("data.n", Operate::Eq, ConditionValue::Int64Value(4)),
+                ]),
+                nor: Box::new(vec![cond("data.name", Operate::Eq, text("beta"))]),
+            },
+            vec!["alphabet"],
+        ),
+        (
+            Condition::all(vec![Condition::Empty, Condition::Empty]),
+            vec!["alpha", "alphabet", "beta", "gamma"],
+        ),
+    ];
+    for (condition, expected) in cases.iter() {
+        let found = find_names(&db, condition.clone()).await;
+        assert_eq!(found, *expected, "condition: {:?}", condition);
+    }
+}
+
+async fn sorting<S: Storage>(db: S) {
+    seed(&db).await;
+    let cases = [
+        (
+            vec![SortOption::new("data.name", SortOrder::Asc)],
+            vec!["alpha", "alphabet", "beta", "gamma"],
+        ),
+        (
+            vec![SortOption::new("data.n", SortOrder::Desc)],
+            vec!["alphabet", "gamma", "beta", "alpha"],
+        ),
+        (
+            vec![
+                SortOption::new("data.group", SortOrder::Asc),
+                SortOption::new("data.n", SortOrder::Desc),
+            ],
+            vec!["gamma", "alpha", "alphabet", "beta"],
+        ),
+        // null升序时在最前，降序时在最后
+        (
+            vec![SortOption::new("data.score", SortOrder::Asc)],
+            vec!["alphabet", "alpha", "beta", "gamma"],
+        ),
+        (
+            vec![SortOption::new("data.score", SortOrder::Desc)],
+            vec!["gamma", "beta", "alpha", "alphabet"],
+        ),
+    ];
+    for (sorts, expected) in cases.iter() {
+        assert_eq!(
+            sorted_names(&db, sorts).await,
+            *expected,
+            "sorts: {:?}",
+            sorts
+        );
+    }
+}
+
+async fn find_page<S: Storage>(db: S) {
+    seed(&db).await;
+    let repo = db.row_repo();
+    let sorts = [SortOption::new("data.n", SortOrder::Asc)];
+    let page = |page, size| PageOption { page, size };
+
+    let first = repo
+        .find_page(&in_table(Condition::Empty), &sorts, &page(1, 3), true)
+        .await
+        .unwrap();
+    assert_eq!(names(&first.datas), vec!["alpha", "beta", "gamma"]);
+    assert_eq!(first.count, 4);
+
+    let second = repo
+        .find_page(&in_table(Condition::Empty), &sorts, &page(2, 3), true)
+        .await
+        .unwrap();
+    assert_eq!(names(&second.datas), vec!["alphabet"]);
+    assert_eq!(second.count, 4);
+
+    let uncounted = repo
+        .find_page(&in_table(Condition::Empty), &sorts, &page(2, 3), false)
+        .await
+        .unwrap();
+    assert_eq!(uncounted.count, 1);
+
+    // 页码从1开始，0按第一页处理
+    let zero = repo
+        .find_page(&in_table(Condition::Empty), &sorts, &page(0, 2), true)
+        .await
+        .unwrap();
+    assert_eq!(names(&zero.datas), vec!["alpha", "beta"]);
+
+    let beyond = repo
+        .find_page(&in_table(Condition::Empty), &sorts, &page(3, 3), true)
+        .await
+        .unwrap();
+    assert!(beyond.datas.is_empty());
+    assert_eq!(beyond.count, 4);
+
+    let filtered = repo
+        .find_page(
+            &in_table(cond("data.group", Operate::Eq, text("b"))),
+            &sorts,
+            &page(1, 1),
+            true,
+        )
+        .await
+        .unwrap();
+    assert_eq!(names(&filtered.datas), vec!["beta"]);
+    assert_eq!(filtered.count, 2);
+}
+
+/// 用游标一页一页取完，返回每一页的name
+async fn drain_pages<S: Storage>(
+    db: &S,
+    condition: &Condition,
+    sorts: &[SortOption],
+    size: usize,
+) -> Vec<Vec<String>> {
+    let repo = db.row_repo();
+    let mut pages = vec![];
+    let mut after = None;
+    loop {
+        let option = CursorOption { after, size };
+        let result = repo.find_after(condition, sorts, &option).await.unwrap();
+        assert!(result.datas.len() <= size);
+        pages.push(names(&result.datas));
+        match result.next_cursor {
+            Some(cursor) => {
+                assert!(cursor.matches(&keyset_sorts(sorts)));
+                after = Some(cursor);
+            }
+            None => break,
+        }
+        assert!(pages.len() <= 5, "cursor does not advance");
+    }
+    pages
+}
+
+async fn find_after<S: Storage>(db: S) {
+    seed(&db).await;
+    let all = in_table(Condition::Empty);
+    let cases = [
+        (
+            vec![
+                SortOption::new("data.group", SortOrder::Asc),
+                SortOption::new("data.n", SortOrder::Desc),
+            ],
+            2,
+        ),
+        (vec![SortOption::new("data.score", SortOrder::Asc)], 1),
+        (vec![SortOption::new("data.score", SortOrder::Desc)], 1),
+        (vec![SortOption::new("data.name", SortOrder::Desc)], 3),
+        (vec![], 1),
+    ];
+    for (sorts, size) in cases.iter() {
+        let pages = drain_pages(&db, &all, sorts, *size).await;
+        let expected = sorted_names(&db, &keyset_sorts(sorts)).await;
+        assert_eq!(pages.concat(), expected, "sorts: {:?}", sorts);
+        assert_eq!(pages.len(), expected.chunks(*size).count());
+    }
+
+    let filtered = in_table(cond("data.n", Operate::Ge, ConditionValue::Int64Value(2)));
+    let sorts = [SortOption::new("data.n", SortOrder::Asc)];
+    assert_eq!(
+        drain_pages(&db, &filtered, &sorts, 2).await,
+        vec![vec!["beta", "gamma"], vec!["alphabet"]]
+    );
+
+    // 游标之后没有数据时返回空页
+    let empty = in_table(cond("data.name", Operate::Eq, text("delta")));
+    assert_eq!(
+        drain_pages(&db, &empty, &sorts, 2).await,
+        vec![Vec::<String>::new()]
+    );
+}
+
+async fn version_conflict<S: Storage>(db: S) {
+    let repo = db.row_repo();
+    let id = repo
+        .create(&row(TABLE, json!({"name": "alpha"})))
+        .await
+        .unwrap();
+    let by_id = cond("_id", Operate::Eq, ConditionValue::ObjectIdValue(oid(&id)));
+
+    let mut first = repo.find_one(&by_id).await.unwrap();
+    let mut stale = repo.find_one(&by_id).await.unwrap();
+    assert_eq!(first.version, 0);
+    first.data.insert(String::from("name"), json!("beta"));
+    assert_eq!(repo.update(&first).await.unwrap(), 1);
+
+    stale.data.insert(String::from("name"), json!("gamma"));
+    assert!(matches!(
+        repo.update(&stale).await,
+        Err(RepositoryError::VersionConflict)
+    ));
+    let current = repo.find_one(&by_id).await.unwrap();
+    assert_eq!(name(&current), "beta");
+    assert_eq!(current.version, 1);
+
+    assert_eq!(repo.update(&current).await.unwrap(), 2);
+
+    // patch也会增加版本号
+    let patched = repo
+        .patch(&by_id, &Patch::new().set("data.name", text("delta")))
+        .await
+        .unwrap();
+    assert_eq!(name(&patched), "delta");
+    assert_eq!(patched.version, 3);
+    assert!(matches!(
+        repo.update(&current).await,
+        Err(RepositoryError::VersionConflict)
+    ));
+
+    let mut missing = repo.find_one(&by_id).await.unwrap();
+    missing.id = ObjectId::new().to_hex();
+    assert!(matches!(
+        repo.update(&missing).await,
+        Err(RepositoryError::DataNotFound)
+    ));
+    let nothing = cond("data.name", Operate::Eq, text("nothing"));
+    assert!(matches!(
+        repo.patch(&nothing, &Patch::new().set("data.name", text("x")))
+            .await,
+        Err(RepositoryError::DataNotFound)
+    ));
+}
+
+async fn create_many<S: Storage>(db: S) {
+    let ids = seed(&db).await;
+    assert_eq!(ids.len(), 5);
+    let mut unique = ids.clone();
+    unique.sort();
+    unique.dedup();
+    assert_eq!(unique.len(), 5);
+
+    // 返回的id和传入的顺序一致
+    let repo = db.row_repo();
+    for (id, expected) in ids
+        .iter()
+        .zip(&["alpha", "beta", "gamma", "alphabet", "alpha"])
+    {
+        let by_id = cond("_id", Operate::Eq, ConditionValue::ObjectIdValue(oid(id)));
+        assert_eq!(name(&repo.find_one(&by_id).await.unwrap()), *expected);
+    }
+
+    assert!(repo.create_many(&[]).await.unwrap().is_empty());
+    assert_eq!(repo.count(&Condition::Empty).await.unwrap(), 5);
+}
+
+async fn update_many<S: Storage>(db: S) {
+    seed(&db).await;
+    let repo = db.row_repo();
+    let group_a = in_table(cond("data.group", Operate::Eq, text("a")));
+    let patch = Patch::new()
+        .set("data.level", ConditionValue::Int64Value(9))
+        .unset("data.score");
+    assert_eq!(repo.update_many(&group_a, &patch).await.unwrap(), 2);
+
+    assert_eq!(
+        find_names(
+            &db,
+            cond("data.level", Operate::Eq, ConditionValue::Int64Value(9))
+        )
+        .await,
+        vec!["alpha", "gamma"]
+    );
+    assert_eq!(
+        find_names(
+            &db,
+            cond("data.score", Operate::Eq, ConditionValue::NullValue)
+        )
+        .await,
+        vec!["alpha", "alphabet", "gamma"]
+    );
+    // 其他字段和其他表不受影响
+    assert_eq!(
+        find_names(&db, cond("data.group", Operate::Eq, text("a"))).await,
+        vec!["alpha", "gamma"]
+    );
+    let other = Condition::and(vec![
+        (String::from("tableId"), Operate::Eq, text(OTHER_TABLE)),
+        (
+            String::from("data.score"),
+            Operate::Eq,
+            ConditionValue::Int64Value(50),
+        ),
+    ]);
+    assert_eq!(repo.count(&other).await.unwrap(), 1);
+
+    // 更新的数据版本号加1
+    assert_eq!(
+        find_names(
+            &db,
+            cond("version", Operate::Eq, ConditionValue::Int64Value(1))
+        )
+        .await,
+        vec!["alpha", "gamma"]
+    );
+
+    let nothing = in_table(cond("data.name", Operate::Eq, text("nothing")));
+    assert_eq!(repo.update_many(&nothing, &patch).await.unwrap(), 0);
+}
+
+async fn rename_and_unset<S: Storage>(db: S) {
+    seed(&db).await;
+    let repo = db.row_repo();
+    let patch = Patch::new().rename("data.score", "data.points");
+    assert_eq!(
+        repo.update_many(&in_table(Condition::Empty), &patch)
+            .await
+            .unwrap(),
+        4
+    );
+
+    assert_eq!(
+        find_names(
+            &db,
+            cond("data.points", Operate::Gt, ConditionValue::Int64Value(15))
+        )
+        .await,
+        vec!["beta", "gamma"]
+    );
+    assert_eq!(
+        find_names(
+            &db,
+            cond("data.score", Operate::Ne, ConditionValue::NullValue)
+        )
+        .await,
+        Vec::<String>::new()
+    );
+    // 原字段不存在时什么都不做
+    assert_eq!(
+        find_names(
+            &db,
+            cond("data.points", Operate::Eq, ConditionValue::NullValue)
+        )
+        .await,
+        vec!["alphabet"]
+    );
+
+    let alpha = in_table(cond("data.name", Operate::Eq, text("alpha")));
+    let patched = repo
+        .patch(&alpha, &Patch::new().unset("data.points"))
+        .await
+        .unwrap();
+    assert!(!patched.data.contains_key("points"));
+    assert_eq!(patched.data["n"], json!(1));
+}
+
+async fn delete_many<S: Storage>(db: S) {
+    seed(&db).await;
+    let repo = db.row_repo();
+    let group_b = in_table(cond("data.group", Operate::Eq, text("b")));
+    assert_eq!(repo.delete_many(&group_b).await.unwrap(), 2);
+    assert_eq!(repo.delete_many(&group_b).await.unwrap(), 0);
+    assert_eq!(
+        sorted_names(&db, &[SortOption::new("data.name", SortOrder::Asc)]).await,
+        vec!["alpha", "gamma"]
+    );
+    let other = cond("tableId", Operate::Eq, text(OTHER_TABLE));
+    assert_eq!(repo.count(&other).await.unwrap(), 1);
+
+    // delete只删除第一条
+    assert!(repo.delete(&in_table(Condition::Empty)).await.unwrap());
+    assert_eq!(repo.count(&in_table(Condition::Empty)).await.unwrap(), 1);
+    assert_eq!(repo.delete_many(&Condition::Empty).await.unwrap(), 2);
+    assert!(!repo.delete(&Condition::Empty).await.unwrap());
+}
+
+fn user(username: &str) -> entity::User {
+    entity::User {
+        id: String::new(),
+        username: username.to_string(),
+        display_name: None,
+        password_hash: String::from("hash"),
+        version: 0,
+    }
+}
+
+fn membership(workspace_id: &str, user_id: &str) -> entity::Membership {
+    entity::Membership {
+        id: String::new(),
+        workspace_id: workspace_id.to_string(),
+        user_id: user_id.to_string(),
+        role: Role::Viewer,
+        created_at: Utc::now(),
+        updated_at: Utc::now(),
+        version: 0,
+    }
+}
+
+async fn unique_keys<S: Storage>(db: S) {
+    let users = db.user_repo();
+    users.create(&user("alice")).await.unwrap();
+    assert!(matches!(
+        users.create(&user("alice")).await,
+        Err(RepositoryError::Conflict(_))
+    ));
+
+    // 批量创建时有重复的都不创建
+    assert!(matches!(
+        users.create_many(&[user("bob"), user("bob")]).await,
+        Err(RepositoryError::Conflict(_))
+    ));
+    let bob = cond("username", Operate::Eq, text("bob"));
+    assert_eq!(users.count(&bob).await.unwrap(), 0);
+
+    users.create(&user("carol")).await.unwrap();
+    let carol = cond("username", Operate::Eq, text("carol"));
+    assert!(matches!(
+        users
+            .patch(&carol, &Patch::new().set("username", text("alice")))
+            .await,
+        Err(RepositoryError::Conflict(_))
+    ));
+    let mut renamed = users.find_one(&carol).await.unwrap();
+    renamed.username = String::from("alice");
+    assert!(matches!(
+        users.update(&renamed).await,
+        Err(RepositoryError::Conflict(_))
+    ));
+    assert_eq!(users.count(&carol).await.unwrap(), 1);
+
+    let memberships = db.membership_repo();
+    memberships.create(&membership("w1", "u1")).await.unwrap();
+    memberships.create(&membership("w1", "u2")).await.unwrap();
+    memberships.create(&membership("w2", "u1")).await.unwrap();
+    assert!(matches!(
+        memberships.create(&membership("w1", "u1")).await,
+        Err(RepositoryError::Conflict(_))
+    ));
+    let all_in_w1 = cond("workspaceId", Operate::Eq, text("w1"));
+    assert!(matches!(
+        memberships
+            .update_many(&all_in_w1, &Patch::new().set("userId", text("u3")))
+            .await,
+        Err(RepositoryError::Conflict(_))
+    ));
+    let u3 = cond("userId", Operate::Eq, text("u3"));
+    assert_eq!(memberships.count(&u3).await.unwrap(), 0);
+}
+
+async fn datetimes<S: Storage>(db: S) {
+    // 整秒的时间，保存的格式和查询条件不一致时按字符串比较会出错
+    let at = Utc.ymd(2021, 5, 1).and_hms(10, 0, 0);
+    let mut data = row(TABLE, json!({"name": "alpha"}));
+    data.created_at = at;
+    let repo = db.row_repo();
+    repo.create(&data).await.unwrap();
+    repo.create(&row(TABLE, json!({"name": "beta"})))
+        .await
+        .unwrap();
+
+    let created = |operate, at| cond("createdAt", operate, ConditionValue::DateTimeValue(at));
+    let later = at + Duration::milliseconds(500);
+    assert_eq!(find_names(&db, created(Operate::Eq, at)).await, ["alpha"]);
+    assert_eq!(
+        find_names(&db, created(Operate::Lt, later)).await,
+        ["alpha"]
+    );
+    assert_eq!(find_names(&db, created(Operate::Gt, later)).await, ["beta"]);
+    assert_eq!(
+        repo.find_one(&created(Operate::Eq, at))
+            .await
+            .unwrap()
+            .created_at,
+        at
+    );
+}
+
+async fn display_name<S: Storage>(db: S) {
+    let users = db.user_repo();
+    let mut alice = user("alice");
+    alice.display_name = Some(String::from("Alice"));
+    let alice_id = users.create(&alice).await.unwrap();
+    let bob_id = users.create(&user("bob")).await.unwrap();
+
+    let by_id = |id: &str| cond("_id", Operate::Eq, ConditionValue::ObjectIdValue(oid(id)));
+    let found = users.find_one(&by_id(&alice_id)).await.unwrap();
+    assert_eq!(found.display_name.as_deref(), Some("Alice"));
+    assert_eq!(
+        users.find_one(&by_id(&bob_id)).await.unwrap().display_name,
+        None
+    );
+
+    users
+        .patch(
+            &by_id(&bob_id),
+            &Patch::new().set("displayName", text("Bob")),
+        )
+        .await
+        .unwrap();
+    let found = users.find_one(&by_id(&bob_id)).await.unwrap();
+    assert_eq!(found.display_name.as_deref(), Some("Bob"));
+}
@@ -0,0 +1,94 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use warp::{Rejection, Reply};
+
+use crate::{
+    entity,
+    repository::Storage,
+    service::{api_key::ApiKeyService, auth::CurrentUser},
+};
+
+use super::{request_object::ApiKeyCreateParam, Response};
+
+/// 返回给客户端的API key信息，不包含key的哈希
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyInfo {
+    pub id: String,
+    pub name: String,
+    pub prefix: String,
+    pub read_only: bool,
+    pub workspace_id: Option<String>,
+    pub expires_at: Option<DateTime<Utc>>,
+    pub last_used_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<entity::ApiKey> for ApiKeyInfo {
+    fn from(api_key: entity::ApiKey) -> Self {
+        ApiKeyInfo {
+            id: api_key.id,
+            name: api_key.name,
+            prefix: api_key.prefix,
+            read_only: api_key.scope.read_only,
+            workspace_id: api_key.scope.workspace_id,
+            expires_at: api_key.expires_at,
+            last_used_at: api_key.last_used_at,
+            created_at: api_key.created_at,
+        }
+    }
+}
+
+/// 创建后返回的API key，key的明文只返回这一次
+#[derive(Serialize, Debug)]
+pub struct ApiKeyCreated {
+    #[serde(flatten)]
+    pub info: ApiKeyInfo,
+    pub key: String,
+}
+
+/// 创建API key
+pub async fn create_api_key<S: Storage>(
+    current_user: CurrentUser,
+    param: ApiKeyCreateParam,
+    api_key_service: ApiKeyService<S>,
+) -> Result<impl Reply, Rejection> {
+    let (api_key, key) = api_key_service
+        .create(&current_user, param.name, param.scope, param.expires_at)
+        .await?;
+    Response::<ApiKeyCreated> {
+        success: true,
+        data: ApiKeyCreated {
+            info: api_key.into(),
+            key,
+        },
+    }
+    .to_http_reply()
+}
+
+/// 获取当前用户的所有API key
+pub async fn find_all_api_key<S: Storage>(
+    current_user: CurrentUser,
+    api_key_service: ApiKeyService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = api_key_service.find_all(&current_user).await?;
+    Response::<Vec<ApiKeyInfo>> {
+        success: true,
+        data: res.into_iter().map(Into::into).collect(),
+    }
+    .to_http_reply()
+}
+
+/// 撤销API key
+pub async fn revoke_api_key<S: Storage>(
+    id: String,
+    current_user: CurrentUser,
+    api_key_service: ApiKeyService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = api_key_service.revoke(&current_user, id).await?;
+    Response::<()> {
+        success: res,
+        data: (),
+    }
+    .to_http_reply()
+}
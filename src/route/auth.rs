@@ -0,0 +1,48 @@
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use warp::{Rejection, Reply};
+
+use crate::{
+    repository::Storage,
+    service::auth::{AuthService, CurrentUser},
+};
+
+use super::{request_object::LoginParam, user::UserInfo, Response};
+
+/// 登录成功后返回的令牌
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenInfo {
+    pub token: String,
+    pub token_type: &'static str,
+    pub expires_at: DateTime<Utc>,
+}
+
+/// 用户名密码登录
+pub async fn login<S: Storage>(
+    param: LoginParam,
+    auth_service: AuthService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = auth_service.login(param.username, param.password).await?;
+    Response::<TokenInfo> {
+        success: true,
+        data: TokenInfo {
+            token: res.token,
+            token_type: "Bearer",
+            expires_at: res.expires_at,
+        },
+    }
+    .to_http_reply()
+}
+
+/// 获取当前登录的用户
+pub async fn current_user(current_user: CurrentUser) -> Result<impl Reply, Rejection> {
+    Response::<UserInfo> {
+        success: true,
+        data: UserInfo {
+            id: current_user.id,
+            username: current_user.username,
+        },
+    }
+    .to_http_reply()
+}
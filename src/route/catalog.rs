@@ -0,0 +1,155 @@
+use warp::{Rejection, Reply};
+
+use crate::{
+    entity,
+    repository::Storage,
+    service::{auth::CurrentUser, catalog::CatalogService, ServiceError},
+};
+
+use super::{
+    filter::{common_filter_fields, parse_filter, parse_sort},
+    request_object::{CatalogCreateParam, CatalogUpdateParam, DeleteQuery, ListQuery},
+    CursorPage, Page, Response,
+};
+
+/// 在工作区下创建目录
+pub async fn create_catalog<S: Storage>(
+    workspace_id: String,
+    current_user: CurrentUser,
+    param: CatalogCreateParam,
+    catalog_service: CatalogService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = catalog_service
+        .create_catalog(&current_user, workspace_id, param.name, param.description)
+        .await?;
+    Response::<String> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 获取工作区下的所有目录
+pub async fn find_all_catalog<S: Storage>(
+    workspace_id: String,
+    current_user: CurrentUser,
+    query: ListQuery,
+    catalog_service: CatalogService<S>,
+) -> Result<impl Reply, Rejection> {
+    let fields = common_filter_fields();
+    let filter = parse_filter(query.filter.as_deref().unwrap_or_default(), &fields)
+        .map_err(ServiceError::InvalidArgument)?;
+    let sorts = parse_sort(query.sort.as_deref().unwrap_or_default(), &fields)
+        .map_err(ServiceError::InvalidArgument)?;
+    if let Some(cursor_option) = query
+        .cursor_option(&sorts)
+        .map_err(ServiceError::InvalidArgument)?
+    {
+        let res = catalog_service
+            .find_catalog_after(&current_user, workspace_id, filter, &sorts, &cursor_option)
+            .await?;
+        let reply = Response::<CursorPage<entity::Catalog>> {
+            success: true,
+            data: CursorPage::new(res, &cursor_option),
+        }
+        .to_http_reply()?;
+        return Ok(reply.into_response());
+    }
+    let page_option = query.page_option().map_err(ServiceError::InvalidArgument)?;
+    let res = catalog_service
+        .find_all_catalog(&current_user, workspace_id, filter, &sorts, &page_option)
+        .await?;
+    Response::<Page<entity::Catalog>> {
+        success: true,
+        data: Page::new(res, &page_option),
+    }
+    .to_http_reply()
+    .map(Reply::into_response)
+}
+
+/// 根据id获取目录
+pub async fn get_catalog_by_id<S: Storage>(
+    workspace_id: String,
+    id: String,
+    current_user: CurrentUser,
+    catalog_service: CatalogService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = catalog_service
+        .find_by_id(&current_user, workspace_id, id)
+        .await?;
+    let version = res.version;
+    Response::<entity::Catalog> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply_with_version(version)
+}
+
+/// 更新目录的名称和描述
+pub async fn update_catalog_info<S: Storage>(
+    workspace_id: String,
+    id: String,
+    current_user: CurrentUser,
+    expected_version: Option<i64>,
+    update_param: CatalogUpdateParam,
+    catalog_service: CatalogService<S>,
+) -> Result<impl Reply, Rejection> {
+    let version = catalog_service
+        .update(
+            &current_user,
+            workspace_id,
+            id,
+            update_param.name,
+            update_param.description,
+            expected_version,
+        )
+        .await?;
+    Response::<()> {
+        success: true,
+        data: (),
+    }
+    .to_http_reply_with_version(version)
+}
+
+/// 删除目录
+pub async fn delete_catalog_by_id<S: Storage>(
+    workspace_id: String,
+    id: String,
+    current_user: CurrentUser,
+    expected_version: Option<i64>,
+    query: DeleteQuery,
+    catalog_service: CatalogService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = catalog_service
+        .delete(
+            &current_user,
+            workspace_id,
+            id,
+            expected_version,
+            query.cascade.unwrap_or(false),
+        )
+        .await?;
+    Response::<()> {
+        success: res,
+        data: (),
+    }
+    .to_http_reply()
+}
+
+/// 从回收站恢复目录，返回恢复后的目录
+pub async fn restore_catalog<S: Storage>(
+    workspace_id: String,
+    id: String,
+    current_user: CurrentUser,
+    catalog_service: CatalogService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = catalog_service
+        .restore(&current_user, workspace_id, id)
+        .await?;
+    let version = res.version;
+    Response::<entity::Catalog> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply_with_version(version)
+}
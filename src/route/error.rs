@@ -0,0 +1,214 @@
+use std::convert::Infallible;
+
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+use warp::{
+    body::BodyDeserializeError,
+    http::StatusCode,
+    reject::{
+        InvalidHeader, InvalidQuery, LengthRequired, MethodNotAllowed, MissingHeader,
+        PayloadTooLarge, UnsupportedMediaType,
+    },
+    Rejection, Reply,
+};
+
+use crate::{
+    repository,
+    service::{Dependent, FieldError, ServiceError},
+};
+
+/// 返回给客户端的错误信息
+///
+/// code是稳定的错误码，客户端按它区分错误；message只用于展示，可能会变
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct ErrorInfo<'a> {
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<&'a [FieldError]>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dependents: Option<&'a [Dependent]>,
+    request_id: String,
+}
+
+#[derive(Serialize, Debug)]
+struct ErrorResponse<'a> {
+    success: bool,
+    error: ErrorInfo<'a>,
+}
+
+/// 批量操作中单个条目的错误，错误码和单独请求时一致
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ItemError {
+    code: &'static str,
+    message: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    details: Option<Vec<FieldError>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    dependents: Option<Vec<Dependent>>,
+}
+
+impl From<&ServiceError> for ItemError {
+    fn from(err: &ServiceError) -> Self {
+        let failure = service_failure(err);
+        if failure.status.is_server_error() {
+            log::error!("bulk item {} {:?}", failure.code, err);
+        }
+        ItemError {
+            code: failure.code,
+            message: failure.message,
+            details: failure.details.map(<[FieldError]>::to_vec),
+            dependents: failure.dependents.map(<[Dependent]>::to_vec),
+        }
+    }
+}
+
+/// 一次失败请求的状态码和错误信息
+struct Failure<'a> {
+    status: StatusCode,
+    code: &'static str,
+    message: String,
+    details: Option<&'a [FieldError]>,
+    dependents: Option<&'a [Dependent]>,
+}
+
+impl<'a> Failure<'a> {
+    fn new(status: StatusCode, code: &'static str, message: impl Into<String>) -> Self {
+        Failure {
+            status,
+            code,
+            message: message.into(),
+            details: None,
+            dependents: None,
+        }
+    }
+
+    /// 服务端错误不把内部信息返回给客户端，只记录在日志中
+    fn internal() -> Self {
+        Failure::new(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "INTERNAL_ERROR",
+            "internal server error",
+        )
+    }
+}
+
+fn service_failure(err: &ServiceError) -> Failure<'_> {
+    use ServiceError::*;
+    match err {
+        InvalidArgument(msg) => Failure::new(StatusCode::BAD_REQUEST, "INVALID_ARGUMENT", msg),
+        ValidationError(errors) => Failure {
+            details: Some(errors),
+            ..Failure::new(
+                StatusCode::BAD_REQUEST,
+                "VALIDATION_FAILED",
+                "request validation failed",
+            )
+        },
+        InvalidRequest(errors) => Failure {
+            details: Some(errors),
+            ..Failure::new(
+                StatusCode::UNPROCESSABLE_ENTITY,
+                "VALIDATION_FAILED",
+                "request validation failed",
+            )
+        },
+        Unauthorized(msg) => Failure::new(StatusCode::UNAUTHORIZED, "UNAUTHORIZED", msg),
+        Forbidden(msg) => Failure::new(StatusCode::FORBIDDEN, "FORBIDDEN", msg),
+        HasDependents { dependents, .. } => Failure {
+            dependents: Some(dependents),
+            ..Failure::new(StatusCode::CONFLICT, "HAS_DEPENDENTS", err.to_string())
+        },
+        PreconditionFailed(msg) => {
+            Failure::new(StatusCode::PRECONDITION_FAILED, "PRECONDITION_FAILED", msg)
+        }
+        RepositoryError(e) => match e {
+            repository::RepositoryError::DataNotFound => {
+                Failure::new(StatusCode::NOT_FOUND, "NOT_FOUND", "resource not found")
+            }
+            repository::RepositoryError::InvalidId(_) => {
+                Failure::new(StatusCode::BAD_REQUEST, "INVALID_ID", e.to_string())
+            }
+            repository::RepositoryError::Conflict(msg) => {
+                Failure::new(StatusCode::CONFLICT, "CONFLICT", msg)
+            }
+            repository::RepositoryError::VersionConflict => Failure::new(
+                StatusCode::CONFLICT,
+                "VERSION_CONFLICT",
+                "resource was modified concurrently, please retry",
+            ),
+            repository::RepositoryError::Backend(_) => Failure::internal(),
+        },
+        InternalError(_) | IoError(_) | WarpError(_) | HttpError(_) => Failure::internal(),
+    }
+}
+
+/// warp内置的拒绝，例如请求体解析失败、请求体过大、缺少请求头
+fn rejection_failure(err: &Rejection) -> Failure<'_> {
+    if err.is_not_found() {
+        Failure::new(StatusCode::NOT_FOUND, "NOT_FOUND", "resource not found")
+    } else if let Some(e) = err.find::<ServiceError>() {
+        service_failure(e)
+    } else if let Some(e) = err.find::<BodyDeserializeError>() {
+        Failure::new(StatusCode::BAD_REQUEST, "INVALID_BODY", e.to_string())
+    } else if let Some(e) = err.find::<InvalidQuery>() {
+        Failure::new(StatusCode::BAD_REQUEST, "INVALID_QUERY", e.to_string())
+    } else if let Some(e) = err.find::<MissingHeader>() {
+        Failure::new(StatusCode::BAD_REQUEST, "MISSING_HEADER", e.to_string())
+    } else if let Some(e) = err.find::<InvalidHeader>() {
+        Failure::new(StatusCode::BAD_REQUEST, "INVALID_HEADER", e.to_string())
+    } else if let Some(e) = err.find::<PayloadTooLarge>() {
+        Failure::new(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "PAYLOAD_TOO_LARGE",
+            e.to_string(),
+        )
+    } else if let Some(e) = err.find::<LengthRequired>() {
+        Failure::new(
+            StatusCode::LENGTH_REQUIRED,
+            "LENGTH_REQUIRED",
+            e.to_string(),
+        )
+    } else if let Some(e) = err.find::<UnsupportedMediaType>() {
+        Failure::new(
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            "UNSUPPORTED_MEDIA_TYPE",
+            e.to_string(),
+        )
+    } else if let Some(e) = err.find::<MethodNotAllowed>() {
+        Failure::new(
+            StatusCode::METHOD_NOT_ALLOWED,
+            "METHOD_NOT_ALLOWED",
+            e.to_string(),
+        )
+    } else {
+        Failure::internal()
+    }
+}
+
+/// 处理warp的不成功情况
+///
+/// 每个错误生成一个请求id，同时写入日志、响应体和x-request-id响应头，方便按id查日志
+pub async fn handle_rejection(err: Rejection) -> Result<impl Reply, Infallible> {
+    let request_id = ObjectId::new().to_hex();
+    let failure = rejection_failure(&err);
+    if failure.status.is_server_error() {
+        log::error!("[{}] {} {:?}", request_id, failure.code, err);
+    } else {
+        log::info!("[{}] {} {}", request_id, failure.code, failure.message);
+    }
+    let json = warp::reply::json(&ErrorResponse {
+        success: false,
+        error: ErrorInfo {
+            code: failure.code,
+            message: failure.message,
+            details: failure.details,
+            dependents: failure.dependents,
+            request_id: request_id.clone(),
+        },
+    });
+    let reply = warp::reply::with_status(json, failure.status);
+    Ok(warp::reply::with_header(reply, "x-request-id", request_id))
+}
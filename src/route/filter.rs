@@ -0,0 +1,323 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde_json::Value;
+
+use crate::{
+    entity::{Column, ColumnType},
+    repository::condition::{
+        Condition, ConditionNode, ConditionValue, Operate, SortOption, SortOrder,
+    },
+};
+
+/// JSON格式的过滤条件最多允许嵌套的层数
+const MAX_FILTER_DEPTH: usize = 5;
+
+/// 可过滤字段的值类型
+#[derive(Clone, Debug, PartialEq)]
+pub enum FieldType {
+    String,
+    Integer,
+    Float,
+    Boolean,
+    DateTime,
+    ObjectId,
+}
+
+/// 允许过滤的字段，name是接口上的字段名，path是存储中的字段名
+#[derive(Clone, Debug)]
+pub struct FilterField {
+    pub name: String,
+    pub path: String,
+    pub field_type: FieldType,
+}
+
+impl FilterField {
+    pub fn new(name: &str, path: &str, field_type: FieldType) -> Self {
+        FilterField {
+            name: name.to_string(),
+            path: path.to_string(),
+            field_type,
+        }
+    }
+}
+
+/// 工作区、目录、表共有的可过滤字段
+pub fn common_filter_fields() -> Vec<FilterField> {
+    vec![
+        FilterField::new("id", "_id", FieldType::ObjectId),
+        FilterField::new("name", "name", FieldType::String),
+        FilterField::new("description", "description", FieldType::String),
+        FilterField::new("creator", "creator", FieldType::ObjectId),
+        FilterField::new("createdAt", "createdAt", FieldType::DateTime),
+        FilterField::new("updatedAt", "updatedAt", FieldType::DateTime),
+    ]
+}
+
+/// 根据表的列定义生成行的可过滤字段
+pub fn row_filter_fields(columns: &[Column]) -> Vec<FilterField> {
+    let mut fields: Vec<FilterField> = columns
+        .iter()
+        .map(|c| {
+            let field_type = match c.column_type {
+                ColumnType::Integer => FieldType::Integer,
+                ColumnType::Float => FieldType::Float,
+                ColumnType::Boolean => FieldType::Boolean,
+                ColumnType::Datetime => FieldType::DateTime,
+                ColumnType::String | ColumnType::Enum { .. } | ColumnType::Reference { .. } => {
+                    FieldType::String
+                }
+            };
+            FilterField::new(&c.name, &format!("data.{}", c.name), field_type)
+        })
+        .collect();
+    for name in ["id", "createdAt", "updatedAt"].iter() {
+        if !columns.iter().any(|c| &c.name == name) {
+            let (path, field_type) = match *name {
+                "id" => ("_id", FieldType::ObjectId),
+                _ => (*name, FieldType::DateTime),
+            };
+            fields.push(FilterField::new(name, path, field_type));
+        }
+    }
+    fields
+}
+
+/// 解析过滤参数
+///
+/// 支持两种写法：
+/// - 简单写法，多个条件之间是且的关系：`name:contains:foo,createdAt:gt:2024-01-01`，
+///   in操作的多个值用`|`分隔
+/// - JSON写法，支持and/or/nor嵌套：
+///   `{"or":[{"field":"name","op":"eq","value":"a"},{"and":[...]}]}`
+pub fn parse_filter(input: &str, fields: &[FilterField]) -> Result<Condition, String> {
+    let input = input.trim();
+    if input.is_empty() {
+        Ok(Condition::Empty)
+    } else if input.starts_with('{') {
+        let value: Value =
+            serde_json::from_str(input).map_err(|e| format!("invalid filter json: {}", e))?;
+        parse_json_node(&value, fields, 0)
+    } else {
+        let mut conditions = vec![];
+        for part in input.split(',') {
+            let mut segments = part.splitn(3, ':');
+            let field = segments.next().unwrap_or_default();
+            let (op, value) = match (segments.next(), segments.next()) {
+                (Some(op), Some(value)) => (op, value),
+                _ => {
+                    return Err(format!(
+                        "filter '{}' should look like field:operator:value",
+                        part
+                    ))
+                }
+            };
+            let values: Vec<&str> = value.split('|').collect();
+            conditions.push(build_condition(field, op, &values, fields)?);
+        }
+        Ok(Condition::all(conditions))
+    }
+}
+
+/// 解析排序参数
+///
+/// 多个字段用逗号分隔，字段前加`-`表示倒序：`-updatedAt,name`，
+/// 字段按过滤的白名单校验
+pub fn parse_sort(input: &str, fields: &[FilterField]) -> Result<Vec<SortOption>, String> {
+    let mut sorts: Vec<SortOption> = vec![];
+    for part in input.split(',').map(str::trim).filter(|p| !p.is_empty()) {
+        let (name, order) = match part.strip_prefix('-') {
+            Some(name) => (name, SortOrder::Desc),
+            None => (part.strip_prefix('+').unwrap_or(part), SortOrder::Asc),
+        };
+        let field = fields
+            .iter()
+            .find(|f| f.name == name)
+            .ok_or_else(|| format!("field '{}' cannot be sorted", name))?;
+        if sorts.iter().any(|s| s.field == field.path) {
+            return Err(format!("field '{}' is sorted more than once", name));
+        }
+        sorts.push(SortOption::new(&field.path, order));
+    }
+    Ok(sorts)
+}
+
+/// 解析JSON写法中的一个节点
+fn parse_json_node(
+    node: &Value,
+    fields: &[FilterField],
+    depth: usize,
+) -> Result<Condition, String> {
+    if depth >= MAX_FILTER_DEPTH {
+        return Err(format!(
+            "filter nesting is limited to {} levels",
+            MAX_FILTER_DEPTH
+        ));
+    }
+    let obj = node
+        .as_object()
+        .ok_or_else(|| format!("filter node {} should be an object", node))?;
+    if let Some(field) = obj.get("field") {
+        let field = field.as_str().ok_or("'field' should be a string")?;
+        let op = obj
+            .get("op")
+            .and_then(Value::as_str)
+            .ok_or("'op' should be a string")?;
+        let values = match obj.get("value") {
+            Some(Value::Array(items)) => items.iter().map(json_scalar).collect::<Result<_, _>>()?,
+            Some(v) => vec![json_scalar(v)?],
+            None => return Err(format!("filter on '{}' is missing a value", field)),
+        };
+        let values: Vec<&str> = values.iter().map(String::as_str).collect();
+        return build_condition(field, op, &values, fields);
+    }
+    let children = |key: &str| -> Result<Vec<Condition>, String> {
+        match obj.get(key) {
+            None => Ok(vec![]),
+            Some(Value::Array(items)) => items
+                .iter()
+                .map(|item| parse_json_node(item, fields, depth + 1))
+                .collect(),
+            Some(_) => Err(format!("'{}' should be an array", key)),
+        }
+    };
+    let and = children("and")?;
+    let or = children("or")?;
+    let nor = children("nor")?;
+    if and.is_empty() && or.is_empty() && nor.is_empty() {
+        return Err(String::from(
+            "filter node needs a field or one of and/or/nor",
+        ));
+    }
+    Ok(Condition::Complex {
+        and: Box::new(and),
+        or: Box::new(or),
+        nor: Box::new(nor),
+    })
+}
+
+/// JSON中的标量值转成字符串，再按字段类型解析
+fn json_scalar(value: &Value) -> Result<String, String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(format!("unsupported filter value {}", value)),
+    }
+}
+
+/// 按白名单校验字段和操作符，生成单个条件
+fn build_condition(
+    field: &str,
+    op: &str,
+    values: &[&str],
+    fields: &[FilterField],
+) -> Result<Condition, String> {
+    let field = fields
+        .iter()
+        .find(|f| f.name == field)
+        .ok_or_else(|| format!("field '{}' cannot be filtered", field))?;
+    let operate = match op {
+        "eq" => Operate::Eq,
+        "ne" => Operate::Ne,
+        "lt" => Operate::Lt,
+        "le" => Operate::Le,
+        "gt" => Operate::Gt,
+        "ge" => Operate::Ge,
+        "in" => Operate::In,
+        "contains" => Operate::Contains,
+        _ => return Err(format!("unknown filter operator '{}'", op)),
+    };
+    let allowed = match (&operate, &field.field_type) {
+        (Operate::Contains, FieldType::String) => true,
+        (Operate::Contains, _) => false,
+        (Operate::In, FieldType::String) | (Operate::In, FieldType::Integer) => true,
+        (Operate::In, _) => false,
+        (Operate::Eq, _) | (Operate::Ne, _) => true,
+        (_, FieldType::Boolean) | (_, FieldType::ObjectId) => false,
+        _ => true,
+    };
+    if !allowed {
+        return Err(format!(
+            "operator '{}' is not supported on field '{}'",
+            op, field.name
+        ));
+    }
+    let value = match operate {
+        Operate::In => parse_values(field, values)?,
+        _ => match values {
+            [value] => parse_value(field, value)?,
+            _ => {
+                return Err(format!(
+                    "operator '{}' on field '{}' takes a single value",
+                    op, field.name
+                ))
+            }
+        },
+    };
+    Ok(Condition::Single(ConditionNode {
+        field: field.path.clone(),
+        operate,
+        value,
+    }))
+}
+
+/// 解析in操作的多个值
+fn parse_values(field: &FilterField, values: &[&str]) -> Result<ConditionValue, String> {
+    match field.field_type {
+        FieldType::Integer => values
+            .iter()
+            .map(|v| parse_integer(field, v))
+            .collect::<Result<Vec<_>, _>>()
+            .map(ConditionValue::Int64VecValue),
+        _ => Ok(ConditionValue::StringVecValue(
+            values.iter().map(|v| v.to_string()).collect(),
+        )),
+    }
+}
+
+/// 按字段类型解析单个值
+fn parse_value(field: &FilterField, value: &str) -> Result<ConditionValue, String> {
+    let invalid = |expected: &str| {
+        format!(
+            "'{}' is not a valid {} for '{}'",
+            value, expected, field.name
+        )
+    };
+    match field.field_type {
+        FieldType::String => Ok(ConditionValue::StringValue(value.to_string())),
+        FieldType::Integer => parse_integer(field, value).map(ConditionValue::Int64Value),
+        FieldType::Float => value
+            .parse::<f64>()
+            .map(ConditionValue::DoubleValue)
+            .map_err(|_| invalid("float")),
+        FieldType::Boolean => value
+            .parse::<bool>()
+            .map(ConditionValue::BooleanValue)
+            .map_err(|_| invalid("boolean")),
+        FieldType::DateTime => parse_datetime(value)
+            .map(ConditionValue::DateTimeValue)
+            .ok_or_else(|| invalid("datetime")),
+        FieldType::ObjectId => ObjectId::from_str(value)
+            .map(ConditionValue::ObjectIdValue)
+            .map_err(|_| invalid("id")),
+    }
+}
+
+fn parse_integer(field: &FilterField, value: &str) -> Result<i64, String> {
+    value
+        .parse::<i64>()
+        .map_err(|_| format!("'{}' is not a valid integer for '{}'", value, field.name))
+}
+
+/// 日期时间支持RFC3339和`YYYY-MM-DD`两种格式，后者按UTC零点处理
+fn parse_datetime(value: &str) -> Option<DateTime<Utc>> {
+    if let Ok(dt) = DateTime::parse_from_rfc3339(value) {
+        return Some(dt.with_timezone(&Utc));
+    }
+    NaiveDate::parse_from_str(value, "%Y-%m-%d")
+        .ok()
+        .and_then(|d| d.and_hms_opt(0, 0, 0))
+        .map(|dt| Utc.from_utc_datetime(&dt))
+}
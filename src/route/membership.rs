@@ -0,0 +1,80 @@
+use warp::{Rejection, Reply};
+
+use crate::{
+    entity,
+    repository::Storage,
+    service::{auth::CurrentUser, membership::MembershipService},
+};
+
+use super::{
+    request_object::{MemberInviteParam, MemberRoleParam},
+    Response,
+};
+
+/// 获取工作区的所有成员
+pub async fn find_all_member<S: Storage>(
+    workspace_id: String,
+    current_user: CurrentUser,
+    membership_service: MembershipService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = membership_service
+        .find_all_member(&current_user, workspace_id)
+        .await?;
+    Response::<Vec<entity::Membership>> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 邀请用户加入工作区
+pub async fn invite_member<S: Storage>(
+    workspace_id: String,
+    current_user: CurrentUser,
+    param: MemberInviteParam,
+    membership_service: MembershipService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = membership_service
+        .invite(&current_user, workspace_id, param.username, param.role)
+        .await?;
+    Response::<String> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 修改成员的角色
+pub async fn change_member_role<S: Storage>(
+    workspace_id: String,
+    user_id: String,
+    current_user: CurrentUser,
+    param: MemberRoleParam,
+    membership_service: MembershipService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = membership_service
+        .change_role(&current_user, workspace_id, user_id, param.role)
+        .await?;
+    Response::<()> {
+        success: res,
+        data: (),
+    }
+    .to_http_reply()
+}
+
+/// 移除成员
+pub async fn remove_member<S: Storage>(
+    workspace_id: String,
+    user_id: String,
+    current_user: CurrentUser,
+    membership_service: MembershipService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = membership_service
+        .remove(&current_user, workspace_id, user_id)
+        .await?;
+    Response::<()> {
+        success: res,
+        data: (),
+    }
+    .to_http_reply()
+}
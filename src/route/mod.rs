@@ -1,17 +1,58 @@
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
-use std::{convert::Infallible, net::SocketAddr};
+use serde_json::{Map, Value};
+use std::{convert::Infallible, net::SocketAddr, time::Duration};
 use warp::{http::StatusCode, Filter, Rejection, Reply};
 
 use crate::{
-    env_var,
-    repository::mongodb::{workspace::WorkspaceRepo, MongoDB, MongodbError},
-    route::request_object::WorkspaceUpdateParam,
-    service::{workspace::WorkspaceService, ServiceError},
+    env_u64, env_var,
+    repository::{
+        condition::{CursorOption, PageOption},
+        memory::MemoryDB,
+        mongodb::MongoDB,
+        postgres::Postgres,
+        sqlite::Sqlite,
+        CursorResult, PageResult, Storage,
+    },
+    route::request_object::{
+        WorkspaceBulkCreateParam, WorkspaceBulkDeleteParam, WorkspaceBulkPatchParam,
+        WorkspacePatchParam, WorkspaceUpdateParam,
+    },
+    service::{
+        api_key::ApiKeyService,
+        auth::{check_secret, AuthService, CurrentUser},
+        catalog::CatalogService,
+        membership::MembershipService,
+        row::RowService,
+        table::TableService,
+        trash::TrashService,
+        user::UserService,
+        workspace::WorkspaceService,
+        ServiceError,
+    },
 };
 
-use self::request_object::WorkspaceCreateParam;
+use self::{
+    request_object::{
+        ApiKeyCreateParam, CatalogCreateParam, CatalogUpdateParam, ColumnAddParam, ColumnNameParam,
+        ColumnRenameParam, ColumnReorderParam, ColumnRetypeParam, DeleteQuery, ListQuery,
+        LoginParam, MemberInviteParam, MemberRoleParam, PasswordChangeParam, TableCreateParam,
+        TableUpdateParam, UserRegisterParam, WorkspaceCreateParam,
+    },
+    validation::Validate,
+};
 
+mod api_key;
+mod auth;
+mod catalog;
+mod error;
+mod filter;
+mod membership;
 mod request_object;
+mod row;
+mod table;
+mod trash;
+mod user;
+mod validation;
 mod workspace;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -24,120 +65,705 @@ impl<T: Serialize> Response<T> {
         let json = warp::reply::json(&self);
         Ok(warp::reply::with_status(json, StatusCode::OK))
     }
+
+    /// 带ETag响应头的回复，ETag是数据的版本号
+    fn to_http_reply_with_version(&self, version: i64) -> Result<impl Reply, Rejection> {
+        let reply = self.to_http_reply()?;
+        Ok(warp::reply::with_header(
+            reply,
+            "etag",
+            format!("\"{}\"", version),
+        ))
+    }
 }
 
-impl warp::reject::Reject for ServiceError {}
+/// 分页列表的返回结构
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Page<T: Serialize> {
+    items: Vec<T>,
+    total: i64,
+    page: usize,
+    size: usize,
+    has_next: bool,
+}
+impl<T: Serialize> Page<T> {
+    fn new(result: PageResult<T>, page_option: &PageOption) -> Self {
+        let has_next = ((page_option.page * page_option.size) as i64) < result.count;
+        Page {
+            items: result.datas,
+            total: result.count,
+            page: page_option.page,
+            size: page_option.size,
+            has_next,
+        }
+    }
+}
 
-/// 处理warp的不成功情况
-async fn handle_rejection(err: Rejection) -> std::result::Result<impl Reply, Infallible> {
-    use ServiceError::*;
-    let code = if err.is_not_found() {
-        StatusCode::NOT_FOUND
-    } else if let Some(err) = err.find::<ServiceError>() {
-        match err {
-            RepositoryError(e) => match e {
-                MongodbError::DataNotFoundError => {
-                    log::info!("data not found");
-                    StatusCode::NOT_FOUND
-                }
-                MongodbError::BsonSerError(_) | MongodbError::BsonOidError(_) => {
-                    log::info!("serialize data error");
-                    StatusCode::BAD_REQUEST
-                }
-                _ => {
-                    log::error!("{:?}", e);
-                    StatusCode::INTERNAL_SERVER_ERROR
-                }
-            },
-            IoError(e) => {
-                log::error!("{:?}", e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            }
-            WarpError(e) => {
-                log::error!("{:?}", e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            }
-            HttpError(e) => {
-                log::error!("{:?}", e);
-                StatusCode::INTERNAL_SERVER_ERROR
-            }
+/// 游标分页列表的返回结构，nextCursor为null时没有下一页
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CursorPage<T: Serialize> {
+    items: Vec<T>,
+    size: usize,
+    next_cursor: Option<String>,
+}
+impl<T: Serialize> CursorPage<T> {
+    fn new(result: CursorResult<T>, cursor_option: &CursorOption) -> Self {
+        CursorPage {
+            items: result.datas,
+            size: cursor_option.size,
+            next_cursor: result.next_cursor.map(|c| c.encode()),
         }
-    } else if err.find::<warp::reject::MethodNotAllowed>().is_some() {
-        log::warn!("{:?}", err);
-        StatusCode::METHOD_NOT_ALLOWED
-    } else {
-        log::error!("{:?}", err);
-        StatusCode::INTERNAL_SERVER_ERROR
-    };
-    let json = warp::reply::json(&Response::<()> {
-        success: false,
-        data: (),
-    });
-    Ok(warp::reply::with_status(json, code))
+    }
+}
+
+/// 批量操作的返回结构，items和请求中的条目按顺序一一对应
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct BulkResult {
+    succeeded: usize,
+    failed: usize,
+    items: Vec<BulkItem>,
+}
+impl BulkResult {
+    fn new(results: Vec<Result<String, ServiceError>>) -> Self {
+        let items = results
+            .into_iter()
+            .enumerate()
+            .map(|(index, result)| match result {
+                Ok(id) => BulkItem {
+                    index,
+                    success: true,
+                    id: Some(id),
+                    error: None,
+                },
+                Err(e) => BulkItem {
+                    index,
+                    success: false,
+                    id: None,
+                    error: Some(error::ItemError::from(&e)),
+                },
+            })
+            .collect::<Vec<_>>();
+        let succeeded = items.iter().filter(|i| i.success).count();
+        BulkResult {
+            succeeded,
+            failed: items.len() - succeeded,
+            items,
+        }
+    }
+}
+
+/// 批量操作中单个条目的结果，成功时id是创建或修改的数据的id
+#[derive(Serialize, Debug)]
+struct BulkItem {
+    index: usize,
+    success: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<error::ItemError>,
 }
 
+impl warp::reject::Reject for ServiceError {}
+
 fn json_body_request<T: DeserializeOwned + Send>(
 ) -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone {
     warp::body::content_length_limit(1024 * 64).and(warp::body::json())
 }
 
-fn with_workspace_service(
-    service: WorkspaceService,
-) -> impl Filter<Extract = (WorkspaceService,), Error = Infallible> + Clone {
+/// 解析JSON请求体并执行声明式校验，校验失败时返回所有字段的错误
+fn validated_body<T: DeserializeOwned + Validate + Send>(
+) -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone {
+    json_body_request::<T>().and_then(|body: T| async move {
+        validation::validate(&body)?;
+        Ok::<_, Rejection>(body)
+    })
+}
+
+/// 批量接口的请求体，上限比普通请求大，这里只校验条目数量等整体规则
+fn bulk_body<T: DeserializeOwned + Validate + Send>(
+) -> impl Filter<Extract = (T,), Error = warp::Rejection> + Clone {
+    warp::body::content_length_limit(1024 * 1024 * 4)
+        .and(warp::body::json())
+        .and_then(|body: T| async move {
+            validation::validate(&body)?;
+            Ok::<_, Rejection>(body)
+        })
+}
+
+/// 从If-Match头中取出期望的版本号，没有这个头或者值是`*`时不检查版本号
+fn if_match() -> impl Filter<Extract = (Option<i64>,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("if-match").and_then(|header: Option<String>| async move {
+        let value = match header.as_deref().map(str::trim) {
+            None | Some("*") => return Ok(None),
+            Some(value) => value,
+        };
+        let version = value
+            .trim_start_matches("W/")
+            .trim_matches('"')
+            .parse::<i64>()
+            .map_err(|_| {
+                ServiceError::InvalidArgument(format!("invalid If-Match header: {}", value))
+            })?;
+        Ok::<_, Rejection>(Some(version))
+    })
+}
+
+/// 从Authorization头中取出Bearer令牌或API key，校验后得到当前用户
+fn with_current_user<S: Storage>(
+    auth_service: AuthService<S>,
+) -> impl Filter<Extract = (CurrentUser,), Error = Rejection> + Clone {
+    warp::header::optional::<String>("authorization").and_then(move |header: Option<String>| {
+        let auth_service = auth_service.clone();
+        async move {
+            let token = header
+                .as_deref()
+                .and_then(|h| h.strip_prefix("Bearer "))
+                .ok_or_else(|| ServiceError::Unauthorized(String::from("missing bearer token")))?;
+            let user = auth_service.authenticate(token.trim()).await?;
+            Ok::<_, Rejection>(user)
+        }
+    })
+}
+
+/// 只要求已登录的接口使用，不向处理函数传递当前用户
+fn authenticated<S: Storage>(
+    auth_service: AuthService<S>,
+) -> impl Filter<Extract = (), Error = Rejection> + Clone {
+    with_current_user(auth_service).map(|_| ()).untuple_one()
+}
+
+fn with_auth_service<S: Storage>(
+    service: AuthService<S>,
+) -> impl Filter<Extract = (AuthService<S>,), Error = Infallible> + Clone {
+    warp::any().map(move || service.clone())
+}
+
+fn with_user_service<S: Storage>(
+    service: UserService<S>,
+) -> impl Filter<Extract = (UserService<S>,), Error = Infallible> + Clone {
     warp::any().map(move || service.clone())
 }
 
+fn with_api_key_service<S: Storage>(
+    service: ApiKeyService<S>,
+) -> impl Filter<Extract = (ApiKeyService<S>,), Error = Infallible> + Clone {
+    warp::any().map(move || service.clone())
+}
+
+fn with_workspace_service<S: Storage>(
+    service: WorkspaceService<S>,
+) -> impl Filter<Extract = (WorkspaceService<S>,), Error = Infallible> + Clone {
+    warp::any().map(move || service.clone())
+}
+
+fn with_membership_service<S: Storage>(
+    service: MembershipService<S>,
+) -> impl Filter<Extract = (MembershipService<S>,), Error = Infallible> + Clone {
+    warp::any().map(move || service.clone())
+}
+
+fn with_catalog_service<S: Storage>(
+    service: CatalogService<S>,
+) -> impl Filter<Extract = (CatalogService<S>,), Error = Infallible> + Clone {
+    warp::any().map(move || service.clone())
+}
+
+fn with_row_service<S: Storage>(
+    service: RowService<S>,
+) -> impl Filter<Extract = (RowService<S>,), Error = Infallible> + Clone {
+    warp::any().map(move || service.clone())
+}
+
+fn with_table_service<S: Storage>(
+    service: TableService<S>,
+) -> impl Filter<Extract = (TableService<S>,), Error = Infallible> + Clone {
+    warp::any().map(move || service.clone())
+}
+
+fn with_trash_service<S: Storage>(
+    service: TrashService<S>,
+) -> impl Filter<Extract = (TrashService<S>,), Error = Infallible> + Clone {
+    warp::any().map(move || service.clone())
+}
+
+/// 清理回收站的间隔
+const TRASH_PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 在后台定期彻底删除回收站中超过保留天数的数据
+fn spawn_trash_purge<S: Storage>(trash_service: TrashService<S>, retention_days: u64) {
+    let retention = chrono::Duration::days(retention_days as i64);
+    tokio::spawn(async move {
+        let mut interval = tokio::time::interval(TRASH_PURGE_INTERVAL);
+        loop {
+            interval.tick().await;
+            match trash_service.purge_expired(retention).await {
+                Ok(0) => {}
+                Ok(count) => log::info!("purged {} items from trash", count),
+                Err(e) => log::error!("failed to purge trash: {:?}", e),
+            }
+        }
+    });
+}
+
 /// 启动路由
+///
+/// 存储后端由环境变量STORAGE选择，可选mongodb（默认）、postgres、sqlite和memory
 pub async fn run(addr: SocketAddr) {
-    let uri = env_var!("MONGODB_URI");
-    let db = MongoDB::init(&uri)
-        .await
-        .expect("couldn't connect to MongoDB server");
+    let storage = std::env::var("STORAGE").unwrap_or_else(|_| String::from("mongodb"));
+    match storage.as_str() {
+        "mongodb" => {
+            let uri = env_var!("MONGODB_URI");
+            let db = MongoDB::init(&uri)
+                .await
+                .expect("couldn't connect to MongoDB server");
+            serve(addr, db).await
+        }
+        "postgres" => {
+            let uri = env_var!("POSTGRES_URI");
+            let db = Postgres::init(&uri)
+                .await
+                .expect("couldn't connect to PostgreSQL server");
+            serve(addr, db).await
+        }
+        "sqlite" => {
+            let path = env_var!("SQLITE_PATH");
+            let db = Sqlite::init(&path)
+                .await
+                .expect("couldn't open SQLite database");
+            serve(addr, db).await
+        }
+        "memory" => {
+            log::warn!("using in-memory storage, data will be lost on exit");
+            serve(addr, MemoryDB::new()).await
+        }
+        _ => panic!("unknown storage backend: {}", storage),
+    }
+}
+
+/// 使用指定的存储后端启动路由
+async fn serve<S: Storage>(addr: SocketAddr, storage: S) {
+    let jwt_secret = env_var!("JWT_SECRET");
+    if let Err(e) = check_secret(&jwt_secret) {
+        panic!("invalid JWT_SECRET: {}", e);
+    }
+    let jwt_ttl_seconds = env_u64!("JWT_TTL_SECONDS");
+    let trash_retention_days = env_u64!("TRASH_RETENTION_DAYS");
+    let workspace_service = WorkspaceService::new(&storage);
+    let membership_service = MembershipService::new(&storage);
+    let catalog_service = CatalogService::new(&storage);
+    let table_service = TableService::new(&storage);
+    let row_service = RowService::new(&storage);
+    let user_service = UserService::new(&storage);
+    let auth_service = AuthService::new(&storage, &jwt_secret, jwt_ttl_seconds);
+    let api_key_service = ApiKeyService::new(&storage);
+    let trash_service = TrashService::new(&storage);
+    spawn_trash_purge(trash_service.clone(), trash_retention_days);
+
+    // POST /auth/login
+    let login_route = warp::path!("auth" / "login")
+        .and(warp::post())
+        .and(validated_body::<LoginParam>())
+        .and(with_auth_service(auth_service.clone()))
+        .and_then(auth::login);
+
+    // GET /auth/me
+    let current_user_route = warp::path!("auth" / "me")
+        .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
+        .and_then(auth::current_user);
 
-    let workspace_service = WorkspaceService::new(WorkspaceRepo::new(db.clone()));
+    // POST /api-keys
+    let create_api_key_route = warp::path!("api-keys")
+        .and(warp::post())
+        .and(with_current_user(auth_service.clone()))
+        .and(validated_body::<ApiKeyCreateParam>())
+        .and(with_api_key_service(api_key_service.clone()))
+        .and_then(api_key::create_api_key);
+
+    // GET /api-keys
+    let get_all_api_key_route = warp::path!("api-keys")
+        .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
+        .and(with_api_key_service(api_key_service.clone()))
+        .and_then(api_key::find_all_api_key);
+
+    // DELETE /api-keys/:ID
+    let revoke_api_key_route = warp::path!("api-keys" / String)
+        .and(warp::delete())
+        .and(with_current_user(auth_service.clone()))
+        .and(with_api_key_service(api_key_service))
+        .and_then(api_key::revoke_api_key);
+
+    // POST /users
+    let register_user_route = warp::path!("users")
+        .and(warp::post())
+        .and(validated_body::<UserRegisterParam>())
+        .and(with_user_service(user_service.clone()))
+        .and_then(user::register_user);
+
+    // GET /users/:ID
+    let get_user_route = warp::path!("users" / String)
+        .and(warp::get())
+        .and(authenticated(auth_service.clone()))
+        .and(with_user_service(user_service.clone()))
+        .and_then(user::get_user_by_id);
+
+    // PUT /users/:ID/password
+    let change_password_route = warp::path!("users" / String / "password")
+        .and(warp::put())
+        .and(with_current_user(auth_service.clone()))
+        .and(validated_body::<PasswordChangeParam>())
+        .and(with_user_service(user_service))
+        .and_then(user::change_password);
 
     // POST /workspaces
     let create_workspace_route = warp::path!("workspaces")
         .and(warp::post())
-        .and(json_body_request::<WorkspaceCreateParam>())
+        .and(with_current_user(auth_service.clone()))
+        .and(validated_body::<WorkspaceCreateParam>())
         .and(with_workspace_service(workspace_service.clone()))
         .and_then(workspace::create_workspace);
 
     // GET /workspaces
     let get_all_workspace_route = warp::path!("workspaces")
         .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
+        .and(warp::query::<ListQuery>())
         .and(with_workspace_service(workspace_service.clone()))
         .and_then(workspace::find_all_workspace);
 
+    // POST /workspaces/bulk
+    let bulk_create_workspace_route = warp::path!("workspaces" / "bulk")
+        .and(warp::post())
+        .and(with_current_user(auth_service.clone()))
+        .and(bulk_body::<WorkspaceBulkCreateParam>())
+        .and(with_workspace_service(workspace_service.clone()))
+        .and_then(workspace::bulk_create_workspace);
+
+    // PATCH /workspaces/bulk
+    let bulk_patch_workspace_route = warp::path!("workspaces" / "bulk")
+        .and(warp::patch())
+        .and(with_current_user(auth_service.clone()))
+        .and(bulk_body::<WorkspaceBulkPatchParam>())
+        .and(with_workspace_service(workspace_service.clone()))
+        .and_then(workspace::bulk_patch_workspace);
+
+    // DELETE /workspaces/bulk
+    let bulk_delete_workspace_route = warp::path!("workspaces" / "bulk")
+        .and(warp::delete())
+        .and(with_current_user(auth_service.clone()))
+        .and(warp::query::<DeleteQuery>())
+        .and(bulk_body::<WorkspaceBulkDeleteParam>())
+        .and(with_workspace_service(workspace_service.clone()))
+        .and_then(workspace::bulk_delete_workspace);
+
     // GET /workspaces/:ID
     let get_workspace_route = warp::path!("workspaces" / String)
         .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
         .and(with_workspace_service(workspace_service.clone()))
         .and_then(workspace::get_workspace_by_id);
 
     // PUT /workspaces/:ID
     let update_workspace_route = warp::path!("workspaces" / String)
         .and(warp::put())
-        .and(json_body_request::<WorkspaceUpdateParam>())
+        .and(with_current_user(auth_service.clone()))
+        .and(if_match())
+        .and(validated_body::<WorkspaceUpdateParam>())
         .and(with_workspace_service(workspace_service.clone()))
         .and_then(workspace::update_workspace_info);
 
+    // PATCH /workspaces/:ID
+    let patch_workspace_route = warp::path!("workspaces" / String)
+        .and(warp::patch())
+        .and(with_current_user(auth_service.clone()))
+        .and(if_match())
+        .and(validated_body::<WorkspacePatchParam>())
+        .and(with_workspace_service(workspace_service.clone()))
+        .and_then(workspace::patch_workspace);
+
     // DELETE /workspaces/:ID
     let delete_workspace_route = warp::path!("workspaces" / String)
         .and(warp::delete())
-        .and(with_workspace_service(workspace_service))
+        .and(with_current_user(auth_service.clone()))
+        .and(if_match())
+        .and(warp::query::<DeleteQuery>())
+        .and(with_workspace_service(workspace_service.clone()))
         .and_then(workspace::delete_workspace_by_id);
 
+    // POST /workspaces/:ID/restore
+    let restore_workspace_route = warp::path!("workspaces" / String / "restore")
+        .and(warp::post())
+        .and(with_current_user(auth_service.clone()))
+        .and(with_workspace_service(workspace_service))
+        .and_then(workspace::restore_workspace);
+
+    // GET /trash
+    let get_trash_route = warp::path!("trash")
+        .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
+        .and(with_trash_service(trash_service))
+        .and_then(trash::find_trash);
+
+    // GET /workspaces/:ID/members
+    let get_all_member_route = warp::path!("workspaces" / String / "members")
+        .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
+        .and(with_membership_service(membership_service.clone()))
+        .and_then(membership::find_all_member);
+
+    // POST /workspaces/:ID/members
+    let invite_member_route = warp::path!("workspaces" / String / "members")
+        .and(warp::post())
+        .and(with_current_user(auth_service.clone()))
+        .and(validated_body::<MemberInviteParam>())
+        .and(with_membership_service(membership_service.clone()))
+        .and_then(membership::invite_member);
+
+    // PUT /workspaces/:ID/members/:USER_ID
+    let change_member_role_route = warp::path!("workspaces" / String / "members" / String)
+        .and(warp::put())
+        .and(with_current_user(auth_service.clone()))
+        .and(json_body_request::<MemberRoleParam>())
+        .and(with_membership_service(membership_service.clone()))
+        .and_then(membership::change_member_role);
+
+    // DELETE /workspaces/:ID/members/:USER_ID
+    let remove_member_route = warp::path!("workspaces" / String / "members" / String)
+        .and(warp::delete())
+        .and(with_current_user(auth_service.clone()))
+        .and(with_membership_service(membership_service))
+        .and_then(membership::remove_member);
+
+    // POST /workspaces/:ID/catalogs
+    let create_catalog_route = warp::path!("workspaces" / String / "catalogs")
+        .and(warp::post())
+        .and(with_current_user(auth_service.clone()))
+        .and(validated_body::<CatalogCreateParam>())
+        .and(with_catalog_service(catalog_service.clone()))
+        .and_then(catalog::create_catalog);
+
+    // GET /workspaces/:ID/catalogs
+    let get_all_catalog_route = warp::path!("workspaces" / String / "catalogs")
+        .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
+        .and(warp::query::<ListQuery>())
+        .and(with_catalog_service(catalog_service.clone()))
+        .and_then(catalog::find_all_catalog);
+
+    // GET /workspaces/:ID/catalogs/:ID
+    let get_catalog_route = warp::path!("workspaces" / String / "catalogs" / String)
+        .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
+        .and(with_catalog_service(catalog_service.clone()))
+        .and_then(catalog::get_catalog_by_id);
+
+    // PUT /workspaces/:ID/catalogs/:ID
+    let update_catalog_route = warp::path!("workspaces" / String / "catalogs" / String)
+        .and(warp::put())
+        .and(with_current_user(auth_service.clone()))
+        .and(if_match())
+        .and(validated_body::<CatalogUpdateParam>())
+        .and(with_catalog_service(catalog_service.clone()))
+        .and_then(catalog::update_catalog_info);
+
+    // DELETE /workspaces/:ID/catalogs/:ID
+    let delete_catalog_route = warp::path!("workspaces" / String / "catalogs" / String)
+        .and(warp::delete())
+        .and(with_current_user(auth_service.clone()))
+        .and(if_match())
+        .and(warp::query::<DeleteQuery>())
+        .and(with_catalog_service(catalog_service.clone()))
+        .and_then(catalog::delete_catalog_by_id);
+
+    // POST /workspaces/:ID/catalogs/:ID/restore
+    let restore_catalog_route =
+        warp::path!("workspaces" / String / "catalogs" / String / "restore")
+            .and(warp::post())
+            .and(with_current_user(auth_service.clone()))
+            .and(with_catalog_service(catalog_service))
+            .and_then(catalog::restore_catalog);
+
+    // POST /catalogs/:ID/tables
+    let create_table_route = warp::path!("catalogs" / String / "tables")
+        .and(warp::post())
+        .and(with_current_user(auth_service.clone()))
+        .and(validated_body::<TableCreateParam>())
+        .and(with_table_service(table_service.clone()))
+        .and_then(table::create_table);
+
+    // GET /catalogs/:ID/tables
+    let get_all_table_route = warp::path!("catalogs" / String / "tables")
+        .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
+        .and(warp::query::<ListQuery>())
+        .and(with_table_service(table_service.clone()))
+        .and_then(table::find_all_table);
+
+    // GET /catalogs/:ID/tables/:ID
+    let get_table_route = warp::path!("catalogs" / String / "tables" / String)
+        .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
+        .and(with_table_service(table_service.clone()))
+        .and_then(table::get_table_by_id);
+
+    // PUT /catalogs/:ID/tables/:ID
+    let update_table_route = warp::path!("catalogs" / String / "tables" / String)
+        .and(warp::put())
+        .and(with_current_user(auth_service.clone()))
+        .and(if_match())
+        .and(validated_body::<TableUpdateParam>())
+        .and(with_table_service(table_service.clone()))
+        .and_then(table::update_table_info);
+
+    // DELETE /catalogs/:ID/tables/:ID
+    let delete_table_route = warp::path!("catalogs" / String / "tables" / String)
+        .and(warp::delete())
+        .and(with_current_user(auth_service.clone()))
+        .and(if_match())
+        .and(warp::query::<DeleteQuery>())
+        .and(with_table_service(table_service.clone()))
+        .and_then(table::delete_table_by_id);
+
+    // POST /catalogs/:ID/tables/:ID/restore
+    let restore_table_route = warp::path!("catalogs" / String / "tables" / String / "restore")
+        .and(warp::post())
+        .and(with_current_user(auth_service.clone()))
+        .and(with_table_service(table_service.clone()))
+        .and_then(table::restore_table);
+
+    // POST /tables/:ID/columns
+    let add_column_route = warp::path!("tables" / String / "columns")
+        .and(warp::post())
+        .and(with_current_user(auth_service.clone()))
+        .and(validated_body::<ColumnAddParam>())
+        .and(with_table_service(table_service.clone()))
+        .and_then(table::add_column);
+
+    // PUT /tables/:ID/columns/order
+    let reorder_columns_route = warp::path!("tables" / String / "columns" / "order")
+        .and(warp::put())
+        .and(with_current_user(auth_service.clone()))
+        .and(validated_body::<ColumnReorderParam>())
+        .and(with_table_service(table_service.clone()))
+        .and_then(table::reorder_columns);
+
+    // PUT /tables/:ID/columns/:NAME/name
+    let rename_column_route = warp::path!("tables" / String / "columns" / ColumnNameParam / "name")
+        .and(warp::put())
+        .and(with_current_user(auth_service.clone()))
+        .and(validated_body::<ColumnRenameParam>())
+        .and(with_table_service(table_service.clone()))
+        .and_then(table::rename_column);
+
+    // PUT /tables/:ID/columns/:NAME/type
+    let retype_column_route = warp::path!("tables" / String / "columns" / ColumnNameParam / "type")
+        .and(warp::put())
+        .and(with_current_user(auth_service.clone()))
+        .and(validated_body::<ColumnRetypeParam>())
+        .and(with_table_service(table_service.clone()))
+        .and_then(table::retype_column);
+
+    // DELETE /tables/:ID/columns/:NAME
+    let drop_column_route = warp::path!("tables" / String / "columns" / ColumnNameParam)
+        .and(warp::delete())
+        .and(with_current_user(auth_service.clone()))
+        .and(with_table_service(table_service))
+        .and_then(table::drop_column);
+
+    // POST /tables/:ID/rows
+    let insert_row_route = warp::path!("tables" / String / "rows")
+        .and(warp::post())
+        .and(with_current_user(auth_service.clone()))
+        .and(json_body_request::<Map<String, Value>>())
+        .and(with_row_service(row_service.clone()))
+        .and_then(row::insert_row);
+
+    // GET /tables/:ID/rows
+    let get_all_row_route = warp::path!("tables" / String / "rows")
+        .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
+        .and(warp::query::<ListQuery>())
+        .and(with_row_service(row_service.clone()))
+        .and_then(row::find_all_row);
+
+    // GET /tables/:ID/rows/:ID
+    let get_row_route = warp::path!("tables" / String / "rows" / String)
+        .and(warp::get())
+        .and(with_current_user(auth_service.clone()))
+        .and(with_row_service(row_service.clone()))
+        .and_then(row::get_row_by_id);
+
+    // PUT /tables/:ID/rows/:ID
+    let update_row_route = warp::path!("tables" / String / "rows" / String)
+        .and(warp::put())
+        .and(with_current_user(auth_service.clone()))
+        .and(if_match())
+        .and(json_body_request::<Map<String, Value>>())
+        .and(with_row_service(row_service.clone()))
+        .and_then(row::update_row);
+
+    // DELETE /tables/:ID/rows/:ID
+    let delete_row_route = warp::path!("tables" / String / "rows" / String)
+        .and(warp::delete())
+        .and(with_current_user(auth_service.clone()))
+        .and(if_match())
+        .and(with_row_service(row_service))
+        .and_then(row::delete_row_by_id);
+
     // 返回整个route
     let routes = warp::path("api")
         .and(
             create_workspace_route
                 .or(get_all_workspace_route)
+                .or(bulk_create_workspace_route)
+                .or(bulk_patch_workspace_route)
+                .or(bulk_delete_workspace_route)
                 .or(get_workspace_route)
                 .or(update_workspace_route)
-                .or(delete_workspace_route),
+                .or(patch_workspace_route)
+                .or(delete_workspace_route)
+                .or(restore_workspace_route)
+                .or(get_all_member_route)
+                .or(invite_member_route)
+                .or(change_member_role_route)
+                .or(remove_member_route)
+                .or(create_catalog_route)
+                .or(get_all_catalog_route)
+                .or(get_catalog_route)
+                .or(update_catalog_route)
+                .or(delete_catalog_route)
+                .or(restore_catalog_route)
+                .or(create_table_route)
+                .or(get_all_table_route)
+                .or(get_table_route)
+                .or(update_table_route)
+                .or(delete_table_route)
+                .or(restore_table_route)
+                .or(get_trash_route)
+                .or(add_column_route)
+                .or(reorder_columns_route)
+                .or(rename_column_route)
+                .or(retype_column_route)
+                .or(drop_column_route)
+                .or(insert_row_route)
+                .or(get_all_row_route)
+                .or(get_row_route)
+                .or(update_row_route)
+                .or(delete_row_route)
+                .or(login_route)
+                .or(current_user_route)
+                .or(create_api_key_route)
+                .or(get_all_api_key_route)
+                .or(revoke_api_key_route)
+                .or(register_user_route)
+                .or(get_user_route)
+                .or(change_password_route),
         )
-        .recover(handle_rejection)
+        .recover(error::handle_rejection)
         .with(warp::log("crud-toy"));
     warp::serve(routes).run(addr).await
 }
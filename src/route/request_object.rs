@@ -1,10 +1,141 @@
+use std::str::{FromStr, Utf8Error};
+
+use chrono::{DateTime, Utc};
+use lazy_static::lazy_static;
+use percent_encoding::percent_decode_str;
+use regex::Regex;
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    entity::{Column, ColumnType, KeyScope, Role},
+    repository::condition::{keyset_sorts, Cursor, CursorOption, PageOption, SortOption},
+};
+
+use super::validation::{Validate, Validator};
+
+/// 默认每页数量
+const DEFAULT_PAGE_SIZE: usize = 20;
+/// 每页数量的上限
+const MAX_PAGE_SIZE: usize = 100;
+/// 工作区、目录和表名称的最大长度
+const NAME_MAX_LENGTH: usize = 100;
+/// 描述的最大长度
+const DESCRIPTION_MAX_LENGTH: usize = 2000;
+/// 列名和API key名称的最大长度
+const SHORT_NAME_MAX_LENGTH: usize = 64;
+/// 一次批量操作的最大条目数量
+const BULK_MAX_ITEMS: usize = 1000;
+
+lazy_static! {
+    /// 名称中不能有换行等控制字符
+    static ref NAME_PATTERN: Regex = Regex::new(r"^[^\p{Cc}]*$").unwrap();
+    /// 列名会作为数据中的键，不能以`$`开头或包含`.`
+    static ref COLUMN_NAME_PATTERN: Regex = Regex::new(r"^[^$.][^.]*$").unwrap();
+}
+
+/// 工作区、目录和表共用的名称和描述规则
+fn validate_name_and_description(v: &mut Validator, name: &str, description: &str) {
+    v.field("name", name)
+        .required()
+        .max_length(NAME_MAX_LENGTH)
+        .pattern(&NAME_PATTERN, "must not contain control characters");
+    v.field("description", description)
+        .max_length(DESCRIPTION_MAX_LENGTH);
+}
+
+fn validate_column_name(v: &mut Validator, field: &str, name: &str) {
+    v.field(field, name)
+        .required()
+        .max_length(SHORT_NAME_MAX_LENGTH)
+        .pattern(
+            &COLUMN_NAME_PATTERN,
+            "must not start with '$' or contain '.'",
+        );
+}
+
+/// 引用类型的列，被引用的表id必须是合法的id
+fn validate_column_type(v: &mut Validator, column_type: &ColumnType) {
+    if let ColumnType::Reference { table_id } = column_type {
+        v.field("type.tableId", table_id).required().object_id();
+    }
+}
+
+/// 删除接口的查询参数，cascade为true时连同下级数据一起删除
+#[derive(Serialize, Deserialize, Debug)]
+pub struct DeleteQuery {
+    pub cascade: Option<bool>,
+}
+
+/// 列表接口的查询参数
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ListQuery {
+    pub filter: Option<String>,
+    pub sort: Option<String>,
+    pub page: Option<usize>,
+    pub size: Option<usize>,
+    /// 传了cursor时使用游标分页，空字符串表示从头开始
+    pub cursor: Option<String>,
+}
+
+impl ListQuery {
+    /// 分页设置，页码从1开始，每页数量超过上限时按上限处理
+    pub fn page_option(&self) -> Result<PageOption, String> {
+        let page = self.page.unwrap_or(1);
+        if page == 0 {
+            return Err(String::from("page starts from 1"));
+        }
+        Ok(PageOption {
+            page,
+            size: self.page_size()?,
+        })
+    }
+
+    /// 游标分页设置，没有传cursor时返回None
+    ///
+    /// 游标必须是按同样的排序生成的
+    pub fn cursor_option(&self, sorts: &[SortOption]) -> Result<Option<CursorOption>, String> {
+        let token = match &self.cursor {
+            Some(token) => token,
+            None => return Ok(None),
+        };
+        if self.page.is_some() {
+            return Err(String::from("page and cursor cannot be used together"));
+        }
+        let after = if token.is_empty() {
+            None
+        } else {
+            let cursor = Cursor::decode(token).ok_or("invalid cursor")?;
+            if !cursor.matches(&keyset_sorts(sorts)) {
+                return Err(String::from("cursor does not match the sort"));
+            }
+            Some(cursor)
+        };
+        Ok(Some(CursorOption {
+            after,
+            size: self.page_size()?,
+        }))
+    }
+
+    fn page_size(&self) -> Result<usize, String> {
+        let size = self.size.unwrap_or(DEFAULT_PAGE_SIZE);
+        if size == 0 {
+            return Err(String::from("size must be greater than 0"));
+        }
+        Ok(size.min(MAX_PAGE_SIZE))
+    }
+}
 
 #[derive(Serialize, Deserialize, Debug)]
 pub struct WorkspaceCreateParam {
     pub name: String,
     pub description: String,
-    pub creator: String,
+}
+
+impl Validate for WorkspaceCreateParam {
+    fn validate(&self, v: &mut Validator) {
+        validate_name_and_description(v, &self.name, &self.description);
+    }
 }
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -12,3 +143,300 @@ pub struct WorkspaceUpdateParam {
     pub name: String,
     pub description: String,
 }
+
+impl Validate for WorkspaceUpdateParam {
+    fn validate(&self, v: &mut Validator) {
+        validate_name_and_description(v, &self.name, &self.description);
+    }
+}
+
+/// 部分更新工作区，没有传的字段保持不变
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkspacePatchParam {
+    pub name: Option<String>,
+    pub description: Option<String>,
+}
+
+impl Validate for WorkspacePatchParam {
+    fn validate(&self, v: &mut Validator) {
+        v.optional("name", self.name.as_deref())
+            .required()
+            .max_length(NAME_MAX_LENGTH)
+            .pattern(&NAME_PATTERN, "must not contain control characters");
+        v.optional("description", self.description.as_deref())
+            .max_length(DESCRIPTION_MAX_LENGTH);
+    }
+}
+
+/// 批量创建工作区，每个条目由处理函数单独校验，校验失败的条目不会创建
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkspaceBulkCreateParam {
+    pub items: Vec<WorkspaceCreateParam>,
+}
+
+impl Validate for WorkspaceBulkCreateParam {
+    fn validate(&self, v: &mut Validator) {
+        v.items("items", self.items.len(), BULK_MAX_ITEMS);
+    }
+}
+
+/// 批量部分更新工作区，所有工作区修改为相同的字段
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkspaceBulkPatchParam {
+    pub ids: Vec<String>,
+    #[serde(flatten)]
+    pub patch: WorkspacePatchParam,
+}
+
+impl Validate for WorkspaceBulkPatchParam {
+    fn validate(&self, v: &mut Validator) {
+        v.items("ids", self.ids.len(), BULK_MAX_ITEMS);
+        v.nested("", &self.patch);
+    }
+}
+
+/// 批量删除工作区
+#[derive(Serialize, Deserialize, Debug)]
+pub struct WorkspaceBulkDeleteParam {
+    pub ids: Vec<String>,
+}
+
+impl Validate for WorkspaceBulkDeleteParam {
+    fn validate(&self, v: &mut Validator) {
+        v.items("ids", self.ids.len(), BULK_MAX_ITEMS);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CatalogCreateParam {
+    pub name: String,
+    pub description: String,
+}
+
+impl Validate for CatalogCreateParam {
+    fn validate(&self, v: &mut Validator) {
+        validate_name_and_description(v, &self.name, &self.description);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct CatalogUpdateParam {
+    pub name: String,
+    pub description: String,
+}
+
+impl Validate for CatalogUpdateParam {
+    fn validate(&self, v: &mut Validator) {
+        validate_name_and_description(v, &self.name, &self.description);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TableCreateParam {
+    pub name: String,
+    pub description: String,
+    #[serde(default)]
+    pub columns: Vec<ColumnParam>,
+}
+
+impl Validate for TableCreateParam {
+    fn validate(&self, v: &mut Validator) {
+        validate_name_and_description(v, &self.name, &self.description);
+        for (i, column) in self.columns.iter().enumerate() {
+            v.nested(&format!("columns[{}]", i), column);
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TableUpdateParam {
+    pub name: String,
+    pub description: String,
+}
+
+impl Validate for TableUpdateParam {
+    fn validate(&self, v: &mut Validator) {
+        validate_name_and_description(v, &self.name, &self.description);
+    }
+}
+
+/// 列定义，nullable默认为true，unique默认为false
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ColumnParam {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+    #[serde(default = "default_nullable")]
+    pub nullable: bool,
+    #[serde(default)]
+    pub default: Option<Value>,
+    #[serde(default)]
+    pub unique: bool,
+}
+
+impl Validate for ColumnParam {
+    fn validate(&self, v: &mut Validator) {
+        validate_column_name(v, "name", &self.name);
+        validate_column_type(v, &self.column_type);
+    }
+}
+
+fn default_nullable() -> bool {
+    true
+}
+
+impl From<ColumnParam> for Column {
+    fn from(param: ColumnParam) -> Self {
+        Column {
+            name: param.name,
+            column_type: param.column_type,
+            nullable: param.nullable,
+            default: param.default,
+            unique: param.unique,
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ColumnAddParam {
+    #[serde(flatten)]
+    pub column: ColumnParam,
+    pub position: Option<usize>,
+}
+
+impl Validate for ColumnAddParam {
+    fn validate(&self, v: &mut Validator) {
+        v.nested("", &self.column);
+    }
+}
+
+/// 路径中的列名，warp不会解码路径参数，列名中的空格和非ASCII字符需要先按百分号编码解码
+#[derive(Debug)]
+pub struct ColumnNameParam(pub String);
+
+impl FromStr for ColumnNameParam {
+    type Err = Utf8Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let name = percent_decode_str(s).decode_utf8()?;
+        Ok(ColumnNameParam(name.into_owned()))
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ColumnRenameParam {
+    pub name: String,
+}
+
+impl Validate for ColumnRenameParam {
+    fn validate(&self, v: &mut Validator) {
+        validate_column_name(v, "name", &self.name);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ColumnReorderParam {
+    pub names: Vec<String>,
+}
+
+impl Validate for ColumnReorderParam {
+    fn validate(&self, v: &mut Validator) {
+        for (i, name) in self.names.iter().enumerate() {
+            v.field(&format!("names[{}]", i), name).required();
+        }
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct ColumnRetypeParam {
+    #[serde(rename = "type")]
+    pub column_type: ColumnType,
+}
+
+impl Validate for ColumnRetypeParam {
+    fn validate(&self, v: &mut Validator) {
+        validate_column_type(v, &self.column_type);
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserRegisterParam {
+    pub username: String,
+    pub password: String,
+    #[serde(default)]
+    pub display_name: Option<String>,
+}
+
+impl Validate for UserRegisterParam {
+    fn validate(&self, v: &mut Validator) {
+        v.field("username", &self.username).required();
+        v.field("password", &self.password).required();
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct PasswordChangeParam {
+    pub old_password: String,
+    pub new_password: String,
+}
+
+impl Validate for PasswordChangeParam {
+    fn validate(&self, v: &mut Validator) {
+        v.field("oldPassword", &self.old_password).required();
+        v.field("newPassword", &self.new_password).required();
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct LoginParam {
+    pub username: String,
+    pub password: String,
+}
+
+impl Validate for LoginParam {
+    fn validate(&self, v: &mut Validator) {
+        v.field("username", &self.username).required();
+        v.field("password", &self.password).required();
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MemberInviteParam {
+    pub username: String,
+    pub role: Role,
+}
+
+impl Validate for MemberInviteParam {
+    fn validate(&self, v: &mut Validator) {
+        v.field("username", &self.username).required();
+    }
+}
+
+#[derive(Serialize, Deserialize, Debug)]
+pub struct MemberRoleParam {
+    pub role: Role,
+}
+
+/// 创建API key，没有expiresAt时永不过期
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct ApiKeyCreateParam {
+    pub name: String,
+    #[serde(default)]
+    pub expires_at: Option<DateTime<Utc>>,
+    #[serde(flatten)]
+    pub scope: KeyScope,
+}
+
+impl Validate for ApiKeyCreateParam {
+    fn validate(&self, v: &mut Validator) {
+        v.field("name", &self.name)
+            .required()
+            .max_length(SHORT_NAME_MAX_LENGTH)
+            .pattern(&NAME_PATTERN, "must not contain control characters");
+        v.optional("workspaceId", self.scope.workspace_id.as_deref())
+            .object_id();
+    }
+}
@@ -0,0 +1,124 @@
+use serde_json::{Map, Value};
+use warp::{Rejection, Reply};
+
+use crate::{
+    entity,
+    repository::Storage,
+    service::{auth::CurrentUser, row::RowService, ServiceError},
+};
+
+use super::{
+    filter::{parse_filter, parse_sort, row_filter_fields},
+    request_object::ListQuery,
+    CursorPage, Page, Response,
+};
+
+/// 向表中插入一行
+pub async fn insert_row<S: Storage>(
+    table_id: String,
+    current_user: CurrentUser,
+    data: Map<String, Value>,
+    row_service: RowService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = row_service
+        .insert_row(&current_user, table_id, data)
+        .await?;
+    Response::<String> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 获取表中的所有行
+pub async fn find_all_row<S: Storage>(
+    table_id: String,
+    current_user: CurrentUser,
+    query: ListQuery,
+    row_service: RowService<S>,
+) -> Result<impl Reply, Rejection> {
+    // 行的可过滤字段来自表的列定义
+    let table = row_service.find_table(&current_user, &table_id).await?;
+    let fields = row_filter_fields(&table.columns);
+    let filter = parse_filter(query.filter.as_deref().unwrap_or_default(), &fields)
+        .map_err(ServiceError::InvalidArgument)?;
+    let sorts = parse_sort(query.sort.as_deref().unwrap_or_default(), &fields)
+        .map_err(ServiceError::InvalidArgument)?;
+    if let Some(cursor_option) = query
+        .cursor_option(&sorts)
+        .map_err(ServiceError::InvalidArgument)?
+    {
+        let res = row_service
+            .find_row_after(&current_user, table_id, filter, &sorts, &cursor_option)
+            .await?;
+        let reply = Response::<CursorPage<entity::Row>> {
+            success: true,
+            data: CursorPage::new(res, &cursor_option),
+        }
+        .to_http_reply()?;
+        return Ok(reply.into_response());
+    }
+    let page_option = query.page_option().map_err(ServiceError::InvalidArgument)?;
+    let res = row_service
+        .find_all_row(&current_user, table_id, filter, &sorts, &page_option)
+        .await?;
+    Response::<Page<entity::Row>> {
+        success: true,
+        data: Page::new(res, &page_option),
+    }
+    .to_http_reply()
+    .map(Reply::into_response)
+}
+
+/// 根据id获取行
+pub async fn get_row_by_id<S: Storage>(
+    table_id: String,
+    id: String,
+    current_user: CurrentUser,
+    row_service: RowService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = row_service.find_by_id(&current_user, table_id, id).await?;
+    let version = res.version;
+    Response::<entity::Row> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply_with_version(version)
+}
+
+/// 更新整行数据
+pub async fn update_row<S: Storage>(
+    table_id: String,
+    id: String,
+    current_user: CurrentUser,
+    expected_version: Option<i64>,
+    data: Map<String, Value>,
+    row_service: RowService<S>,
+) -> Result<impl Reply, Rejection> {
+    let version = row_service
+        .update_row(&current_user, table_id, id, data, expected_version)
+        .await?;
+    Response::<()> {
+        success: true,
+        data: (),
+    }
+    .to_http_reply_with_version(version)
+}
+
+/// 删除行
+pub async fn delete_row_by_id<S: Storage>(
+    table_id: String,
+    id: String,
+    current_user: CurrentUser,
+    expected_version: Option<i64>,
+    row_service: RowService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = row_service
+        .delete_row(&current_user, table_id, id, expected_version)
+        .await?;
+    Response::<()> {
+        success: res,
+        data: (),
+    }
+    .to_http_reply()
+}
@@ -0,0 +1,249 @@
+use warp::{Rejection, Reply};
+
+use crate::{
+    entity,
+    repository::Storage,
+    service::{auth::CurrentUser, table::TableService, ServiceError},
+};
+
+use super::{
+    filter::{common_filter_fields, parse_filter, parse_sort},
+    request_object::{
+        ColumnAddParam, ColumnNameParam, ColumnRenameParam, ColumnReorderParam, ColumnRetypeParam,
+        DeleteQuery, ListQuery, TableCreateParam, TableUpdateParam,
+    },
+    CursorPage, Page, Response,
+};
+
+/// 在目录下创建表
+pub async fn create_table<S: Storage>(
+    catalog_id: String,
+    current_user: CurrentUser,
+    param: TableCreateParam,
+    table_service: TableService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = table_service
+        .create_table(
+            &current_user,
+            catalog_id,
+            param.name,
+            param.description,
+            param.columns.into_iter().map(Into::into).collect(),
+        )
+        .await?;
+    Response::<String> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 获取目录下的所有表
+pub async fn find_all_table<S: Storage>(
+    catalog_id: String,
+    current_user: CurrentUser,
+    query: ListQuery,
+    table_service: TableService<S>,
+) -> Result<impl Reply, Rejection> {
+    let fields = common_filter_fields();
+    let filter = parse_filter(query.filter.as_deref().unwrap_or_default(), &fields)
+        .map_err(ServiceError::InvalidArgument)?;
+    let sorts = parse_sort(query.sort.as_deref().unwrap_or_default(), &fields)
+        .map_err(ServiceError::InvalidArgument)?;
+    if let Some(cursor_option) = query
+        .cursor_option(&sorts)
+        .map_err(ServiceError::InvalidArgument)?
+    {
+        let res = table_service
+            .find_table_after(&current_user, catalog_id, filter, &sorts, &cursor_option)
+            .await?;
+        let reply = Response::<CursorPage<entity::Table>> {
+            success: true,
+            data: CursorPage::new(res, &cursor_option),
+        }
+        .to_http_reply()?;
+        return Ok(reply.into_response());
+    }
+    let page_option = query.page_option().map_err(ServiceError::InvalidArgument)?;
+    let res = table_service
+        .find_all_table(&current_user, catalog_id, filter, &sorts, &page_option)
+        .await?;
+    Response::<Page<entity::Table>> {
+        success: true,
+        data: Page::new(res, &page_option),
+    }
+    .to_http_reply()
+    .map(Reply::into_response)
+}
+
+/// 根据id获取表
+pub async fn get_table_by_id<S: Storage>(
+    catalog_id: String,
+    id: String,
+    current_user: CurrentUser,
+    table_service: TableService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = table_service
+        .find_by_id(&current_user, catalog_id, id)
+        .await?;
+    let version = res.version;
+    Response::<entity::Table> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply_with_version(version)
+}
+
+/// 更新表的名称和描述
+pub async fn update_table_info<S: Storage>(
+    catalog_id: String,
+    id: String,
+    current_user: CurrentUser,
+    expected_version: Option<i64>,
+    update_param: TableUpdateParam,
+    table_service: TableService<S>,
+) -> Result<impl Reply, Rejection> {
+    let version = table_service
+        .update(
+            &current_user,
+            catalog_id,
+            id,
+            update_param.name,
+            update_param.description,
+            expected_version,
+        )
+        .await?;
+    Response::<()> {
+        success: true,
+        data: (),
+    }
+    .to_http_reply_with_version(version)
+}
+
+/// 删除表
+pub async fn delete_table_by_id<S: Storage>(
+    catalog_id: String,
+    id: String,
+    current_user: CurrentUser,
+    expected_version: Option<i64>,
+    query: DeleteQuery,
+    table_service: TableService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = table_service
+        .delete(
+            &current_user,
+            catalog_id,
+            id,
+            expected_version,
+            query.cascade.unwrap_or(false),
+        )
+        .await?;
+    Response::<()> {
+        success: res,
+        data: (),
+    }
+    .to_http_reply()
+}
+
+/// 从回收站恢复表，返回恢复后的表
+pub async fn restore_table<S: Storage>(
+    catalog_id: String,
+    id: String,
+    current_user: CurrentUser,
+    table_service: TableService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = table_service.restore(&current_user, catalog_id, id).await?;
+    let version = res.version;
+    Response::<entity::Table> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply_with_version(version)
+}
+
+/// 添加列
+pub async fn add_column<S: Storage>(
+    table_id: String,
+    current_user: CurrentUser,
+    param: ColumnAddParam,
+    table_service: TableService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = table_service
+        .add_column(&current_user, table_id, param.column.into(), param.position)
+        .await?;
+    Response::<Vec<entity::Column>> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 重命名列
+pub async fn rename_column<S: Storage>(
+    table_id: String,
+    name: ColumnNameParam,
+    current_user: CurrentUser,
+    param: ColumnRenameParam,
+    table_service: TableService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = table_service
+        .rename_column(&current_user, table_id, name.0, param.name)
+        .await?;
+    Response::<Vec<entity::Column>> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 调整列的顺序
+pub async fn reorder_columns<S: Storage>(
+    table_id: String,
+    current_user: CurrentUser,
+    param: ColumnReorderParam,
+    table_service: TableService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = table_service
+        .reorder_columns(&current_user, table_id, param.names)
+        .await?;
+    Response::<Vec<entity::Column>> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 修改列的类型
+pub async fn retype_column<S: Storage>(
+    table_id: String,
+    name: ColumnNameParam,
+    current_user: CurrentUser,
+    param: ColumnRetypeParam,
+    table_service: TableService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = table_service
+        .retype_column(&current_user, table_id, name.0, param.column_type)
+        .await?;
+    Response::<Vec<entity::Column>> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 删除列
+pub async fn drop_column<S: Storage>(
+    table_id: String,
+    name: ColumnNameParam,
+    current_user: CurrentUser,
+    table_service: TableService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = table_service
+        .drop_column(&current_user, table_id, name.0)
+        .await?;
+    Response::<Vec<entity::Column>> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
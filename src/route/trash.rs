@@ -0,0 +1,24 @@
+use warp::{Rejection, Reply};
+
+use crate::{
+    repository::Storage,
+    service::{
+        auth::CurrentUser,
+        trash::{Trash, TrashService},
+    },
+};
+
+use super::Response;
+
+/// 获取回收站中的工作区、目录和表
+pub async fn find_trash<S: Storage>(
+    current_user: CurrentUser,
+    trash_service: TrashService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = trash_service.find_all(&current_user).await?;
+    Response::<Trash> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
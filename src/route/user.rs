@@ -0,0 +1,78 @@
+use serde::Serialize;
+use warp::{Rejection, Reply};
+
+use crate::{
+    entity,
+    repository::Storage,
+    service::{auth::CurrentUser, user::UserService},
+};
+
+use super::{
+    request_object::{PasswordChangeParam, UserRegisterParam},
+    Response,
+};
+
+/// 返回给客户端的用户信息，不包含密码哈希
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct UserInfo {
+    pub id: String,
+    pub username: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub display_name: Option<String>,
+}
+
+impl From<entity::User> for UserInfo {
+    fn from(user: entity::User) -> Self {
+        UserInfo {
+            id: user.id,
+            username: user.username,
+            display_name: user.display_name,
+        }
+    }
+}
+
+/// 注册用户
+pub async fn register_user<S: Storage>(
+    param: UserRegisterParam,
+    user_service: UserService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = user_service
+        .register(param.username, param.password, param.display_name)
+        .await?;
+    Response::<String> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 根据id获取用户
+pub async fn get_user_by_id<S: Storage>(
+    id: String,
+    user_service: UserService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = user_service.find_by_id(id).await?;
+    Response::<UserInfo> {
+        success: true,
+        data: res.into(),
+    }
+    .to_http_reply()
+}
+
+/// 修改密码
+pub async fn change_password<S: Storage>(
+    id: String,
+    current_user: CurrentUser,
+    param: PasswordChangeParam,
+    user_service: UserService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = user_service
+        .change_password(&current_user, id, param.old_password, param.new_password)
+        .await?;
+    Response::<()> {
+        success: res,
+        data: (),
+    }
+    .to_http_reply()
+}
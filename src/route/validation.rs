@@ -0,0 +1,123 @@
+use std::str::FromStr;
+
+use mongodb::bson::oid::ObjectId;
+use regex::Regex;
+
+use crate::service::{FieldError, ServiceError};
+
+/// 请求对象的声明式校验，在处理函数执行前由`validated_body`过滤器调用
+pub trait Validate {
+    fn validate(&self, v: &mut Validator);
+}
+
+/// 校验请求对象，收集所有字段的错误，有错误时返回InvalidRequest
+pub fn validate<T: Validate>(value: &T) -> Result<(), ServiceError> {
+    let mut validator = Validator::default();
+    value.validate(&mut validator);
+    if validator.errors.is_empty() {
+        Ok(())
+    } else {
+        Err(ServiceError::InvalidRequest(validator.errors))
+    }
+}
+
+/// 收集校验错误
+#[derive(Default)]
+pub struct Validator {
+    errors: Vec<FieldError>,
+}
+
+impl Validator {
+    /// 对必填的字段声明规则
+    pub fn field<'v, 'a>(&'v mut self, name: &str, value: &'a str) -> Field<'v, 'a> {
+        Field {
+            validator: self,
+            name: name.to_string(),
+            value: Some(value),
+        }
+    }
+
+    /// 对可选的字段声明规则，值为None时跳过所有规则
+    pub fn optional<'v, 'a>(&'v mut self, name: &str, value: Option<&'a str>) -> Field<'v, 'a> {
+        Field {
+            validator: self,
+            name: name.to_string(),
+            value,
+        }
+    }
+
+    /// 校验嵌套的对象，错误的字段名加上前缀，例如`columns[0].name`
+    pub fn nested<T: Validate>(&mut self, prefix: &str, value: &T) {
+        let mut nested = Validator::default();
+        value.validate(&mut nested);
+        for error in nested.errors {
+            let field = if prefix.is_empty() {
+                error.field
+            } else {
+                format!("{}.{}", prefix, error.field)
+            };
+            self.errors.push(FieldError {
+                field,
+                message: error.message,
+            });
+        }
+    }
+
+    /// 列表的条目数量在1到max之间
+    pub fn items(&mut self, name: &str, len: usize, max: usize) {
+        if len == 0 {
+            self.error(name, String::from("must not be empty"));
+        } else if len > max {
+            self.error(name, format!("must not contain more than {} items", max));
+        }
+    }
+
+    fn error(&mut self, field: &str, message: String) {
+        self.errors.push(FieldError::new(field, message));
+    }
+}
+
+/// 某个字段上的规则，按声明的顺序执行，一个规则失败后不再执行后面的规则
+pub struct Field<'v, 'a> {
+    validator: &'v mut Validator,
+    name: String,
+    value: Option<&'a str>,
+}
+
+impl<'v, 'a> Field<'v, 'a> {
+    fn check(mut self, ok: impl FnOnce(&str) -> bool, message: impl FnOnce() -> String) -> Self {
+        if let Some(value) = self.value {
+            if !ok(value) {
+                self.validator.error(&self.name, message());
+                self.value = None;
+            }
+        }
+        self
+    }
+
+    /// 不能为空或只有空白字符
+    pub fn required(self) -> Self {
+        self.check(|v| !v.trim().is_empty(), || String::from("is required"))
+    }
+
+    /// 字符数不超过max
+    pub fn max_length(self, max: usize) -> Self {
+        self.check(
+            |v| v.chars().count() <= max,
+            || format!("length must not exceed {}", max),
+        )
+    }
+
+    /// 匹配正则表达式，message说明格式要求
+    pub fn pattern(self, pattern: &Regex, message: &str) -> Self {
+        self.check(|v| pattern.is_match(v), || message.to_string())
+    }
+
+    /// 合法的ObjectId
+    pub fn object_id(self) -> Self {
+        self.check(
+            |v| ObjectId::from_str(v).is_ok(),
+            || String::from("must be a valid id"),
+        )
+    }
+}
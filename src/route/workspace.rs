@@ -1,19 +1,28 @@
 use warp::{Rejection, Reply};
 
-use crate::{entity, service::workspace::WorkspaceService};
+use crate::{
+    entity,
+    repository::Storage,
+    service::{auth::CurrentUser, workspace::WorkspaceService, ServiceError},
+};
 
 use super::{
-    request_object::{WorkspaceCreateParam, WorkspaceUpdateParam},
-    Response,
+    filter::{common_filter_fields, parse_filter, parse_sort},
+    request_object::{
+        DeleteQuery, ListQuery, WorkspaceBulkCreateParam, WorkspaceBulkDeleteParam,
+        WorkspaceBulkPatchParam, WorkspaceCreateParam, WorkspacePatchParam, WorkspaceUpdateParam,
+    },
+    validation, BulkResult, CursorPage, Page, Response,
 };
 
 /// 创建工作区
-pub async fn create_workspace(
+pub async fn create_workspace<S: Storage>(
+    current_user: CurrentUser,
     param: WorkspaceCreateParam,
-    workspace_service: WorkspaceService,
+    workspace_service: WorkspaceService<S>,
 ) -> Result<impl Reply, Rejection> {
     let res = workspace_service
-        .create_workspace(param.name, param.description, param.creator)
+        .create_workspace(&current_user, param.name, param.description)
         .await?;
     Response::<String> {
         success: true,
@@ -23,55 +32,222 @@ pub async fn create_workspace(
 }
 
 /// 获取所有工作区
-pub async fn find_all_workspace(
-    workspace_service: WorkspaceService,
+pub async fn find_all_workspace<S: Storage>(
+    current_user: CurrentUser,
+    query: ListQuery,
+    workspace_service: WorkspaceService<S>,
 ) -> Result<impl Reply, warp::Rejection> {
-    let res = workspace_service.find_all_workspace().await?;
-    Response::<Vec<entity::Workspace>> {
+    let fields = common_filter_fields();
+    let filter = parse_filter(query.filter.as_deref().unwrap_or_default(), &fields)
+        .map_err(ServiceError::InvalidArgument)?;
+    let sorts = parse_sort(query.sort.as_deref().unwrap_or_default(), &fields)
+        .map_err(ServiceError::InvalidArgument)?;
+    if let Some(cursor_option) = query
+        .cursor_option(&sorts)
+        .map_err(ServiceError::InvalidArgument)?
+    {
+        let res = workspace_service
+            .find_workspace_after(&current_user, filter, &sorts, &cursor_option)
+            .await?;
+        let reply = Response::<CursorPage<entity::Workspace>> {
+            success: true,
+            data: CursorPage::new(res, &cursor_option),
+        }
+        .to_http_reply()?;
+        return Ok(reply.into_response());
+    }
+    let page_option = query.page_option().map_err(ServiceError::InvalidArgument)?;
+    let res = workspace_service
+        .find_all_workspace(&current_user, filter, &sorts, &page_option)
+        .await?;
+    Response::<Page<entity::Workspace>> {
         success: true,
-        data: res,
+        data: Page::new(res, &page_option),
     }
     .to_http_reply()
+    .map(Reply::into_response)
 }
 
 /// 根据id获取工作区
-pub async fn get_workspace_by_id(
+pub async fn get_workspace_by_id<S: Storage>(
     id: String,
-    workspace_service: WorkspaceService,
+    current_user: CurrentUser,
+    workspace_service: WorkspaceService<S>,
 ) -> Result<impl Reply, Rejection> {
-    let res = workspace_service.find_by_id(id).await?;
+    let res = workspace_service.find_by_id(&current_user, id).await?;
+    let version = res.version;
     Response::<entity::Workspace> {
         success: true,
         data: res,
     }
-    .to_http_reply()
+    .to_http_reply_with_version(version)
 }
 
 /// 更新工作区的名称和描述
-pub async fn update_workspace_info(
+pub async fn update_workspace_info<S: Storage>(
     id: String,
+    current_user: CurrentUser,
+    expected_version: Option<i64>,
     update_param: WorkspaceUpdateParam,
-    workspace_service: WorkspaceService,
+    workspace_service: WorkspaceService<S>,
 ) -> Result<impl Reply, Rejection> {
-    let res = workspace_service
-        .update(id, update_param.name, update_param.description)
+    let version = workspace_service
+        .update(
+            &current_user,
+            id,
+            update_param.name,
+            update_param.description,
+            expected_version,
+        )
         .await?;
     Response::<()> {
-        success: res,
+        success: true,
         data: (),
     }
-    .to_http_reply()
+    .to_http_reply_with_version(version)
+}
+
+/// 部分更新工作区，返回更新后的工作区
+pub async fn patch_workspace<S: Storage>(
+    id: String,
+    current_user: CurrentUser,
+    expected_version: Option<i64>,
+    patch_param: WorkspacePatchParam,
+    workspace_service: WorkspaceService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = workspace_service
+        .patch(
+            &current_user,
+            id,
+            patch_param.name,
+            patch_param.description,
+            expected_version,
+        )
+        .await?;
+    let version = res.version;
+    Response::<entity::Workspace> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply_with_version(version)
 }
 
 /// 删除工作区
-pub async fn delete_workspace_by_id(
+pub async fn delete_workspace_by_id<S: Storage>(
     id: String,
-    workspace_service: WorkspaceService,
+    current_user: CurrentUser,
+    expected_version: Option<i64>,
+    query: DeleteQuery,
+    workspace_service: WorkspaceService<S>,
 ) -> Result<impl Reply, Rejection> {
-    let res = workspace_service.delete(id).await?;
+    let res = workspace_service
+        .delete(
+            &current_user,
+            id,
+            expected_version,
+            query.cascade.unwrap_or(false),
+        )
+        .await?;
     Response::<()> {
         success: res,
         data: (),
     }
     .to_http_reply()
 }
+
+/// 批量创建工作区，校验失败的条目不会创建，其余条目在一个事务中一起创建
+pub async fn bulk_create_workspace<S: Storage>(
+    current_user: CurrentUser,
+    param: WorkspaceBulkCreateParam,
+    workspace_service: WorkspaceService<S>,
+) -> Result<impl Reply, Rejection> {
+    let mut checked = Vec::with_capacity(param.items.len());
+    let mut valid = vec![];
+    for item in param.items {
+        let result = validation::validate(&item);
+        if result.is_ok() {
+            valid.push((item.name, item.description));
+        }
+        checked.push(result);
+    }
+    let expected = valid.len();
+    let ids = workspace_service.create_many(&current_user, valid).await?;
+    // 每个校验通过的条目都要有对应的id，数量不一致时不能把id对应到条目上
+    if ids.len() != expected {
+        return Err(ServiceError::InternalError(format!(
+            "created {} workspaces for {} items",
+            ids.len(),
+            expected
+        ))
+        .into());
+    }
+    let mut ids = ids.into_iter();
+    let results = checked
+        .into_iter()
+        .filter_map(|result| match result {
+            Ok(_) => ids.next().map(Ok),
+            Err(e) => Some(Err(e)),
+        })
+        .collect();
+    let res = BulkResult::new(results);
+    Response::<BulkResult> {
+        success: res.failed == 0,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 批量部分更新工作区，返回每个工作区的结果
+pub async fn bulk_patch_workspace<S: Storage>(
+    current_user: CurrentUser,
+    param: WorkspaceBulkPatchParam,
+    workspace_service: WorkspaceService<S>,
+) -> Result<impl Reply, Rejection> {
+    let results = workspace_service
+        .patch_many(
+            &current_user,
+            param.ids,
+            param.patch.name,
+            param.patch.description,
+        )
+        .await?;
+    let res = BulkResult::new(results);
+    Response::<BulkResult> {
+        success: res.failed == 0,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 批量删除工作区，返回每个工作区的结果
+pub async fn bulk_delete_workspace<S: Storage>(
+    current_user: CurrentUser,
+    query: DeleteQuery,
+    param: WorkspaceBulkDeleteParam,
+    workspace_service: WorkspaceService<S>,
+) -> Result<impl Reply, Rejection> {
+    let results = workspace_service
+        .delete_many(&current_user, param.ids, query.cascade.unwrap_or(false))
+        .await?;
+    let res = BulkResult::new(results);
+    Response::<BulkResult> {
+        success: res.failed == 0,
+        data: res,
+    }
+    .to_http_reply()
+}
+
+/// 从回收站恢复工作区，返回恢复后的工作区
+pub async fn restore_workspace<S: Storage>(
+    id: String,
+    current_user: CurrentUser,
+    workspace_service: WorkspaceService<S>,
+) -> Result<impl Reply, Rejection> {
+    let res = workspace_service.restore(&current_user, id).await?;
+    let version = res.version;
+    Response::<entity::Workspace> {
+        success: true,
+        data: res,
+    }
+    .to_http_reply_with_version(version)
+}
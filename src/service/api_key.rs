@@ -0,0 +1,139 @@
+use std::str::FromStr;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    entity::{self, KeyScope, Role},
+    repository::{
+        condition::{Condition, ConditionValue, Operate, SortOption, SortOrder},
+        CRUDRepository, RepositoryError, Storage,
+    },
+};
+
+use super::{auth::CurrentUser, membership::Access, FieldError, ServiceError};
+
+/// API key的前缀，认证时用来区分API key和登录令牌
+pub const API_KEY_PREFIX: &str = "ck_";
+/// 保存下来用于辨认key的长度，包含前缀
+const DISPLAY_PREFIX_LENGTH: usize = 10;
+
+/// 生成新的key，32字节随机数
+fn generate_key() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    format!(
+        "{}{}",
+        API_KEY_PREFIX,
+        base64::encode_config(bytes, base64::URL_SAFE_NO_PAD)
+    )
+}
+
+/// key本身是高熵的随机数，用SHA-256保存就足够，并且可以直接按哈希查找
+pub fn hash_key(key: &str) -> String {
+    format!("{:x}", Sha256::digest(key.as_bytes()))
+}
+
+#[derive(Clone)]
+pub struct ApiKeyService<S: Storage> {
+    repo: S::ApiKeyRepo,
+    access: Access<S>,
+}
+
+impl<S: Storage> ApiKeyService<S> {
+    pub fn new(storage: &S) -> Self {
+        Self {
+            repo: storage.api_key_repo(),
+            access: Access::new(storage),
+        }
+    }
+
+    /// 创建API key，返回保存的key和key的明文
+    ///
+    /// 限定工作区时，当前用户必须是工作区的成员
+    pub async fn create(
+        &self,
+        operator: &CurrentUser,
+        name: String,
+        scope: KeyScope,
+        expires_at: Option<DateTime<Utc>>,
+    ) -> Result<(entity::ApiKey, String), ServiceError> {
+        operator.require_session()?;
+        let now = Utc::now();
+        if matches!(expires_at, Some(t) if t <= now) {
+            return Err(ServiceError::ValidationError(vec![FieldError::new(
+                "expiresAt",
+                String::from("must be in the future"),
+            )]));
+        }
+        if let Some(workspace_id) = &scope.workspace_id {
+            self.access
+                .require_workspace(operator, workspace_id, Role::Viewer)
+                .await?;
+        }
+        let key = generate_key();
+        let mut api_key = entity::ApiKey {
+            id: String::new(),
+            user_id: operator.id.clone(),
+            name: name.trim().to_string(),
+            prefix: key.chars().take(DISPLAY_PREFIX_LENGTH).collect(),
+            key_hash: hash_key(&key),
+            scope,
+            expires_at,
+            last_used_at: None,
+            created_at: now,
+            updated_at: now,
+            version: 0,
+        };
+        api_key.id = self.repo.create(&api_key).await?;
+        Ok((api_key, key))
+    }
+
+    /// 当前用户的所有API key，新创建的在前
+    pub async fn find_all(
+        &self,
+        operator: &CurrentUser,
+    ) -> Result<Vec<entity::ApiKey>, ServiceError> {
+        operator.require_session()?;
+        let result = self
+            .repo
+            .find(
+                &Condition::single(
+                    String::from("userId"),
+                    Operate::Eq,
+                    ConditionValue::StringValue(operator.id.clone()),
+                ),
+                &[SortOption::new("createdAt", SortOrder::Desc)],
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// 撤销API key，只能撤销自己的key
+    pub async fn revoke(&self, operator: &CurrentUser, id: String) -> Result<bool, ServiceError> {
+        operator.require_session()?;
+        let oid = ObjectId::from_str(&id)?;
+        let result = self
+            .repo
+            .delete(&Condition::and(vec![
+                (
+                    String::from("_id"),
+                    Operate::Eq,
+                    ConditionValue::ObjectIdValue(oid),
+                ),
+                (
+                    String::from("userId"),
+                    Operate::Eq,
+                    ConditionValue::StringValue(operator.id.clone()),
+                ),
+            ]))
+            .await?;
+        if result {
+            Ok(result)
+        } else {
+            Err(RepositoryError::DataNotFound.into())
+        }
+    }
+}
@@ -0,0 +1,257 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use jsonwebtoken::{decode, encode, DecodingKey, EncodingKey, Header, Validation};
+use mongodb::bson::oid::ObjectId;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    entity::{KeyScope, Role, UserSummary},
+    repository::{
+        condition::{Condition, ConditionValue, Operate, Patch},
+        CRUDRepository, RepositoryError, Storage,
+    },
+};
+
+use super::{
+    api_key::{hash_key, API_KEY_PREFIX},
+    user::{verify_dummy_password, verify_password},
+    ServiceError,
+};
+
+/// 签名令牌的密钥的最小字节数
+const MIN_SECRET_LENGTH: usize = 32;
+/// .env中原来的示例密钥
+const EXAMPLE_SECRET: &str = "change-me";
+
+/// 检查签名令牌的密钥，知道示例密钥或者能猜出短密钥的人可以伪造任何用户的令牌
+pub fn check_secret(secret: &str) -> Result<(), String> {
+    if secret == EXAMPLE_SECRET {
+        Err(String::from("the example secret must not be used"))
+    } else if secret.len() < MIN_SECRET_LENGTH {
+        Err(format!(
+            "the secret must be at least {} bytes",
+            MIN_SECRET_LENGTH
+        ))
+    } else {
+        Ok(())
+    }
+}
+
+/// 使用API key时，lastUsedAt最多每隔这么多秒更新一次，避免每个请求都写一次
+const LAST_USED_INTERVAL_SECONDS: i64 = 60;
+
+/// 当前登录的用户
+#[derive(Clone, Debug)]
+pub struct CurrentUser {
+    pub id: String,
+    pub username: String,
+    /// 通过API key认证时key的权限范围，登录令牌没有限制
+    pub scope: Option<KeyScope>,
+}
+
+impl CurrentUser {
+    /// 作为创建者保存时的用户信息
+    pub fn summary(&self) -> UserSummary {
+        UserSummary {
+            id: self.id.clone(),
+            username: self.username.clone(),
+            display_name: None,
+        }
+    }
+
+    /// 只允许通过登录令牌操作，例如修改密码和管理API key
+    pub fn require_session(&self) -> Result<(), ServiceError> {
+        match self.scope {
+            None => Ok(()),
+            Some(_) => Err(ServiceError::Forbidden(String::from(
+                "this operation is not allowed with an api key",
+            ))),
+        }
+    }
+
+    /// 检查API key的权限范围是否允许在工作区中进行需要某个角色的操作
+    pub fn check_scope(&self, workspace_id: &str, required: Role) -> Result<(), ServiceError> {
+        let scope = match &self.scope {
+            Some(scope) => scope,
+            None => return Ok(()),
+        };
+        if scope.read_only && required > Role::Viewer {
+            return Err(ServiceError::Forbidden(String::from(
+                "api key is read-only",
+            )));
+        }
+        match &scope.workspace_id {
+            Some(id) if id != workspace_id => Err(ServiceError::Forbidden(String::from(
+                "api key is restricted to another workspace",
+            ))),
+            _ => Ok(()),
+        }
+    }
+
+    /// 创建工作区需要不受限制的权限
+    pub fn check_unrestricted(&self) -> Result<(), ServiceError> {
+        match &self.scope {
+            Some(scope) if scope.read_only || scope.workspace_id.is_some() => {
+                Err(ServiceError::Forbidden(String::from(
+                    "api key is restricted and cannot create workspaces",
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// 令牌中保存的信息
+#[derive(Serialize, Deserialize, Debug)]
+struct Claims {
+    sub: String,
+    username: String,
+    iat: i64,
+    exp: i64,
+}
+
+/// 登录后签发的令牌
+pub struct Token {
+    pub token: String,
+    pub expires_at: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+pub struct AuthService<S: Storage> {
+    user_repo: S::UserRepo,
+    api_key_repo: S::ApiKeyRepo,
+    secret: Vec<u8>,
+    ttl: Duration,
+}
+
+impl<S: Storage> AuthService<S> {
+    /// secret是签名令牌的密钥，ttl_seconds是令牌的有效期
+    pub fn new(storage: &S, secret: &str, ttl_seconds: u64) -> Self {
+        Self {
+            user_repo: storage.user_repo(),
+            api_key_repo: storage.api_key_repo(),
+            secret: secret.as_bytes().to_vec(),
+            ttl: Duration::seconds(ttl_seconds as i64),
+        }
+    }
+
+    /// 校验用户名和密码，成功时签发令牌
+    ///
+    /// 用户不存在和密码错误返回同样的错误，不暴露用户是否存在；
+    /// 用户不存在时同样校验一次密码，响应时间也不暴露用户是否存在
+    pub async fn login(&self, username: String, password: String) -> Result<Token, ServiceError> {
+        let invalid = || ServiceError::Unauthorized(String::from("invalid username or password"));
+        let user = match self
+            .user_repo
+            .find_one(&Condition::single(
+                String::from("username"),
+                Operate::Eq,
+                ConditionValue::StringValue(username),
+            ))
+            .await
+        {
+            Ok(user) => user,
+            Err(RepositoryError::DataNotFound) => {
+                verify_dummy_password(password).await?;
+                return Err(invalid());
+            }
+            Err(e) => return Err(e.into()),
+        };
+        if !verify_password(password, user.password_hash).await? {
+            return Err(invalid());
+        }
+        let now = Utc::now();
+        let expires_at = now + self.ttl;
+        let claims = Claims {
+            sub: user.id,
+            username: user.username,
+            iat: now.timestamp(),
+            exp: expires_at.timestamp(),
+        };
+        let token = encode(
+            &Header::default(),
+            &claims,
+            &EncodingKey::from_secret(&self.secret),
+        )
+        .map_err(|e| ServiceError::InternalError(e.to_string()))?;
+        Ok(Token { token, expires_at })
+    }
+
+    /// 校验令牌或API key，返回对应的用户
+    pub async fn authenticate(&self, token: &str) -> Result<CurrentUser, ServiceError> {
+        if token.starts_with(API_KEY_PREFIX) {
+            return self.authenticate_api_key(token).await;
+        }
+        let data = decode::<Claims>(
+            token,
+            &DecodingKey::from_secret(&self.secret),
+            &Validation::default(),
+        )
+        .map_err(|e| ServiceError::Unauthorized(e.to_string()))?;
+        Ok(CurrentUser {
+            id: data.claims.sub,
+            username: data.claims.username,
+            scope: None,
+        })
+    }
+
+    /// 按哈希查找API key，检查是否过期，并记录使用时间
+    async fn authenticate_api_key(&self, key: &str) -> Result<CurrentUser, ServiceError> {
+        let invalid = || ServiceError::Unauthorized(String::from("invalid api key"));
+        let api_key = match self
+            .api_key_repo
+            .find_one(&Condition::single(
+                String::from("keyHash"),
+                Operate::Eq,
+                ConditionValue::StringValue(hash_key(key)),
+            ))
+            .await
+        {
+            Ok(api_key) => api_key,
+            Err(RepositoryError::DataNotFound) => return Err(invalid()),
+            Err(e) => return Err(e.into()),
+        };
+        let now = Utc::now();
+        if matches!(api_key.expires_at, Some(t) if t <= now) {
+            return Err(ServiceError::Unauthorized(String::from(
+                "api key has expired",
+            )));
+        }
+        let user = match self
+            .user_repo
+            .find_one(&Condition::single(
+                String::from("_id"),
+                Operate::Eq,
+                ConditionValue::ObjectIdValue(ObjectId::from_str(&api_key.user_id)?),
+            ))
+            .await
+        {
+            Ok(user) => user,
+            Err(RepositoryError::DataNotFound) => return Err(invalid()),
+            Err(e) => return Err(e.into()),
+        };
+        let stale = match api_key.last_used_at {
+            Some(t) => now - t >= Duration::seconds(LAST_USED_INTERVAL_SECONDS),
+            None => true,
+        };
+        // 只更新lastUsedAt，同时使用同一个key的请求不会因为版本号冲突而失败
+        if stale {
+            self.api_key_repo
+                .patch(
+                    &Condition::single(
+                        String::from("_id"),
+                        Operate::Eq,
+                        ConditionValue::ObjectIdValue(ObjectId::from_str(&api_key.id)?),
+                    ),
+                    &Patch::new().set("lastUsedAt", ConditionValue::DateTimeValue(now)),
+                )
+                .await?;
+        }
+        Ok(CurrentUser {
+            id: user.id,
+            username: user.username,
+            scope: Some(api_key.scope),
+        })
+    }
+}
@@ -0,0 +1,509 @@
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    entity,
+    repository::{
+        condition::{Condition, ConditionValue, Operate, PageOption},
+        soft_delete::{Deletion, SoftDeleteRepository},
+        CRUDRepository, PaginationRepository, RepositoryError, Storage,
+    },
+};
+
+use super::{membership::Access, version::soft_delete_versioned, Dependent, ServiceError};
+
+/// 409中最多列出的下级数据数量
+const DEPENDENTS_LIMIT: usize = 20;
+
+fn parent_condition(field: &str, parent_id: &str) -> Condition {
+    Condition::single(
+        String::from(field),
+        Operate::Eq,
+        ConditionValue::StringValue(parent_id.to_string()),
+    )
+}
+
+fn parents_condition(field: &str, parent_ids: Vec<String>) -> Condition {
+    Condition::single(
+        String::from(field),
+        Operate::In,
+        ConditionValue::StringVecValue(parent_ids),
+    )
+}
+
+fn id_condition(id: &str) -> Result<Condition, ServiceError> {
+    Ok(Condition::single(
+        String::from("_id"),
+        Operate::Eq,
+        ConditionValue::ObjectIdValue(ObjectId::from_str(id)?),
+    ))
+}
+
+/// 有下级数据时返回HasDependents
+fn ensure_empty(count: i64, dependents: Vec<Dependent>) -> Result<(), ServiceError> {
+    if count == 0 {
+        Ok(())
+    } else {
+        Err(ServiceError::HasDependents { count, dependents })
+    }
+}
+
+fn dependents_page() -> PageOption {
+    PageOption {
+        page: 1,
+        size: DEPENDENTS_LIMIT,
+    }
+}
+
+/// 已删除的数据的删除时间，没有找到已删除的数据时返回DataNotFound
+fn deleted_time(deleted_at: Option<DateTime<Utc>>) -> Result<DateTime<Utc>, ServiceError> {
+    deleted_at.ok_or_else(|| RepositoryError::DataNotFound.into())
+}
+
+/// 工作区、目录、表和行之间的引用关系
+///
+/// 不级联时有下级数据的上级不能删除；级联删除时目录和表和上级在同一个事务中进入回收站，
+/// 删除时间和上级相同，恢复上级时一起恢复。行没有删除标记，表在回收站中时行不能访问，
+/// 表恢复后行也就恢复了。上级被彻底删除时下级也在同一个事务中一起彻底删除
+#[derive(Clone)]
+pub struct Cascade<S: Storage> {
+    storage: S,
+}
+
+impl<S: Storage> Cascade<S> {
+    pub fn new(storage: &S) -> Self {
+        Self {
+            storage: storage.clone(),
+        }
+    }
+
+    /// 软删除满足条件的工作区，返回是否删除了工作区
+    ///
+    /// 下级数据的检查和删除在一个事务中完成，中途失败时什么都不会删除
+    pub async fn delete_workspace(
+        &self,
+        workspace_id: &str,
+        condition: &Condition,
+        expected_version: Option<i64>,
+        deletion: &Deletion,
+        cascade: bool,
+    ) -> Result<bool, ServiceError> {
+        let workspace_id = workspace_id.to_string();
+        let condition = condition.clone();
+        let deletion = deletion.clone();
+        self.storage
+            .with_transaction(move |tx| {
+                let workspace_id = workspace_id.clone();
+                let condition = condition.clone();
+                let deletion = deletion.clone();
+                Box::pin(async move {
+                    delete_workspace(
+                        tx,
+                        &workspace_id,
+                        condition,
+                        expected_version,
+                        &deletion,
+                        cascade,
+                    )
+                    .await
+                })
+            })
+            .await
+    }
+
+    /// 软删除满足条件的目录，返回是否删除了目录
+    pub async fn delete_catalog(
+        &self,
+        catalog_id: &str,
+        condition: &Condition,
+        expected_version: Option<i64>,
+        deletion: &Deletion,
+        cascade: bool,
+    ) -> Result<bool, ServiceError> {
+        let catalog_id = catalog_id.to_string();
+        let condition = condition.clone();
+        let deletion = deletion.clone();
+        self.storage
+            .with_transaction(move |tx| {
+                let catalog_id = catalog_id.clone();
+                let condition = condition.clone();
+                let deletion = deletion.clone();
+                Box::pin(async move {
+                    delete_catalog(
+                        tx,
+                        &catalog_id,
+                        condition,
+                        expected_version,
+                        &deletion,
+                        cascade,
+                    )
+                    .await
+                })
+            })
+            .await
+    }
+
+    /// 软删除满足条件的表，返回是否删除了表
+    pub async fn delete_table(
+        &self,
+        table_id: &str,
+        condition: &Condition,
+        expected_version: Option<i64>,
+        deletion: &Deletion,
+        cascade: bool,
+    ) -> Result<bool, ServiceError> {
+        let table_id = table_id.to_string();
+        let condition = condition.clone();
+        let deletion = deletion.clone();
+        self.storage
+            .with_transaction(move |tx| {
+                let table_id = table_id.clone();
+                let condition = condition.clone();
+                let deletion = deletion.clone();
+                Box::pin(async move {
+                    delete_table(
+                        tx,
+                        &table_id,
+                        condition,
+                        expected_version,
+                        &deletion,
+                        cascade,
+                    )
+                    .await
+                })
+            })
+            .await
+    }
+
+    /// 恢复满足条件的工作区，以及和它一起删除的目录和表
+    pub async fn restore_workspace(
+        &self,
+        condition: &Condition,
+    ) -> Result<entity::Workspace, ServiceError> {
+        let condition = condition.clone();
+        self.storage
+            .with_transaction(move |tx| {
+                let condition = condition.clone();
+                Box::pin(async move { restore_workspace(tx, &condition).await })
+            })
+            .await
+    }
+
+    /// 恢复满足条件的目录，以及和它一起删除的表
+    pub async fn restore_catalog(
+        &self,
+        condition: &Condition,
+    ) -> Result<entity::Catalog, ServiceError> {
+        let condition = condition.clone();
+        self.storage
+            .with_transaction(move |tx| {
+                let condition = condition.clone();
+                Box::pin(async move { restore_catalog(tx, &condition).await })
+            })
+            .await
+    }
+
+    /// 彻底删除满足条件的工作区以及其中的所有数据和成员关系，返回是否删除了工作区
+    ///
+    /// 在一个事务中完成，中途失败时什么都不会删除
+    pub async fn purge_workspace(
+        &self,
+        workspace_id: &str,
+        condition: &Condition,
+    ) -> Result<bool, ServiceError> {
+        let workspace_id = workspace_id.to_string();
+        let condition = condition.clone();
+        self.storage
+            .with_transaction(move |tx| {
+                let workspace_id = workspace_id.clone();
+                let condition = condition.clone();
+                Box::pin(async move { purge_workspace(tx, &workspace_id, &condition).await })
+            })
+            .await
+    }
+
+    /// 彻底删除满足条件的目录以及其中的表和行，返回是否删除了目录
+    pub async fn purge_catalog(
+        &self,
+        catalog_id: &str,
+        condition: &Condition,
+    ) -> Result<bool, ServiceError> {
+        let catalog_id = catalog_id.to_string();
+        let condition = condition.clone();
+        self.storage
+            .with_transaction(move |tx| {
+                let catalog_id = catalog_id.clone();
+                let condition = condition.clone();
+                Box::pin(async move { purge_catalog(tx, &catalog_id, &condition).await })
+            })
+            .await
+    }
+
+    /// 彻底删除满足条件的表以及其中的行，返回是否删除了表
+    pub async fn purge_table(
+        &self,
+        table_id: &str,
+        condition: &Condition,
+    ) -> Result<bool, ServiceError> {
+        let table_id = table_id.to_string();
+        let condition = condition.clone();
+        self.storage
+            .with_transaction(move |tx| {
+                let table_id = table_id.clone();
+                let condition = condition.clone();
+                Box::pin(async move { purge_table(tx, &table_id, &condition).await })
+            })
+            .await
+    }
+}
+
+/// 要求工作区中没有目录
+pub async fn ensure_workspace_empty<S: Storage>(
+    tx: &S,
+    workspace_id: &str,
+) -> Result<(), ServiceError> {
+    let result = tx
+        .catalog_repo()
+        .find_page(
+            &parent_condition("workspaceId", workspace_id),
+            &[],
+            &dependents_page(),
+            true,
+        )
+        .await?;
+    let dependents = result
+        .datas
+        .into_iter()
+        .map(|c| Dependent {
+            kind: "catalog",
+            id: c.id,
+            name: Some(c.name),
+        })
+        .collect();
+    ensure_empty(result.count, dependents)
+}
+
+/// 要求目录中没有表
+async fn ensure_catalog_empty<S: Storage>(tx: &S, catalog_id: &str) -> Result<(), ServiceError> {
+    let result = tx
+        .table_repo()
+        .find_page(
+            &parent_condition("catalogId", catalog_id),
+            &[],
+            &dependents_page(),
+            true,
+        )
+        .await?;
+    let dependents = result
+        .datas
+        .into_iter()
+        .map(|t| Dependent {
+            kind: "table",
+            id: t.id,
+            name: Some(t.name),
+        })
+        .collect();
+    ensure_empty(result.count, dependents)
+}
+
+/// 要求表中没有行
+async fn ensure_table_empty<S: Storage>(tx: &S, table_id: &str) -> Result<(), ServiceError> {
+    let result = tx
+        .row_repo()
+        .find_page(
+            &parent_condition("tableId", table_id),
+            &[],
+            &dependents_page(),
+            true,
+        )
+        .await?;
+    let dependents = result
+        .datas
+        .into_iter()
+        .map(|r| Dependent {
+            kind: "row",
+            id: r.id,
+            name: None,
+        })
+        .collect();
+    ensure_empty(result.count, dependents)
+}
+
+async fn delete_workspace<S: Storage>(
+    tx: &S,
+    workspace_id: &str,
+    condition: Condition,
+    expected_version: Option<i64>,
+    deletion: &Deletion,
+    cascade: bool,
+) -> Result<bool, ServiceError> {
+    if !cascade {
+        ensure_workspace_empty(tx, workspace_id).await?;
+    }
+    let repo = tx.workspace_repo();
+    if !soft_delete_versioned(&repo, condition, expected_version, deletion).await? {
+        return Ok(false);
+    }
+    delete_workspace_children(tx, workspace_id, deletion).await?;
+    Ok(true)
+}
+
+/// 工作区中的目录和表使用工作区的删除时间一起进入回收站，已经在回收站中的不变
+pub async fn delete_workspace_children<S: Storage>(
+    tx: &S,
+    workspace_id: &str,
+    deletion: &Deletion,
+) -> Result<(), ServiceError> {
+    let catalogs = parent_condition("workspaceId", workspace_id);
+    let catalog_ids = tx
+        .catalog_repo()
+        .find(&catalogs, &[])
+        .await?
+        .into_iter()
+        .map(|c| c.id)
+        .collect();
+    tx.table_repo()
+        .soft_delete_many(&parents_condition("catalogId", catalog_ids), deletion)
+        .await?;
+    tx.catalog_repo()
+        .soft_delete_many(&catalogs, deletion)
+        .await?;
+    Ok(())
+}
+
+async fn delete_catalog<S: Storage>(
+    tx: &S,
+    catalog_id: &str,
+    condition: Condition,
+    expected_version: Option<i64>,
+    deletion: &Deletion,
+    cascade: bool,
+) -> Result<bool, ServiceError> {
+    let repo = tx.catalog_repo();
+    if !cascade && repo.exist(&condition).await? {
+        ensure_catalog_empty(tx, catalog_id).await?;
+    }
+    if !soft_delete_versioned(&repo, condition, expected_version, deletion).await? {
+        return Ok(false);
+    }
+    tx.table_repo()
+        .soft_delete_many(&parent_condition("catalogId", catalog_id), deletion)
+        .await?;
+    Ok(true)
+}
+
+async fn delete_table<S: Storage>(
+    tx: &S,
+    table_id: &str,
+    condition: Condition,
+    expected_version: Option<i64>,
+    deletion: &Deletion,
+    cascade: bool,
+) -> Result<bool, ServiceError> {
+    let repo = tx.table_repo();
+    if !cascade && repo.exist(&condition).await? {
+        ensure_table_empty(tx, table_id).await?;
+    }
+    soft_delete_versioned(&repo, condition, expected_version, deletion).await
+}
+
+/// 按删除时间找到和工作区一起删除的目录和表，先于工作区删除的下级保留在回收站中
+async fn restore_workspace<S: Storage>(
+    tx: &S,
+    condition: &Condition,
+) -> Result<entity::Workspace, ServiceError> {
+    let repo = tx.workspace_repo();
+    let deleted = repo.find_deleted(condition, &[]).await?;
+    let deleted_at = deleted_time(deleted.first().and_then(|w| w.deleted_at))?;
+    let workspace = repo.restore(condition).await?;
+    let catalogs = Condition::all(vec![
+        parent_condition("workspaceId", &workspace.id),
+        Deletion::deleted_at(deleted_at),
+    ]);
+    let catalog_ids = tx
+        .catalog_repo()
+        .find_deleted(&catalogs, &[])
+        .await?
+        .into_iter()
+        .map(|c| c.id)
+        .collect();
+    tx.table_repo()
+        .restore_many(&Condition::all(vec![
+            parents_condition("catalogId", catalog_ids),
+            Deletion::deleted_at(deleted_at),
+        ]))
+        .await?;
+    tx.catalog_repo().restore_many(&catalogs).await?;
+    Ok(workspace)
+}
+
+async fn restore_catalog<S: Storage>(
+    tx: &S,
+    condition: &Condition,
+) -> Result<entity::Catalog, ServiceError> {
+    let repo = tx.catalog_repo();
+    let deleted = repo.find_deleted(condition, &[]).await?;
+    let deleted_at = deleted_time(deleted.first().and_then(|c| c.deleted_at))?;
+    let catalog = repo.restore(condition).await?;
+    tx.table_repo()
+        .restore_many(&Condition::all(vec![
+            parent_condition("catalogId", &catalog.id),
+            Deletion::deleted_at(deleted_at),
+        ]))
+        .await?;
+    Ok(catalog)
+}
+
+/// 先彻底删除上级，上级不再满足条件时（例如清理期间被恢复）不删除任何下级数据
+async fn purge_workspace<S: Storage>(
+    tx: &S,
+    workspace_id: &str,
+    condition: &Condition,
+) -> Result<bool, ServiceError> {
+    if !tx.workspace_repo().purge(condition).await? {
+        return Ok(false);
+    }
+    let catalogs = tx
+        .catalog_repo()
+        .find_with_deleted(&parent_condition("workspaceId", workspace_id), &[])
+        .await?;
+    for catalog in catalogs {
+        purge_catalog(tx, &catalog.id, &id_condition(&catalog.id)?).await?;
+    }
+    Access::new(tx).revoke_all(workspace_id).await?;
+    Ok(true)
+}
+
+async fn purge_catalog<S: Storage>(
+    tx: &S,
+    catalog_id: &str,
+    condition: &Condition,
+) -> Result<bool, ServiceError> {
+    if !tx.catalog_repo().purge(condition).await? {
+        return Ok(false);
+    }
+    let tables = tx
+        .table_repo()
+        .find_with_deleted(&parent_condition("catalogId", catalog_id), &[])
+        .await?;
+    for table in tables {
+        purge_table(tx, &table.id, &id_condition(&table.id)?).await?;
+    }
+    Ok(true)
+}
+
+async fn purge_table<S: Storage>(
+    tx: &S,
+    table_id: &str,
+    condition: &Condition,
+) -> Result<bool, ServiceError> {
+    if !tx.table_repo().purge(condition).await? {
+        return Ok(false);
+    }
+    tx.row_repo()
+        .delete_many(&parent_condition("tableId", table_id))
+        .await?;
+    Ok(true)
+}
@@ -0,0 +1,247 @@
+use std::{iter, str::FromStr};
+
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    entity::{self, Role},
+    repository::{
+        condition::{Condition, ConditionValue, CursorOption, Operate, PageOption, SortOption},
+        soft_delete::Deletion,
+        CRUDRepository, CursorResult, PageResult, PaginationRepository, RepositoryError, Storage,
+    },
+};
+
+use super::{
+    auth::CurrentUser, cascade::Cascade, membership::Access, user::Creators,
+    version::check_version, ServiceError,
+};
+
+#[derive(Clone)]
+pub struct CatalogService<S: Storage> {
+    repo: S::CatalogRepo,
+    workspace_repo: S::WorkspaceRepo,
+    access: Access<S>,
+    cascade: Cascade<S>,
+    creators: Creators<S>,
+}
+
+/// 限定在某个工作区下的按id查询条件
+fn catalog_condition(workspace_id: &str, oid: ObjectId) -> Condition {
+    Condition::and(vec![
+        (
+            String::from("_id"),
+            Operate::Eq,
+            ConditionValue::ObjectIdValue(oid),
+        ),
+        (
+            String::from("workspaceId"),
+            Operate::Eq,
+            ConditionValue::StringValue(workspace_id.to_string()),
+        ),
+    ])
+}
+
+/// 在过滤条件上限定某个工作区下的目录
+fn in_workspace(workspace_id: String, filter: Condition) -> Condition {
+    Condition::all(vec![
+        Condition::single(
+            String::from("workspaceId"),
+            Operate::Eq,
+            ConditionValue::StringValue(workspace_id),
+        ),
+        filter,
+    ])
+}
+
+impl<S: Storage> CatalogService<S> {
+    pub fn new(storage: &S) -> Self {
+        Self {
+            repo: storage.catalog_repo(),
+            workspace_repo: storage.workspace_repo(),
+            access: Access::new(storage),
+            cascade: Cascade::new(storage),
+            creators: Creators::new(storage),
+        }
+    }
+
+    /// 确认上级工作区存在
+    async fn ensure_workspace_exist(&self, workspace_id: &str) -> Result<(), ServiceError> {
+        let oid = ObjectId::from_str(workspace_id)?;
+        let exist = self
+            .workspace_repo
+            .exist(&Condition::single(
+                String::from("_id"),
+                Operate::Eq,
+                ConditionValue::ObjectIdValue(oid),
+            ))
+            .await?;
+        if exist {
+            Ok(())
+        } else {
+            Err(RepositoryError::DataNotFound.into())
+        }
+    }
+
+    pub async fn create_catalog(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: String,
+        name: String,
+        description: String,
+    ) -> Result<String, ServiceError> {
+        self.ensure_workspace_exist(&workspace_id).await?;
+        self.access
+            .require_workspace(operator, &workspace_id, Role::Editor)
+            .await?;
+        let now = Utc::now();
+        let result = self
+            .repo
+            .create(&entity::Catalog {
+                id: String::new(),
+                workspace_id,
+                name,
+                description,
+                creator: operator.summary(),
+                created_at: now,
+                updated_at: now,
+                version: 0,
+                deleted_at: None,
+                deleted_by: None,
+            })
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn find_all_catalog(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: String,
+        filter: Condition,
+        sorts: &[SortOption],
+        page: &PageOption,
+    ) -> Result<PageResult<entity::Catalog>, ServiceError> {
+        self.ensure_workspace_exist(&workspace_id).await?;
+        self.access
+            .require_workspace(operator, &workspace_id, Role::Viewer)
+            .await?;
+        let mut result = self
+            .repo
+            .find_page(&in_workspace(workspace_id, filter), sorts, page, true)
+            .await?;
+        self.creators
+            .fill(result.datas.iter_mut().map(|c| &mut c.creator))
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn find_catalog_after(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: String,
+        filter: Condition,
+        sorts: &[SortOption],
+        cursor_option: &CursorOption,
+    ) -> Result<CursorResult<entity::Catalog>, ServiceError> {
+        self.ensure_workspace_exist(&workspace_id).await?;
+        self.access
+            .require_workspace(operator, &workspace_id, Role::Viewer)
+            .await?;
+        let mut result = self
+            .repo
+            .find_after(&in_workspace(workspace_id, filter), sorts, cursor_option)
+            .await?;
+        self.creators
+            .fill(result.datas.iter_mut().map(|c| &mut c.creator))
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn find_by_id(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: String,
+        id: String,
+    ) -> Result<entity::Catalog, ServiceError> {
+        self.access
+            .require_workspace(operator, &workspace_id, Role::Viewer)
+            .await?;
+        let oid = ObjectId::from_str(&id)?;
+        let mut result = self
+            .repo
+            .find_one(&catalog_condition(&workspace_id, oid))
+            .await?;
+        self.creators.fill(iter::once(&mut result.creator)).await?;
+        Ok(result)
+    }
+
+    pub async fn update(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: String,
+        id: String,
+        name: String,
+        description: String,
+        expected_version: Option<i64>,
+    ) -> Result<i64, ServiceError> {
+        self.access
+            .require_workspace(operator, &workspace_id, Role::Editor)
+            .await?;
+        let oid = ObjectId::from_str(&id)?;
+        let mut catalog = self
+            .repo
+            .find_one(&catalog_condition(&workspace_id, oid))
+            .await?;
+        check_version(expected_version, catalog.version)?;
+        catalog.name = name;
+        catalog.description = description;
+        catalog.updated_at = Utc::now();
+        let result = self.repo.update(&catalog).await?;
+        Ok(result)
+    }
+
+    /// 删除后进入回收站，可以恢复
+    ///
+    /// 目录中还有表时，只有cascade为true才能删除，其中的表随目录一起进入回收站
+    pub async fn delete(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: String,
+        id: String,
+        expected_version: Option<i64>,
+        cascade: bool,
+    ) -> Result<bool, ServiceError> {
+        self.access
+            .require_workspace(operator, &workspace_id, Role::Editor)
+            .await?;
+        let oid = ObjectId::from_str(&id)?;
+        self.cascade
+            .delete_catalog(
+                &id,
+                &catalog_condition(&workspace_id, oid),
+                expected_version,
+                &Deletion::new(&operator.id),
+                cascade,
+            )
+            .await
+    }
+
+    /// 从回收站恢复，所在的工作区需要没有被删除，和目录一起删除的表也一起恢复
+    pub async fn restore(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: String,
+        id: String,
+    ) -> Result<entity::Catalog, ServiceError> {
+        self.access
+            .require_workspace(operator, &workspace_id, Role::Editor)
+            .await?;
+        let oid = ObjectId::from_str(&id)?;
+        let mut result = self
+            .cascade
+            .restore_catalog(&catalog_condition(&workspace_id, oid))
+            .await?;
+        self.creators.fill(iter::once(&mut result.creator)).await?;
+        Ok(result)
+    }
+}
@@ -0,0 +1,418 @@
+use std::str::FromStr;
+
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    entity::{self, Role},
+    repository::{
+        condition::{Condition, ConditionValue, Operate, SortOption, SortOrder},
+        CRUDRepository, RepositoryError, Storage,
+    },
+};
+
+use super::{auth::CurrentUser, ServiceError};
+
+fn id_condition(oid: ObjectId) -> Condition {
+    Condition::single(
+        String::from("_id"),
+        Operate::Eq,
+        ConditionValue::ObjectIdValue(oid),
+    )
+}
+
+/// 某个用户在某个工作区中的成员关系
+fn member_condition(workspace_id: &str, user_id: &str) -> Condition {
+    Condition::and(vec![
+        (
+            String::from("workspaceId"),
+            Operate::Eq,
+            ConditionValue::StringValue(workspace_id.to_string()),
+        ),
+        (
+            String::from("userId"),
+            Operate::Eq,
+            ConditionValue::StringValue(user_id.to_string()),
+        ),
+    ])
+}
+
+/// 工作区的所有成员
+fn workspace_condition(workspace_id: &str) -> Condition {
+    Condition::single(
+        String::from("workspaceId"),
+        Operate::Eq,
+        ConditionValue::StringValue(workspace_id.to_string()),
+    )
+}
+
+/// 权限检查，目录、表和行的权限都由所在工作区中的角色决定
+#[derive(Clone)]
+pub struct Access<S: Storage> {
+    membership_repo: S::MembershipRepo,
+    workspace_repo: S::WorkspaceRepo,
+    catalog_repo: S::CatalogRepo,
+    table_repo: S::TableRepo,
+}
+
+impl<S: Storage> Access<S> {
+    pub fn new(storage: &S) -> Self {
+        Self {
+            membership_repo: storage.membership_repo(),
+            workspace_repo: storage.workspace_repo(),
+            catalog_repo: storage.catalog_repo(),
+            table_repo: storage.table_repo(),
+        }
+    }
+
+    /// 用户在工作区中的角色，不是成员时返回None
+    pub async fn role(
+        &self,
+        workspace_id: &str,
+        user_id: &str,
+    ) -> Result<Option<Role>, ServiceError> {
+        match self
+            .membership_repo
+            .find_one(&member_condition(workspace_id, user_id))
+            .await
+        {
+            Ok(membership) => Ok(Some(membership.role)),
+            Err(RepositoryError::DataNotFound) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    /// 要求当前用户在工作区中至少是某个角色，返回用户实际的角色
+    ///
+    /// 工作区已删除时返回DataNotFound，其中的目录、表和行也都不能访问
+    pub async fn require_workspace(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: &str,
+        required: Role,
+    ) -> Result<Role, ServiceError> {
+        let role = self.require_role(operator, workspace_id, required).await?;
+        let oid = ObjectId::from_str(workspace_id)?;
+        if self.workspace_repo.exist(&id_condition(oid)).await? {
+            Ok(role)
+        } else {
+            Err(RepositoryError::DataNotFound.into())
+        }
+    }
+
+    /// 只检查角色，不要求工作区没有删除，从回收站恢复工作区时使用
+    pub async fn require_role(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: &str,
+        required: Role,
+    ) -> Result<Role, ServiceError> {
+        operator.check_scope(workspace_id, required)?;
+        match self.role(workspace_id, &operator.id).await? {
+            Some(role) if role >= required => Ok(role),
+            Some(role) => Err(ServiceError::Forbidden(format!(
+                "role '{}' is required, but current role is '{}'",
+                required, role
+            ))),
+            None => Err(ServiceError::Forbidden(String::from(
+                "not a member of this workspace",
+            ))),
+        }
+    }
+
+    /// 要求当前用户在目录所在的工作区中至少是某个角色
+    pub async fn require_catalog(
+        &self,
+        operator: &CurrentUser,
+        catalog_id: &str,
+        required: Role,
+    ) -> Result<(), ServiceError> {
+        let oid = ObjectId::from_str(catalog_id)?;
+        let catalog = self.catalog_repo.find_one(&id_condition(oid)).await?;
+        self.require_workspace(operator, &catalog.workspace_id, required)
+            .await?;
+        Ok(())
+    }
+
+    /// 要求当前用户在表所在的工作区中至少是某个角色
+    pub async fn require_table(
+        &self,
+        operator: &CurrentUser,
+        table_id: &str,
+        required: Role,
+    ) -> Result<(), ServiceError> {
+        let oid = ObjectId::from_str(table_id)?;
+        let table = self.table_repo.find_one(&id_condition(oid)).await?;
+        self.require_catalog(operator, &table.catalog_id, required)
+            .await
+    }
+
+    /// 当前用户是否能读取表，用于检查引用的表
+    ///
+    /// 表不存在和没有权限都返回false，不暴露用户看不到的表是否存在
+    pub async fn can_read_table(
+        &self,
+        operator: &CurrentUser,
+        table_id: &str,
+    ) -> Result<bool, ServiceError> {
+        match self.require_table(operator, table_id, Role::Viewer).await {
+            Ok(()) => Ok(true),
+            Err(ServiceError::Forbidden(_))
+            | Err(ServiceError::RepositoryError(RepositoryError::DataNotFound))
+            | Err(ServiceError::RepositoryError(RepositoryError::InvalidId(_))) => Ok(false),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// 用户加入的所有工作区的id，API key限定了工作区时只包含这个工作区
+    pub async fn workspace_ids(
+        &self,
+        operator: &CurrentUser,
+    ) -> Result<Vec<ObjectId>, ServiceError> {
+        let memberships = self
+            .membership_repo
+            .find(
+                &Condition::single(
+                    String::from("userId"),
+                    Operate::Eq,
+                    ConditionValue::StringValue(operator.id.clone()),
+                ),
+                &[],
+            )
+            .await?;
+        let scoped = operator
+            .scope
+            .as_ref()
+            .and_then(|s| s.workspace_id.as_ref());
+        let ids = memberships
+            .iter()
+            .filter(|m| scoped.is_none() || scoped == Some(&m.workspace_id))
+            .map(|m| ObjectId::from_str(&m.workspace_id))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(ids)
+    }
+
+    /// 添加成员关系，创建工作区时创建者成为owner
+    pub async fn grant(
+        &self,
+        workspace_id: &str,
+        user_id: &str,
+        role: Role,
+    ) -> Result<String, ServiceError> {
+        let now = Utc::now();
+        let result = self
+            .membership_repo
+            .create(&entity::Membership {
+                id: String::new(),
+                workspace_id: workspace_id.to_string(),
+                user_id: user_id.to_string(),
+                role,
+                created_at: now,
+                updated_at: now,
+                version: 0,
+            })
+            .await?;
+        Ok(result)
+    }
+
+    /// 给同一个用户添加多个工作区的成员关系，批量创建工作区时使用
+    pub async fn grant_many(
+        &self,
+        workspace_ids: &[String],
+        user_id: &str,
+        role: Role,
+    ) -> Result<Vec<String>, ServiceError> {
+        let now = Utc::now();
+        let memberships = workspace_ids
+            .iter()
+            .map(|workspace_id| entity::Membership {
+                id: String::new(),
+                workspace_id: workspace_id.clone(),
+                user_id: user_id.to_string(),
+                role,
+                created_at: now,
+                updated_at: now,
+                version: 0,
+            })
+            .collect::<Vec<_>>();
+        Ok(self.membership_repo.create_many(&memberships).await?)
+    }
+
+    /// 删除工作区的所有成员关系
+    pub async fn revoke_all(&self, workspace_id: &str) -> Result<(), ServiceError> {
+        self.membership_repo
+            .delete_many(&workspace_condition(workspace_id))
+            .await?;
+        Ok(())
+    }
+}
+
+#[derive(Clone)]
+pub struct MembershipService<S: Storage> {
+    repo: S::MembershipRepo,
+    user_repo: S::UserRepo,
+    access: Access<S>,
+}
+
+impl<S: Storage> MembershipService<S> {
+    pub fn new(storage: &S) -> Self {
+        Self {
+            repo: storage.membership_repo(),
+            user_repo: storage.user_repo(),
+            access: Access::new(storage),
+        }
+    }
+
+    /// 查看工作区的所有成员，按加入时间排序
+    pub async fn find_all_member(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: String,
+    ) -> Result<Vec<entity::Membership>, ServiceError> {
+        self.access
+            .require_workspace(operator, &workspace_id, Role::Viewer)
+            .await?;
+        let result = self
+            .repo
+            .find(
+                &workspace_condition(&workspace_id),
+                &[SortOption::new("createdAt", SortOrder::Asc)],
+            )
+            .await?;
+        Ok(result)
+    }
+
+    /// 按用户名邀请成员，只有owner可以授予owner
+    pub async fn invite(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: String,
+        username: String,
+        role: Role,
+    ) -> Result<String, ServiceError> {
+        let operator_role = self
+            .access
+            .require_workspace(operator, &workspace_id, Role::Admin)
+            .await?;
+        ensure_can_assign(operator_role, role)?;
+        let user = self
+            .user_repo
+            .find_one(&Condition::single(
+                String::from("username"),
+                Operate::Eq,
+                ConditionValue::StringValue(username),
+            ))
+            .await?;
+        if self.access.role(&workspace_id, &user.id).await?.is_some() {
+            return Err(RepositoryError::Conflict(format!(
+                "user '{}' is already a member",
+                user.username
+            ))
+            .into());
+        }
+        self.access.grant(&workspace_id, &user.id, role).await
+    }
+
+    /// 修改成员的角色
+    ///
+    /// admin不能修改owner的角色，也不能授予owner；工作区至少要保留一个owner
+    pub async fn change_role(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: String,
+        user_id: String,
+        role: Role,
+    ) -> Result<bool, ServiceError> {
+        let operator_role = self
+            .access
+            .require_workspace(operator, &workspace_id, Role::Admin)
+            .await?;
+        let mut membership = self
+            .repo
+            .find_one(&member_condition(&workspace_id, &user_id))
+            .await?;
+        ensure_can_assign(operator_role, membership.role)?;
+        ensure_can_assign(operator_role, role)?;
+        if membership.role == Role::Owner && role != Role::Owner {
+            self.ensure_other_owner(&workspace_id).await?;
+        }
+        membership.role = role;
+        membership.updated_at = Utc::now();
+        self.repo.update(&membership).await?;
+        Ok(true)
+    }
+
+    /// 移除成员，成员也可以自己退出工作区
+    ///
+    /// 退出工作区只能用登录令牌，API key不能移除自己的成员关系
+    pub async fn remove(
+        &self,
+        operator: &CurrentUser,
+        workspace_id: String,
+        user_id: String,
+    ) -> Result<bool, ServiceError> {
+        let membership = self
+            .repo
+            .find_one(&member_condition(&workspace_id, &user_id))
+            .await;
+        if user_id == operator.id {
+            operator.require_session()?;
+            self.access
+                .require_workspace(operator, &workspace_id, Role::Viewer)
+                .await?;
+        } else {
+            let operator_role = self
+                .access
+                .require_workspace(operator, &workspace_id, Role::Admin)
+                .await?;
+            if let Ok(m) = &membership {
+                ensure_can_assign(operator_role, m.role)?;
+            }
+        }
+        let membership = membership?;
+        if membership.role == Role::Owner {
+            self.ensure_other_owner(&workspace_id).await?;
+        }
+        let oid = ObjectId::from_str(&membership.id)?;
+        let result = self.repo.delete(&id_condition(oid)).await?;
+        Ok(result)
+    }
+
+    /// 确认工作区中还有其他owner
+    async fn ensure_other_owner(&self, workspace_id: &str) -> Result<(), ServiceError> {
+        let owners = self
+            .repo
+            .count(&Condition::and(vec![
+                (
+                    String::from("workspaceId"),
+                    Operate::Eq,
+                    ConditionValue::StringValue(workspace_id.to_string()),
+                ),
+                (
+                    String::from("role"),
+                    Operate::Eq,
+                    ConditionValue::StringValue(Role::Owner.to_string()),
+                ),
+            ]))
+            .await?;
+        if owners > 1 {
+            Ok(())
+        } else {
+            Err(
+                RepositoryError::Conflict(String::from("a workspace must keep at least one owner"))
+                    .into(),
+            )
+        }
+    }
+}
+
+/// 只有owner可以授予、修改或移除owner
+fn ensure_can_assign(operator_role: Role, role: Role) -> Result<(), ServiceError> {
+    if role == Role::Owner && operator_role != Role::Owner {
+        Err(ServiceError::Forbidden(String::from(
+            "only an owner can manage owners",
+        )))
+    } else {
+        Ok(())
+    }
+}
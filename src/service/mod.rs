@@ -1,11 +1,71 @@
-use crate::repository::mongodb::MongodbError;
+use serde::Serialize;
 
+use crate::repository::{RepositoryError, TransactionError};
+
+pub mod api_key;
+pub mod auth;
+pub mod cascade;
+pub mod catalog;
+pub mod membership;
+pub mod row;
+pub mod schema;
+pub mod table;
+pub mod trash;
+pub mod user;
+pub mod version;
 pub mod workspace;
 
+/// 单个字段的校验错误
+#[derive(Serialize, Debug, Clone)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    pub fn new(field: &str, message: String) -> Self {
+        FieldError {
+            field: field.to_string(),
+            message,
+        }
+    }
+}
+
+/// 阻止删除的下级数据
+#[derive(Serialize, Debug, Clone)]
+pub struct Dependent {
+    pub kind: &'static str,
+    pub id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum ServiceError {
+    #[error("invalid argument: {0}")]
+    InvalidArgument(String),
+    /// 数据不满足业务规则，例如行不满足表的列定义
+    #[error("validation failed")]
+    ValidationError(Vec<FieldError>),
+    /// 请求对象没有通过声明式校验
+    #[error("invalid request")]
+    InvalidRequest(Vec<FieldError>),
+    #[error("unauthorized: {0}")]
+    Unauthorized(String),
+    #[error("forbidden: {0}")]
+    Forbidden(String),
+    /// 还有下级数据时不能删除，dependents只包含其中的一部分
+    #[error("resource still has {count} dependents")]
+    HasDependents {
+        count: i64,
+        dependents: Vec<Dependent>,
+    },
+    #[error("precondition failed: {0}")]
+    PreconditionFailed(String),
+    #[error("internal error: {0}")]
+    InternalError(String),
     #[error(transparent)]
-    RepositoryError(#[from] MongodbError),
+    RepositoryError(#[from] RepositoryError),
     #[error(transparent)]
     IoError(#[from] std::io::Error),
     #[error(transparent)]
@@ -14,8 +74,17 @@ pub enum ServiceError {
     HttpError(#[from] warp::http::Error),
 }
 
+impl TransactionError for ServiceError {
+    fn repository_error(&self) -> Option<&RepositoryError> {
+        match self {
+            ServiceError::RepositoryError(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
 impl From<mongodb::bson::oid::Error> for ServiceError {
     fn from(err: mongodb::bson::oid::Error) -> Self {
-        ServiceError::RepositoryError(MongodbError::BsonOidError(err))
+        ServiceError::RepositoryError(RepositoryError::InvalidId(err))
     }
 }
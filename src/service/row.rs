@@ -0,0 +1,272 @@
+use std::str::FromStr;
+
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use serde_json::{Map, Value};
+
+use crate::{
+    entity::{self, ColumnType, Role},
+    repository::{
+        condition::{Condition, ConditionValue, CursorOption, Operate, PageOption, SortOption},
+        CRUDRepository, CursorResult, PageResult, PaginationRepository, Storage,
+    },
+};
+
+use super::{
+    auth::CurrentUser,
+    membership::Access,
+    schema,
+    version::{check_version, delete_versioned},
+    FieldError, ServiceError,
+};
+
+#[derive(Clone)]
+pub struct RowService<S: Storage> {
+    repo: S::RowRepo,
+    table_repo: S::TableRepo,
+    access: Access<S>,
+}
+
+/// 限定在某张表下的按id查询条件
+fn row_condition(table_id: &str, oid: ObjectId) -> Condition {
+    Condition::and(vec![
+        (
+            String::from("_id"),
+            Operate::Eq,
+            ConditionValue::ObjectIdValue(oid),
+        ),
+        (
+            String::from("tableId"),
+            Operate::Eq,
+            ConditionValue::StringValue(table_id.to_string()),
+        ),
+    ])
+}
+
+/// 在过滤条件上限定某张表中的行
+fn in_table(table_id: String, filter: Condition) -> Condition {
+    Condition::all(vec![
+        Condition::single(
+            String::from("tableId"),
+            Operate::Eq,
+            ConditionValue::StringValue(table_id),
+        ),
+        filter,
+    ])
+}
+
+impl<S: Storage> RowService<S> {
+    pub fn new(storage: &S) -> Self {
+        Self {
+            repo: storage.row_repo(),
+            table_repo: storage.table_repo(),
+            access: Access::new(storage),
+        }
+    }
+
+    /// 获取行所在的表，需要viewer角色
+    pub async fn find_table(
+        &self,
+        operator: &CurrentUser,
+        table_id: &str,
+    ) -> Result<entity::Table, ServiceError> {
+        self.access
+            .require_table(operator, table_id, Role::Viewer)
+            .await?;
+        self.load_table(table_id).await
+    }
+
+    async fn load_table(&self, table_id: &str) -> Result<entity::Table, ServiceError> {
+        let oid = ObjectId::from_str(table_id)?;
+        let result = self
+            .table_repo
+            .find_one(&Condition::single(
+                String::from("_id"),
+                Operate::Eq,
+                ConditionValue::ObjectIdValue(oid),
+            ))
+            .await?;
+        Ok(result)
+    }
+
+    /// 按表结构校验数据，并检查唯一约束和引用
+    ///
+    /// row_id是正在更新的行，检查唯一约束时要排除它自己。
+    /// 引用的行所在的表需要当前用户至少是viewer
+    async fn validate(
+        &self,
+        operator: &CurrentUser,
+        table: &entity::Table,
+        data: &Map<String, Value>,
+        row_id: Option<ObjectId>,
+    ) -> Result<Map<String, Value>, ServiceError> {
+        let data =
+            schema::validate_row(&table.columns, data).map_err(ServiceError::ValidationError)?;
+        let mut errors = vec![];
+        for column in table.columns.iter() {
+            let value = match data.get(&column.name) {
+                Some(v) if !v.is_null() => v,
+                _ => continue,
+            };
+            if column.unique {
+                if let Some(cv) = schema::to_condition_value(value) {
+                    let mut conds = vec![
+                        (
+                            String::from("tableId"),
+                            Operate::Eq,
+                            ConditionValue::StringValue(table.id.clone()),
+                        ),
+                        (format!("data.{}", column.name), Operate::Eq, cv),
+                    ];
+                    if let Some(oid) = row_id {
+                        conds.push((
+                            String::from("_id"),
+                            Operate::Ne,
+                            ConditionValue::ObjectIdValue(oid),
+                        ));
+                    }
+                    if self.repo.exist(&Condition::and(conds)).await? {
+                        errors.push(FieldError::new(
+                            &column.name,
+                            format!("value {} already exists", value),
+                        ));
+                    }
+                }
+            }
+            if let ColumnType::Reference { table_id } = &column.column_type {
+                let id = value.as_str().ok_or_else(|| {
+                    ServiceError::InvalidArgument(format!(
+                        "value of column '{}' is not a row id",
+                        column.name
+                    ))
+                })?;
+                let oid = ObjectId::from_str(id)?;
+                // 没有权限读取被引用的表时和行不存在一样处理
+                let readable = self.access.can_read_table(operator, table_id).await?;
+                if !readable || !self.repo.exist(&row_condition(table_id, oid)).await? {
+                    errors.push(FieldError::new(
+                        &column.name,
+                        format!("referenced row {} does not exist", value),
+                    ));
+                }
+            }
+        }
+        if errors.is_empty() {
+            Ok(data)
+        } else {
+            Err(ServiceError::ValidationError(errors))
+        }
+    }
+
+    pub async fn insert_row(
+        &self,
+        operator: &CurrentUser,
+        table_id: String,
+        data: Map<String, Value>,
+    ) -> Result<String, ServiceError> {
+        self.access
+            .require_table(operator, &table_id, Role::Editor)
+            .await?;
+        let table = self.load_table(&table_id).await?;
+        let data = self.validate(operator, &table, &data, None).await?;
+        let now = Utc::now();
+        let result = self
+            .repo
+            .create(&entity::Row {
+                id: String::new(),
+                table_id,
+                data,
+                created_at: now,
+                updated_at: now,
+                version: 0,
+            })
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn find_all_row(
+        &self,
+        operator: &CurrentUser,
+        table_id: String,
+        filter: Condition,
+        sorts: &[SortOption],
+        page: &PageOption,
+    ) -> Result<PageResult<entity::Row>, ServiceError> {
+        self.access
+            .require_table(operator, &table_id, Role::Viewer)
+            .await?;
+        let result = self
+            .repo
+            .find_page(&in_table(table_id, filter), sorts, page, true)
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn find_row_after(
+        &self,
+        operator: &CurrentUser,
+        table_id: String,
+        filter: Condition,
+        sorts: &[SortOption],
+        cursor_option: &CursorOption,
+    ) -> Result<CursorResult<entity::Row>, ServiceError> {
+        self.access
+            .require_table(operator, &table_id, Role::Viewer)
+            .await?;
+        let result = self
+            .repo
+            .find_after(&in_table(table_id, filter), sorts, cursor_option)
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn find_by_id(
+        &self,
+        operator: &CurrentUser,
+        table_id: String,
+        id: String,
+    ) -> Result<entity::Row, ServiceError> {
+        self.access
+            .require_table(operator, &table_id, Role::Viewer)
+            .await?;
+        let oid = ObjectId::from_str(&id)?;
+        let result = self.repo.find_one(&row_condition(&table_id, oid)).await?;
+        Ok(result)
+    }
+
+    /// 用新数据替换整行，返回更新后的版本号
+    pub async fn update_row(
+        &self,
+        operator: &CurrentUser,
+        table_id: String,
+        id: String,
+        data: Map<String, Value>,
+        expected_version: Option<i64>,
+    ) -> Result<i64, ServiceError> {
+        self.access
+            .require_table(operator, &table_id, Role::Editor)
+            .await?;
+        let oid = ObjectId::from_str(&id)?;
+        let table = self.load_table(&table_id).await?;
+        let mut row = self.repo.find_one(&row_condition(&table_id, oid)).await?;
+        check_version(expected_version, row.version)?;
+        row.data = self.validate(operator, &table, &data, Some(oid)).await?;
+        row.updated_at = Utc::now();
+        let result = self.repo.update(&row).await?;
+        Ok(result)
+    }
+
+    pub async fn delete_row(
+        &self,
+        operator: &CurrentUser,
+        table_id: String,
+        id: String,
+        expected_version: Option<i64>,
+    ) -> Result<bool, ServiceError> {
+        self.access
+            .require_table(operator, &table_id, Role::Editor)
+            .await?;
+        let oid = ObjectId::from_str(&id)?;
+        delete_versioned(&self.repo, row_condition(&table_id, oid), expected_version).await
+    }
+}
@@ -0,0 +1,175 @@
+use std::{collections::HashSet, str::FromStr};
+
+use chrono::{DateTime, SecondsFormat, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde_json::{Map, Value};
+
+use crate::{
+    entity::{Column, ColumnType},
+    repository::condition::ConditionValue,
+};
+
+use super::FieldError;
+
+/// 检查值是否符合列类型，返回规范化后的值
+///
+/// 日期时间统一转成UTC的RFC3339字符串，保证按字符串比较时顺序正确
+pub fn check_value(column_type: &ColumnType, value: &Value) -> Result<Value, String> {
+    match (column_type, value) {
+        (ColumnType::String, Value::String(_)) => Ok(value.clone()),
+        (ColumnType::Integer, Value::Number(n)) if n.is_i64() => Ok(value.clone()),
+        (ColumnType::Float, Value::Number(n)) => n
+            .as_f64()
+            .map(Value::from)
+            .ok_or_else(|| format!("{} is not a valid float", n)),
+        (ColumnType::Boolean, Value::Bool(_)) => Ok(value.clone()),
+        (ColumnType::Datetime, Value::String(s)) => DateTime::parse_from_rfc3339(s)
+            .map(|dt| {
+                Value::String(
+                    dt.with_timezone(&Utc)
+                        .to_rfc3339_opts(SecondsFormat::Millis, true),
+                )
+            })
+            .map_err(|_| format!("'{}' is not a RFC3339 datetime", s)),
+        (ColumnType::Enum { values }, Value::String(s)) => {
+            if values.contains(s) {
+                Ok(value.clone())
+            } else {
+                Err(format!("'{}' is not one of {:?}", s, values))
+            }
+        }
+        (ColumnType::Reference { .. }, Value::String(s)) => ObjectId::from_str(s)
+            .map(|_| value.clone())
+            .map_err(|_| format!("'{}' is not a valid row id", s)),
+        _ => Err(format!("expected a value of type {}", column_type)),
+    }
+}
+
+/// 尝试把已有的值转换成新的列类型，修改列类型时使用
+pub fn convert_value(value: &Value, to: &ColumnType) -> Result<Value, String> {
+    if let Ok(v) = check_value(to, value) {
+        return Ok(v);
+    }
+    let converted = match (value, to) {
+        (Value::Number(n), ColumnType::String) => Some(Value::String(n.to_string())),
+        (Value::Bool(b), ColumnType::String) => Some(Value::String(b.to_string())),
+        (Value::Number(n), ColumnType::Integer) => n
+            .as_f64()
+            .filter(|f| f.fract() == 0.0 && *f >= i64::MIN as f64 && *f <= i64::MAX as f64)
+            .map(|f| Value::from(f as i64)),
+        (Value::String(s), ColumnType::Integer) => s.trim().parse::<i64>().ok().map(Value::from),
+        (Value::String(s), ColumnType::Float) => s.trim().parse::<f64>().ok().map(Value::from),
+        (Value::String(s), ColumnType::Boolean) => s.trim().parse::<bool>().ok().map(Value::from),
+        _ => None,
+    };
+    match converted {
+        // 转换后的值还要再满足一次新类型，例如枚举成员
+        Some(v) => check_value(to, &v),
+        None => Err(format!("cannot convert {} to {}", value, to)),
+    }
+}
+
+/// 校验单个列定义，返回默认值规范化后的列
+pub fn validate_column(column: &Column) -> Result<Column, String> {
+    let name = column.name.trim();
+    if name.is_empty() {
+        return Err(String::from("column name is required"));
+    }
+    if name.starts_with('$') || name.contains('.') {
+        return Err(format!(
+            "column name '{}' must not start with '$' or contain '.'",
+            name
+        ));
+    }
+    if let ColumnType::Enum { values } = &column.column_type {
+        if values.is_empty() {
+            return Err(format!("enum column '{}' needs at least one value", name));
+        }
+        let distinct: HashSet<&String> = values.iter().collect();
+        if distinct.len() != values.len() {
+            return Err(format!("enum column '{}' has duplicated values", name));
+        }
+    }
+    let default = match &column.default {
+        None | Some(Value::Null) => None,
+        Some(v) => Some(
+            check_value(&column.column_type, v)
+                .map_err(|e| format!("invalid default of column '{}': {}", name, e))?,
+        ),
+    };
+    Ok(Column {
+        name: name.to_string(),
+        column_type: column.column_type.clone(),
+        nullable: column.nullable,
+        default,
+        unique: column.unique,
+    })
+}
+
+/// 校验整组列定义，列名不能重复
+pub fn validate_columns(columns: &[Column]) -> Result<Vec<Column>, String> {
+    let mut names = HashSet::new();
+    let mut result = vec![];
+    for column in columns {
+        let column = validate_column(column)?;
+        if !names.insert(column.name.clone()) {
+            return Err(format!("duplicated column '{}'", column.name));
+        }
+        result.push(column);
+    }
+    Ok(result)
+}
+
+/// 按列定义校验一行数据，返回补上默认值、规范化后的数据
+///
+/// 所有不合法的字段会一次性返回
+pub fn validate_row(
+    columns: &[Column],
+    data: &Map<String, Value>,
+) -> Result<Map<String, Value>, Vec<FieldError>> {
+    let mut errors = vec![];
+    for key in data.keys() {
+        if !columns.iter().any(|c| &c.name == key) {
+            errors.push(FieldError::new(key, String::from("unknown column")));
+        }
+    }
+    let mut result = Map::new();
+    for column in columns {
+        let value = match data.get(&column.name) {
+            None => column.default.clone().unwrap_or(Value::Null),
+            Some(v) => v.clone(),
+        };
+        if value.is_null() {
+            if column.nullable {
+                result.insert(column.name.clone(), Value::Null);
+            } else {
+                errors.push(FieldError::new(&column.name, String::from("is required")));
+            }
+            continue;
+        }
+        match check_value(&column.column_type, &value) {
+            Ok(v) => {
+                result.insert(column.name.clone(), v);
+            }
+            Err(e) => errors.push(FieldError::new(&column.name, e)),
+        }
+    }
+    if errors.is_empty() {
+        Ok(result)
+    } else {
+        Err(errors)
+    }
+}
+
+/// 将行中的值转成查询条件的值，null和复合值返回None
+pub fn to_condition_value(value: &Value) -> Option<ConditionValue> {
+    match value {
+        Value::String(s) => Some(ConditionValue::StringValue(s.clone())),
+        Value::Bool(b) => Some(ConditionValue::BooleanValue(*b)),
+        Value::Number(n) => match n.as_i64() {
+            Some(i) => Some(ConditionValue::Int64Value(i)),
+            None => n.as_f64().map(ConditionValue::DoubleValue),
+        },
+        _ => None,
+    }
+}
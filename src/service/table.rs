@@ -0,0 +1,642 @@
+use std::{iter, str::FromStr};
+
+use chrono::Utc;
+use mongodb::bson::oid::ObjectId;
+use serde_json::Value;
+
+use crate::{
+    entity::{self, Column, ColumnType, Role},
+    repository::{
+        condition::{
+            keyset_sorts, Condition, ConditionValue, Cursor, CursorOption, Operate, PageOption,
+            Patch, SortOption,
+        },
+        soft_delete::SoftDeleteRepository,
+        CRUDRepository, CursorResult, PageResult, PaginationRepository, RepositoryError, Storage,
+    },
+};
+
+use super::{
+    auth::CurrentUser, cascade::Cascade, membership::Access, schema, user::Creators,
+    version::check_version, ServiceError,
+};
+
+/// 修改列时每批读取的行数
+const ROW_BATCH_SIZE: usize = 500;
+
+#[derive(Clone)]
+pub struct TableService<S: Storage> {
+    storage: S,
+    repo: S::TableRepo,
+    row_repo: S::RowRepo,
+    access: Access<S>,
+    cascade: Cascade<S>,
+    creators: Creators<S>,
+}
+
+/// 限定在某个目录下的按id查询条件
+fn table_condition(catalog_id: &str, oid: ObjectId) -> Condition {
+    Condition::and(vec![
+        (
+            String::from("_id"),
+            Operate::Eq,
+            ConditionValue::ObjectIdValue(oid),
+        ),
+        (
+            String::from("catalogId"),
+            Operate::Eq,
+            ConditionValue::StringValue(catalog_id.to_string()),
+        ),
+    ])
+}
+
+/// 在过滤条件上限定某个目录下的表
+fn in_catalog(catalog_id: String, filter: Condition) -> Condition {
+    Condition::all(vec![
+        Condition::single(
+            String::from("catalogId"),
+            Operate::Eq,
+            ConditionValue::StringValue(catalog_id),
+        ),
+        filter,
+    ])
+}
+
+impl<S: Storage> TableService<S> {
+    pub fn new(storage: &S) -> Self {
+        Self {
+            storage: storage.clone(),
+            repo: storage.table_repo(),
+            row_repo: storage.row_repo(),
+            access: Access::new(storage),
+            cascade: Cascade::new(storage),
+            creators: Creators::new(storage),
+        }
+    }
+
+    pub async fn create_table(
+        &self,
+        operator: &CurrentUser,
+        catalog_id: String,
+        name: String,
+        description: String,
+        columns: Vec<Column>,
+    ) -> Result<String, ServiceError> {
+        self.access
+            .require_catalog(operator, &catalog_id, Role::Editor)
+            .await?;
+        let columns = schema::validate_columns(&columns).map_err(ServiceError::InvalidArgument)?;
+        for column in columns.iter() {
+            self.ensure_reference_exist(operator, &column.column_type)
+                .await?;
+        }
+        let now = Utc::now();
+        let result = self
+            .repo
+            .create(&entity::Table {
+                id: String::new(),
+                catalog_id,
+                name,
+                description,
+                columns,
+                creator: operator.summary(),
+                created_at: now,
+                updated_at: now,
+                version: 0,
+                deleted_at: None,
+                deleted_by: None,
+            })
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn find_all_table(
+        &self,
+        operator: &CurrentUser,
+        catalog_id: String,
+        filter: Condition,
+        sorts: &[SortOption],
+        page: &PageOption,
+    ) -> Result<PageResult<entity::Table>, ServiceError> {
+        self.access
+            .require_catalog(operator, &catalog_id, Role::Viewer)
+            .await?;
+        let mut result = self
+            .repo
+            .find_page(&in_catalog(catalog_id, filter), sorts, page, true)
+            .await?;
+        self.creators
+            .fill(result.datas.iter_mut().map(|t| &mut t.creator))
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn find_table_after(
+        &self,
+        operator: &CurrentUser,
+        catalog_id: String,
+        filter: Condition,
+        sorts: &[SortOption],
+        cursor_option: &CursorOption,
+    ) -> Result<CursorResult<entity::Table>, ServiceError> {
+        self.access
+            .require_catalog(operator, &catalog_id, Role::Viewer)
+            .await?;
+        let mut result = self
+            .repo
+            .find_after(&in_catalog(catalog_id, filter), sorts, cursor_option)
+            .await?;
+        self.creators
+            .fill(result.datas.iter_mut().map(|t| &mut t.creator))
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn find_by_id(
+        &self,
+        operator: &CurrentUser,
+        catalog_id: String,
+        id: String,
+    ) -> Result<entity::Table, ServiceError> {
+        self.access
+            .require_catalog(operator, &catalog_id, Role::Viewer)
+            .await?;
+        let oid = ObjectId::from_str(&id)?;
+        let mut result = self
+            .repo
+            .find_one(&table_condition(&catalog_id, oid))
+            .await?;
+        self.creators.fill(iter::once(&mut result.creator)).await?;
+        Ok(result)
+    }
+
+    pub async fn update(
+        &self,
+        operator: &CurrentUser,
+        catalog_id: String,
+        id: String,
+        name: String,
+        description: String,
+        expected_version: Option<i64>,
+    ) -> Result<i64, ServiceError> {
+        self.access
+            .require_catalog(operator, &catalog_id, Role::Editor)
+            .await?;
+        let oid = ObjectId::from_str(&id)?;
+        let mut table = self
+            .repo
+            .find_one(&table_condition(&catalog_id, oid))
+            .await?;
+        check_version(expected_version, table.version)?;
+        table.name = name;
+        table.description = description;
+        table.updated_at = Utc::now();
+        let result = self.repo.update(&table).await?;
+        Ok(result)
+    }
+
+    /// 删除后进入回收站，可以恢复
+    ///
+    /// 表中还有行时，只有cascade为true才能删除，行不单独标记，表恢复前无法访问
+    pub async fn delete(
+        &self,
+        operator: &CurrentUser,
+        catalog_id: String,
+        id: String,
+        expected_version: Option<i64>,
+        cascade: bool,
+    ) -> Result<bool, ServiceError> {
+        self.access
+            .require_catalog(operator, &catalog_id, Role::Editor)
+            .await?;
+        let oid = ObjectId::from_str(&id)?;
+        self.cascade
+            .delete_table(
+                &id,
+                &table_condition(&catalog_id, oid),
+                expected_version,
+                &Deletion::new(&operator.id),
+                cascade,
+            )
+            .await
+    }
+
+    /// 从回收站恢复，所在的目录需要没有被删除
+    pub async fn restore(
+        &self,
+        operator: &CurrentUser,
+        catalog_id: String,
+        id: String,
+    ) -> Result<entity::Table, ServiceError> {
+        self.access
+            .require_catalog(operator, &catalog_id, Role::Editor)
+            .await?;
+        let oid = ObjectId::from_str(&id)?;
+        let mut result = self
+            .repo
+            .restore(&table_condition(&catalog_id, oid))
+            .await?;
+        self.creators.fill(iter::once(&mut result.creator)).await?;
+        Ok(result)
+    }
+
+    /// 按id获取表，不限定目录
+    async fn find_table(&self, id: &str) -> Result<entity::Table, ServiceError> {
+        let oid = ObjectId::from_str(id)?;
+        let result = self
+            .repo
+            .find_one(&Condition::single(
+                String::from("_id"),
+                Operate::Eq,
+                ConditionValue::ObjectIdValue(oid),
+            ))
+            .await?;
+        Ok(result)
+    }
+
+    /// 引用类型的列，被引用的表必须存在，并且当前用户至少是viewer
+    ///
+    /// 不存在和没有权限返回同样的错误
+    async fn ensure_reference_exist(
+        &self,
+        operator: &CurrentUser,
+        column_type: &ColumnType,
+    ) -> Result<(), ServiceError> {
+        if let ColumnType::Reference { table_id } = column_type {
+            if !self.access.can_read_table(operator, table_id).await? {
+                return Err(ServiceError::InvalidArgument(format!(
+                    "referenced table '{}' does not exist",
+                    table_id
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// 在一个事务中修改表中已有的行并保存新的列定义，中途失败时行和列定义都不变
+    async fn migrate(
+        &self,
+        table: entity::Table,
+        columns: Vec<Column>,
+        migration: RowMigration,
+    ) -> Result<Vec<Column>, ServiceError> {
+        self.storage
+            .with_transaction(move |tx| {
+                let table = table.clone();
+                let columns = columns.clone();
+                let migration = migration.clone();
+                Box::pin(async move { migrate_table(tx, table, columns, &migration).await })
+            })
+            .await
+    }
+
+    /// 添加列，position为空时添加到最后
+    pub async fn add_column(
+        &self,
+        operator: &CurrentUser,
+        table_id: String,
+        column: Column,
+        position: Option<usize>,
+    ) -> Result<Vec<Column>, ServiceError> {
+        self.access
+            .require_table(operator, &table_id, Role::Editor)
+            .await?;
+        let table = self.find_table(&table_id).await?;
+        let column = schema::validate_column(&column).map_err(ServiceError::InvalidArgument)?;
+        if table.columns.iter().any(|c| c.name == column.name) {
+            return Err(ServiceError::InvalidArgument(format!(
+                "column '{}' already exists",
+                column.name
+            )));
+        }
+        self.ensure_reference_exist(operator, &column.column_type)
+            .await?;
+        // 已有数据的表，新列的值用默认值补上
+        let row_count = self.row_repo.count(&rows_condition(&table.id)).await?;
+        if row_count > 0 && !column.nullable && column.default.is_none() {
+            return Err(ServiceError::InvalidArgument(format!(
+                "column '{}' must be nullable or have a default because the table has rows",
+                column.name
+            )));
+        }
+        if row_count > 1 && column.unique && column.default.is_some() {
+            return Err(ServiceError::InvalidArgument(format!(
+                "unique column '{}' cannot be filled with the same default in existing rows",
+                column.name
+            )));
+        }
+        let value = column
+            .default
+            .as_ref()
+            .and_then(schema::to_condition_value)
+            .unwrap_or(ConditionValue::NullValue);
+        let patch = row_patch().set(&data_field(&column.name), value);
+        let mut columns = table.columns.clone();
+        let index = position.unwrap_or(columns.len()).min(columns.len());
+        columns.insert(index, column);
+        self.migrate(table, columns, RowMigration::Patch(patch))
+            .await
+    }
+
+    /// 重命名列
+    pub async fn rename_column(
+        &self,
+        operator: &CurrentUser,
+        table_id: String,
+        name: String,
+        new_name: String,
+    ) -> Result<Vec<Column>, ServiceError> {
+        self.access
+            .require_table(operator, &table_id, Role::Editor)
+            .await?;
+        let table = self.find_table(&table_id).await?;
+        let mut columns = table.columns.clone();
+        let index = column_index(&columns, &name)?;
+        let mut renamed = columns[index].clone();
+        renamed.name = new_name;
+        let renamed = schema::validate_column(&renamed).map_err(ServiceError::InvalidArgument)?;
+        if renamed.name != name && columns.iter().any(|c| c.name == renamed.name) {
+            return Err(ServiceError::InvalidArgument(format!(
+                "column '{}' already exists",
+                renamed.name
+            )));
+        }
+        let migration = if renamed.name != name {
+            let patch = row_patch().rename(&data_field(&name), &data_field(&renamed.name));
+            RowMigration::Patch(patch)
+        } else {
+            RowMigration::None
+        };
+        columns[index] = renamed;
+        self.migrate(table, columns, migration).await
+    }
+
+    /// 重新排列列的顺序，names必须包含所有列
+    pub async fn reorder_columns(
+        &self,
+        operator: &CurrentUser,
+        table_id: String,
+        names: Vec<String>,
+    ) -> Result<Vec<Column>, ServiceError> {
+        self.access
+            .require_table(operator, &table_id, Role::Editor)
+            .await?;
+        let table = self.find_table(&table_id).await?;
+        if names.len() != table.columns.len() {
+            return Err(ServiceError::InvalidArgument(String::from(
+                "the new order must list every column exactly once",
+            )));
+        }
+        let mut columns = vec![];
+        for name in names.iter() {
+            let index = column_index(&table.columns, name)?;
+            if columns.iter().any(|c: &Column| &c.name == name) {
+                return Err(ServiceError::InvalidArgument(format!(
+                    "column '{}' is listed more than once",
+                    name
+                )));
+            }
+            columns.push(table.columns[index].clone());
+        }
+        save_columns(&self.storage, table, columns).await
+    }
+
+    /// 修改列的类型，已有数据或默认值不能转换成新类型时拒绝修改
+    pub async fn retype_column(
+        &self,
+        operator: &CurrentUser,
+        table_id: String,
+        name: String,
+        column_type: ColumnType,
+    ) -> Result<Vec<Column>, ServiceError> {
+        self.access
+            .require_table(operator, &table_id, Role::Editor)
+            .await?;
+        let table = self.find_table(&table_id).await?;
+        let mut columns = table.columns.clone();
+        let index = column_index(&columns, &name)?;
+        let mut retyped = columns[index].clone();
+        retyped.default = match retyped.default {
+            Some(v) => Some(schema::convert_value(&v, &column_type).map_err(|e| {
+                ServiceError::InvalidArgument(format!(
+                    "default of column '{}' is incompatible: {}",
+                    name, e
+                ))
+            })?),
+            None => None,
+        };
+        retyped.column_type = column_type;
+        let retyped = schema::validate_column(&retyped).map_err(ServiceError::InvalidArgument)?;
+        self.ensure_reference_exist(operator, &retyped.column_type)
+            .await?;
+        let column_type = retyped.column_type.clone();
+        columns[index] = retyped;
+        self.migrate(table, columns, RowMigration::Convert { name, column_type })
+            .await
+    }
+
+    /// 删除列
+    pub async fn drop_column(
+        &self,
+        operator: &CurrentUser,
+        table_id: String,
+        name: String,
+    ) -> Result<Vec<Column>, ServiceError> {
+        self.access
+            .require_table(operator, &table_id, Role::Editor)
+            .await?;
+        let table = self.find_table(&table_id).await?;
+        let mut columns = table.columns.clone();
+        let index = column_index(&columns, &name)?;
+        columns.remove(index);
+        let patch = row_patch().unset(&data_field(&name));
+        self.migrate(table, columns, RowMigration::Patch(patch))
+            .await
+    }
+}
+
+/// 查找列的位置，列不存在时返回数据不存在
+fn column_index(columns: &[Column], name: &str) -> Result<usize, ServiceError> {
+    columns
+        .iter()
+        .position(|c| c.name == name)
+        .ok_or_else(|| RepositoryError::DataNotFound.into())
+}
+
+/// 修改列时对表中已有的行的修改
+#[derive(Clone)]
+enum RowMigration {
+    None,
+    /// 所有行执行同一个部分更新
+    Patch(Patch),
+    /// 把列中的值转换成新的类型
+    Convert {
+        name: String,
+        column_type: ColumnType,
+    },
+}
+
+/// 表中所有行的条件
+fn rows_condition(table_id: &str) -> Condition {
+    Condition::single(
+        String::from("tableId"),
+        Operate::Eq,
+        ConditionValue::StringValue(table_id.to_string()),
+    )
+}
+
+/// 行数据中的列对应的字段
+fn data_field(name: &str) -> String {
+    format!("data.{}", name)
+}
+
+/// 修改行的部分更新，同时更新行的修改时间
+fn row_patch() -> Patch {
+    Patch::new().set("updatedAt", ConditionValue::DateTimeValue(Utc::now()))
+}
+
+/// 保存修改后的列定义，返回新的列定义
+async fn save_columns<S: Storage>(
+    storage: &S,
+    mut table: entity::Table,
+    columns: Vec<Column>,
+) -> Result<Vec<Column>, ServiceError> {
+    table.columns = columns;
+    table.updated_at = Utc::now();
+    storage.table_repo().update(&table).await?;
+    Ok(table.columns)
+}
+
+async fn migrate_table<S: Storage>(
+    tx: &S,
+    table: entity::Table,
+    columns: Vec<Column>,
+    migration: &RowMigration,
+) -> Result<Vec<Column>, ServiceError> {
+    match migration {
+        RowMigration::None => {}
+        RowMigration::Patch(patch) => {
+            tx.row_repo()
+                .update_many(&rows_condition(&table.id), patch)
+                .await?;
+        }
+        RowMigration::Convert { name, column_type } => {
+            ensure_convertible(tx, &table.id, name, column_type).await?;
+            convert_rows(tx, &table.id, name, column_type).await?;
+        }
+    }
+    save_columns(tx, table, columns).await
+}
+
+/// 按_id顺序分批读取表中的行，after为None时从头开始
+async fn next_rows<S: Storage>(
+    tx: &S,
+    table_id: &str,
+    after: Option<Cursor>,
+) -> Result<CursorResult<entity::Row>, ServiceError> {
+    let cursor_option = CursorOption {
+        after,
+        size: ROW_BATCH_SIZE,
+    };
+    let result = tx
+        .row_repo()
+        .find_after(
+            &rows_condition(table_id),
+            &keyset_sorts(&[]),
+            &cursor_option,
+        )
+        .await?;
+    Ok(result)
+}
+
+/// 列中有值而且和转换后不同时返回转换后的值
+fn converted(
+    row: &entity::Row,
+    name: &str,
+    column_type: &ColumnType,
+) -> Option<Result<Value, String>> {
+    let value = row.data.get(name).filter(|v| !v.is_null())?;
+    match schema::convert_value(value, column_type) {
+        Ok(v) if &v == value => None,
+        result => Some(result),
+    }
+}
+
+/// 要求列中已有的值都能转换成新的类型，否则列出其中一部分不能转换的行
+async fn ensure_convertible<S: Storage>(
+    tx: &S,
+    table_id: &str,
+    name: &str,
+    column_type: &ColumnType,
+) -> Result<(), ServiceError> {
+    let mut failed = 0;
+    let mut examples = vec![];
+    let mut after = None;
+    loop {
+        let result = next_rows(tx, table_id, after).await?;
+        for row in result.datas.iter() {
+            if let Some(Err(_)) = converted(row, name, column_type) {
+                failed += 1;
+                if examples.len() < 10 {
+                    examples.push(row.id.clone());
+                }
+            }
+        }
+        after = match result.next_cursor {
+            Some(cursor) => Some(cursor),
+            None => break,
+        };
+    }
+    if failed == 0 {
+        Ok(())
+    } else {
+        Err(ServiceError::InvalidArgument(format!(
+            "{} row(s) cannot be converted to {}, e.g. {}",
+            failed,
+            column_type,
+            examples.join(", ")
+        )))
+    }
+}
+
+/// 转换列中已有的值，每批中转换成同一个值的行用一次update_many写回
+async fn convert_rows<S: Storage>(
+    tx: &S,
+    table_id: &str,
+    name: &str,
+    column_type: &ColumnType,
+) -> Result<(), ServiceError> {
+    let row_repo = tx.row_repo();
+    let mut after = None;
+    loop {
+        let result = next_rows(tx, table_id, after).await?;
+        let mut groups: Vec<(Value, Vec<ObjectId>)> = vec![];
+        for row in result.datas.iter() {
+            let value = match converted(row, name, column_type) {
+                Some(Ok(v)) => v,
+                _ => continue,
+            };
+            let oid = ObjectId::from_str(&row.id)?;
+            match groups.iter_mut().find(|(v, _)| v == &value) {
+                Some((_, oids)) => oids.push(oid),
+                None => groups.push((value, vec![oid])),
+            }
+        }
+        for (value, oids) in groups {
+            let value = schema::to_condition_value(&value).unwrap_or(ConditionValue::NullValue);
+            let condition = Condition::single(
+                String::from("_id"),
+                Operate::In,
+                ConditionValue::ObjectIdVecValue(oids),
+            );
+            row_repo
+                .update_many(&condition, &row_patch().set(&data_field(name), value))
+                .await?;
+        }
+        after = match result.next_cursor {
+            Some(cursor) => Some(cursor),
+            None => break,
+        };
+    }
+    Ok(())
+}
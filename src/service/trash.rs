@@ -0,0 +1,163 @@
+use std::str::FromStr;
+
+use chrono::{Duration, Utc};
+use mongodb::bson::oid::ObjectId;
+use serde::Serialize;
+
+use crate::{
+    entity,
+    repository::{
+        condition::{Condition, ConditionValue, Operate, SortOption, SortOrder},
+        soft_delete::{SoftDeleteRepository, DELETED_AT},
+        CRUDRepository, Storage,
+    },
+};
+
+use super::{
+    auth::CurrentUser, cascade::Cascade, membership::Access, user::Creators, ServiceError,
+};
+
+fn id_condition(id: &str) -> Result<Condition, ServiceError> {
+    Ok(Condition::single(
+        String::from("_id"),
+        Operate::Eq,
+        ConditionValue::ObjectIdValue(ObjectId::from_str(id)?),
+    ))
+}
+
+/// 回收站中的数据，最近删除的在前
+#[derive(Serialize, Debug)]
+#[serde(rename_all = "camelCase")]
+pub struct Trash {
+    pub workspaces: Vec<entity::Workspace>,
+    pub catalogs: Vec<entity::Catalog>,
+    pub tables: Vec<entity::Table>,
+}
+
+#[derive(Clone)]
+pub struct TrashService<S: Storage> {
+    workspace_repo: S::WorkspaceRepo,
+    catalog_repo: S::CatalogRepo,
+    table_repo: S::TableRepo,
+    access: Access<S>,
+    cascade: Cascade<S>,
+    creators: Creators<S>,
+}
+
+impl<S: Storage> TrashService<S> {
+    pub fn new(storage: &S) -> Self {
+        Self {
+            workspace_repo: storage.workspace_repo(),
+            catalog_repo: storage.catalog_repo(),
+            table_repo: storage.table_repo(),
+            access: Access::new(storage),
+            cascade: Cascade::new(storage),
+            creators: Creators::new(storage),
+        }
+    }
+
+    /// 当前用户能看到的回收站
+    ///
+    /// 包含用户所在的已删除的工作区，以及未删除的工作区中已删除的目录和表。
+    /// 已删除的工作区中的目录和表随工作区一起恢复，不单独列出
+    pub async fn find_all(&self, operator: &CurrentUser) -> Result<Trash, ServiceError> {
+        let sorts = [SortOption::new(DELETED_AT, SortOrder::Desc)];
+        let ids = self.access.workspace_ids(operator).await?;
+        let in_workspaces = Condition::single(
+            String::from("_id"),
+            Operate::In,
+            ConditionValue::ObjectIdVecValue(ids),
+        );
+        let mut workspaces = self
+            .workspace_repo
+            .find_deleted(&in_workspaces, &sorts)
+            .await?;
+
+        let workspace_ids = self
+            .workspace_repo
+            .find(&in_workspaces, &[])
+            .await?
+            .into_iter()
+            .map(|w| w.id)
+            .collect::<Vec<_>>();
+        let in_catalogs = Condition::single(
+            String::from("workspaceId"),
+            Operate::In,
+            ConditionValue::StringVecValue(workspace_ids),
+        );
+        let mut catalogs = self.catalog_repo.find_deleted(&in_catalogs, &sorts).await?;
+
+        let catalog_ids = self
+            .catalog_repo
+            .find(&in_catalogs, &[])
+            .await?
+            .into_iter()
+            .map(|c| c.id)
+            .collect::<Vec<_>>();
+        let mut tables = self
+            .table_repo
+            .find_deleted(
+                &Condition::single(
+                    String::from("catalogId"),
+                    Operate::In,
+                    ConditionValue::StringVecValue(catalog_ids),
+                ),
+                &sorts,
+            )
+            .await?;
+
+        self.creators
+            .fill(
+                workspaces
+                    .iter_mut()
+                    .map(|w| &mut w.creator)
+                    .chain(catalogs.iter_mut().map(|c| &mut c.creator))
+                    .chain(tables.iter_mut().map(|t| &mut t.creator)),
+            )
+            .await?;
+        Ok(Trash {
+            workspaces,
+            catalogs,
+            tables,
+        })
+    }
+
+    /// 彻底删除在回收站中超过保留时间的数据，返回删除的数量
+    ///
+    /// 下级数据随上级一起彻底删除，工作区被彻底删除时同时删除它的成员关系
+    pub async fn purge_expired(&self, retention: Duration) -> Result<u64, ServiceError> {
+        let expired = Condition::single(
+            String::from(DELETED_AT),
+            Operate::Lt,
+            ConditionValue::DateTimeValue(Utc::now() - retention),
+        );
+        // 只删除仍在回收站中的数据，避免删除清理期间刚恢复的数据
+        let still_expired = |id: &str| -> Result<Condition, ServiceError> {
+            Ok(Condition::all(vec![id_condition(id)?, expired.clone()]))
+        };
+        let mut count = 0;
+        for table in self.table_repo.find_deleted(&expired, &[]).await? {
+            let condition = still_expired(&table.id)?;
+            if self.cascade.purge_table(&table.id, &condition).await? {
+                count += 1;
+            }
+        }
+        for catalog in self.catalog_repo.find_deleted(&expired, &[]).await? {
+            let condition = still_expired(&catalog.id)?;
+            if self.cascade.purge_catalog(&catalog.id, &condition).await? {
+                count += 1;
+            }
+        }
+        for workspace in self.workspace_repo.find_deleted(&expired, &[]).await? {
+            let condition = still_expired(&workspace.id)?;
+            if self
+                .cascade
+                .purge_workspace(&workspace.id, &condition)
+                .await?
+            {
+                count += 1;
+            }
+        }
+        Ok(count)
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::{HashMap, HashSet},
+    str::FromStr,
+};
+
+use argon2::{
+    password_hash::{rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString},
+    Argon2,
+};
+use lazy_static::lazy_static;
+use mongodb::bson::oid::ObjectId;
+
+use crate::{
+    entity::{self, UserSummary},
+    repository::{
+        condition::{Condition, ConditionValue, Operate},
+        CRUDRepository, RepositoryError, Storage,
+    },
+};
+
+use super::{auth::CurrentUser, FieldError, ServiceError};
+
+/// 用户名长度范围
+const USERNAME_LENGTH: (usize, usize) = (3, 32);
+/// 密码长度范围
+const PASSWORD_LENGTH: (usize, usize) = (8, 128);
+/// 显示名的最大长度
+const DISPLAY_NAME_MAX_LENGTH: usize = 64;
+
+lazy_static! {
+    /// 用户不存在时用来校验密码的哈希，参数和真实的哈希相同，校验的耗时也相同
+    static ref DUMMY_HASH: String = {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(b"dummy password", &salt)
+            .expect("couldn't hash the dummy password")
+            .to_string()
+    };
+}
+
+#[derive(Clone)]
+pub struct UserService<S: Storage> {
+    repo: S::UserRepo,
+}
+
+/// 用户名只能包含字母、数字和`_.-`
+fn check_username(username: &str, errors: &mut Vec<FieldError>) {
+    let length = username.chars().count();
+    if length < USERNAME_LENGTH.0 || length > USERNAME_LENGTH.1 {
+        errors.push(FieldError::new(
+            "username",
+            format!(
+                "length must be between {} and {}",
+                USERNAME_LENGTH.0, USERNAME_LENGTH.1
+            ),
+        ));
+    }
+    if !username
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '.' || c == '-')
+    {
+        errors.push(FieldError::new(
+            "username",
+            String::from("only letters, digits, '_', '.' and '-' are allowed"),
+        ));
+    }
+}
+
+/// 显示名去掉首尾空白后不能为空，也不能超过最大长度
+fn check_display_name(display_name: &str, errors: &mut Vec<FieldError>) {
+    let length = display_name.trim().chars().count();
+    if length == 0 || length > DISPLAY_NAME_MAX_LENGTH {
+        errors.push(FieldError::new(
+            "displayName",
+            format!("length must be between 1 and {}", DISPLAY_NAME_MAX_LENGTH),
+        ));
+    }
+}
+
+/// 密码策略：长度在范围内，同时包含字母和数字，并且不能和用户名相同
+fn check_password(field: &str, password: &str, username: &str, errors: &mut Vec<FieldError>) {
+    let length = password.chars().count();
+    if length < PASSWORD_LENGTH.0 || length > PASSWORD_LENGTH.1 {
+        errors.push(FieldError::new(
+            field,
+            format!(
+                "length must be between {} and {}",
+                PASSWORD_LENGTH.0, PASSWORD_LENGTH.1
+            ),
+        ));
+    }
+    if !password.chars().any(|c| c.is_alphabetic()) || !password.chars().any(|c| c.is_numeric()) {
+        errors.push(FieldError::new(
+            field,
+            String::from("must contain both letters and digits"),
+        ));
+    }
+    if password.eq_ignore_ascii_case(username) {
+        errors.push(FieldError::new(
+            field,
+            String::from("must not be the same as the username"),
+        ));
+    }
+}
+
+/// 使用argon2id计算密码哈希，结果是包含盐和参数的PHC字符串
+///
+/// 哈希计算比较耗时，放到阻塞线程池中执行
+pub async fn hash_password(password: String) -> Result<String, ServiceError> {
+    tokio::task::spawn_blocking(move || {
+        let salt = SaltString::generate(&mut OsRng);
+        Argon2::default()
+            .hash_password(password.as_bytes(), &salt)
+            .map(|hash| hash.to_string())
+            .map_err(|e| ServiceError::InternalError(e.to_string()))
+    })
+    .await
+    .map_err(|e| ServiceError::InternalError(e.to_string()))?
+}
+
+/// 校验密码和哈希是否匹配
+///
+/// 旧数据中的哈希可能不是PHC格式，这时记录警告并当作不匹配
+pub async fn verify_password(password: String, hash: String) -> Result<bool, ServiceError> {
+    tokio::task::spawn_blocking(move || {
+        let parsed = match PasswordHash::new(&hash) {
+            Ok(parsed) => parsed,
+            Err(e) => {
+                log::warn!("stored password hash is not valid: {}", e);
+                return Ok(false);
+            }
+        };
+        Ok(Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok())
+    })
+    .await
+    .map_err(|e| ServiceError::InternalError(e.to_string()))?
+}
+
+/// 用一个固定的哈希校验密码并丢弃结果，用户不存在时让登录和用户存在时一样慢
+pub async fn verify_dummy_password(password: String) -> Result<(), ServiceError> {
+    let hash = tokio::task::spawn_blocking(|| DUMMY_HASH.clone())
+        .await
+        .map_err(|e| ServiceError::InternalError(e.to_string()))?;
+    verify_password(password, hash).await?;
+    Ok(())
+}
+
+/// 填充实体中创建者的用户名，存储中只保存了创建者的id
+#[derive(Clone)]
+pub struct Creators<S: Storage> {
+    repo: S::UserRepo,
+}
+
+impl<S: Storage> Creators<S> {
+    pub fn new(storage: &S) -> Self {
+        Self {
+            repo: storage.user_repo(),
+        }
+    }
+
+    /// 用一次`$in`查询取出所有创建者的用户名和显示名，已经不存在的用户保持用户名为空
+    pub async fn fill<'a>(
+        &self,
+        creators: impl IntoIterator<Item = &'a mut UserSummary>,
+    ) -> Result<(), ServiceError> {
+        let creators: Vec<&mut UserSummary> = creators.into_iter().collect();
+        let ids: HashSet<ObjectId> = creators
+            .iter()
+            .filter_map(|c| ObjectId::from_str(&c.id).ok())
+            .collect();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let users: HashMap<String, entity::User> = self
+            .repo
+            .find(
+                &Condition::single(
+                    String::from("_id"),
+                    Operate::In,
+                    ConditionValue::ObjectIdVecValue(ids.into_iter().collect()),
+                ),
+                &[],
+            )
+            .await?
+            .into_iter()
+            .map(|u| (u.id.clone(), u))
+            .collect();
+        for creator in creators {
+            if let Some(user) = users.get(&creator.id) {
+                creator.username = user.username.clone();
+                creator.display_name = user.display_name.clone();
+            }
+        }
+        Ok(())
+    }
+}
+
+fn id_condition(oid: ObjectId) -> Condition {
+    Condition::single(
+        String::from("_id"),
+        Operate::Eq,
+        ConditionValue::ObjectIdValue(oid),
+    )
+}
+
+impl<S: Storage> UserService<S> {
+    pub fn new(storage: &S) -> Self {
+        Self {
+            repo: storage.user_repo(),
+        }
+    }
+
+    /// 注册用户，用户名重复时返回Conflict，显示名可以不设置
+    pub async fn register(
+        &self,
+        username: String,
+        password: String,
+        display_name: Option<String>,
+    ) -> Result<String, ServiceError> {
+        let mut errors = vec![];
+        check_username(&username, &mut errors);
+        if let Some(display_name) = &display_name {
+            check_display_name(display_name, &mut errors);
+        }
+        check_password("password", &password, &username, &mut errors);
+        if !errors.is_empty() {
+            return Err(ServiceError::ValidationError(errors));
+        }
+        // 先查一次给出明确的错误，并发注册时由唯一索引保证
+        let exist = self
+            .repo
+            .exist(&Condition::single(
+                String::from("username"),
+                Operate::Eq,
+                ConditionValue::StringValue(username.clone()),
+            ))
+            .await?;
+        if exist {
+            return Err(RepositoryError::Conflict(format!(
+                "username '{}' already exists",
+                username
+            ))
+            .into());
+        }
+        let password_hash = hash_password(password).await?;
+        let result = self
+            .repo
+            .create(&entity::User {
+                id: String::new(),
+                username,
+                display_name: display_name.map(|n| n.trim().to_string()),
+                password_hash,
+                version: 0,
+            })
+            .await?;
+        Ok(result)
+    }
+
+    pub async fn find_by_id(&self, id: String) -> Result<entity::User, ServiceError> {
+        let oid = ObjectId::from_str(&id)?;
+        let result = self.repo.find_one(&id_condition(oid)).await?;
+        Ok(result)
+    }
+
+    /// 修改密码，只能修改自己的密码，并且需要提供原密码
+    pub async fn change_password(
+        &self,
+        operator: &CurrentUser,
+        id: String,
+        old_password: String,
+        new_password: String,
+    ) -> Result<bool, ServiceError> {
+        operator.require_session()?;
+        if operator.id != id {
+            return Err(ServiceError::Forbidden(String::from(
+                "cannot change another user's password",
+            )));
+        }
+        let oid = ObjectId::from_str(&id)?;
+        let mut user = self.repo.find_one(&id_condition(oid)).await?;
+        if !verify_password(old_password, user.password_hash.clone()).await? {
+            return Err(ServiceError::Unauthorized(String::from(
+                "old password is incorrect",
+            )));
+        }
+        let mut errors = vec![];
+        check_password("newPassword", &new_password, &user.username, &mut errors);
+        if !errors.is_empty() {
+            return Err(ServiceError::ValidationError(errors));
+        }
+        user.password_hash = hash_password(new_password).await?;
+        self.repo.update(&user).await?;
+        Ok(true)
+    }
+}
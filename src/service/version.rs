@@ -0,0 +1,107 @@
+use crate::repository::{
+    condition::{Condition, ConditionValue, Operate, Patch},
+    soft_delete::{Deletion, SoftDeleteRepository},
+    Repository, RepositoryError,
+};
+
+use super::ServiceError;
+
+fn precondition_failed() -> ServiceError {
+    ServiceError::PreconditionFailed(String::from("resource version does not match"))
+}
+
+/// 数据当前的版本号和期望的版本号不一致时返回PreconditionFailed，没有期望的版本号时不检查
+pub fn check_version(expected: Option<i64>, actual: i64) -> Result<(), ServiceError> {
+    match expected {
+        Some(version) if version != actual => Err(precondition_failed()),
+        _ => Ok(()),
+    }
+}
+
+/// 在条件上加上期望的版本号，没有期望的版本号时不限定
+///
+/// 没有version字段的旧数据当作版本0
+pub fn with_version(condition: Condition, expected: Option<i64>) -> Condition {
+    let version = match expected {
+        Some(0) => Condition::or(vec![
+            (
+                String::from("version"),
+                Operate::Eq,
+                ConditionValue::Int64Value(0),
+            ),
+            (
+                String::from("version"),
+                Operate::Eq,
+                ConditionValue::NullValue,
+            ),
+        ]),
+        Some(version) => Condition::single(
+            String::from("version"),
+            Operate::Eq,
+            ConditionValue::Int64Value(version),
+        ),
+        None => Condition::Empty,
+    };
+    Condition::all(vec![condition, version])
+}
+
+/// 按期望的版本号部分更新，版本号不一致时返回PreconditionFailed
+pub async fn patch_versioned<T, R: Repository<T>>(
+    repo: &R,
+    condition: Condition,
+    expected: Option<i64>,
+    patch: &Patch,
+) -> Result<T, ServiceError> {
+    match repo
+        .patch(&with_version(condition.clone(), expected), patch)
+        .await
+    {
+        Err(RepositoryError::DataNotFound) if expected.is_some() => {
+            Err(mismatch_or_not_found(repo, &condition).await)
+        }
+        result => Ok(result?),
+    }
+}
+
+/// 按期望的版本号删除，版本号不一致时返回PreconditionFailed，数据不存在时返回false
+pub async fn delete_versioned<T, R: Repository<T>>(
+    repo: &R,
+    condition: Condition,
+    expected: Option<i64>,
+) -> Result<bool, ServiceError> {
+    let result = repo
+        .delete(&with_version(condition.clone(), expected))
+        .await?;
+    if !result && expected.is_some() && repo.exist(&condition).await? {
+        return Err(precondition_failed());
+    }
+    Ok(result)
+}
+
+/// 按期望的版本号软删除，记录删除的时间和用户，其余和delete_versioned相同
+pub async fn soft_delete_versioned<T, R: SoftDeleteRepository<T>>(
+    repo: &R,
+    condition: Condition,
+    expected: Option<i64>,
+    deletion: &Deletion,
+) -> Result<bool, ServiceError> {
+    let result = repo
+        .soft_delete(&with_version(condition.clone(), expected), deletion)
+        .await?;
+    if !result && expected.is_some() && repo.exist(&condition).await? {
+        return Err(precondition_failed());
+    }
+    Ok(result)
+}
+
+/// 带版本号条件的操作没有匹配到数据时，区分是版本号不一致还是数据不存在
+async fn mismatch_or_not_found<T, R: Repository<T>>(
+    repo: &R,
+    condition: &Condition,
+) -> ServiceError {
+    match repo.exist(condition).await {
+        Ok(true) => precondition_failed(),
+        Ok(false) => RepositoryError::DataNotFound.into(),
+        Err(e) => e.into(),
+    }
+}
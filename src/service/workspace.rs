@@ -1,104 +1,442 @@
-use std::str::FromStr;
+use std::{iter, str::FromStr, sync::Arc};
 
 use chrono::Utc;
 use mongodb::bson::oid::ObjectId;
 
 use crate::{
-    entity::{self, User},
+    entity::{self, Role},
     repository::{
-        condition::{Condition, ConditionValue, Operate},
-        mongodb::workspace::WorkspaceRepo,
-        CRUDRepository,
+        condition::{
+            Condition, ConditionValue, CursorOption, Operate, PageOption, Patch, SortOption,
+        },
+        soft_delete::{Deletion, SoftDeleteRepository},
+        CRUDRepository, CursorResult, PageResult, PaginationRepository, RepositoryError, Storage,
     },
 };
 
-use super::ServiceError;
+use super::{
+    auth::CurrentUser,
+    cascade::{delete_workspace_children, ensure_workspace_empty, Cascade},
+    membership::Access,
+    user::Creators,
+    version::patch_versioned,
+    ServiceError,
+};
+
+fn id_condition(oid: ObjectId) -> Condition {
+    Condition::single(
+        String::from("_id"),
+        Operate::Eq,
+        ConditionValue::ObjectIdValue(oid),
+    )
+}
+
+fn ids_condition(oids: Vec<ObjectId>) -> Condition {
+    Condition::single(
+        String::from("_id"),
+        Operate::In,
+        ConditionValue::ObjectIdVecValue(oids),
+    )
+}
+
+/// 部分更新的字段，updatedAt总是更新
+fn workspace_patch(name: Option<String>, description: Option<String>) -> Patch {
+    let mut patch = Patch::new().set("updatedAt", ConditionValue::DateTimeValue(Utc::now()));
+    if let Some(name) = name {
+        patch = patch.set("name", ConditionValue::StringValue(name));
+    }
+    if let Some(description) = description {
+        patch = patch.set("description", ConditionValue::StringValue(description));
+    }
+    patch
+}
+
+/// 在事务中创建工作区和创建者的owner成员关系
+async fn create_workspaces<S: Storage>(
+    tx: &S,
+    workspaces: &[entity::Workspace],
+    owner_id: &str,
+) -> Result<Vec<String>, ServiceError> {
+    let ids = tx.workspace_repo().create_many(workspaces).await?;
+    Access::new(tx)
+        .grant_many(&ids, owner_id, Role::Owner)
+        .await?;
+    Ok(ids)
+}
+
+/// 批量操作中单个工作区的权限检查
+async fn check_item<S: Storage>(
+    access: &Access<S>,
+    operator: &CurrentUser,
+    id: &str,
+    required: Role,
+) -> Result<ObjectId, ServiceError> {
+    let oid = ObjectId::from_str(id)?;
+    access.require_workspace(operator, id, required).await?;
+    Ok(oid)
+}
+
+/// 写入的数量少于通过检查的数量时，没有写入的工作区标记为不存在
+fn mark_unwritten(results: &mut [Result<String, ServiceError>], written: &[String]) {
+    for result in results.iter_mut() {
+        if matches!(result, Ok(id) if !written.contains(id)) {
+            *result = Err(RepositoryError::DataNotFound.into());
+        }
+    }
+}
+
+/// 在事务中逐个检查后一起更新，返回每个工作区的结果
+///
+/// update_many只更新没有删除的工作区，检查之后被删除的工作区不会更新
+async fn patch_workspaces<S: Storage>(
+    tx: &S,
+    operator: &CurrentUser,
+    ids: &[String],
+    patch: &Patch,
+) -> Result<Vec<Result<String, ServiceError>>, ServiceError> {
+    let access = Access::new(tx);
+    let mut results = Vec::with_capacity(ids.len());
+    let mut allowed = vec![];
+    for id in ids {
+        match check_item(&access, operator, id, Role::Admin).await {
+            Ok(oid) => {
+                allowed.push(oid);
+                results.push(Ok(id.clone()));
+            }
+            Err(e) => results.push(Err(e)),
+        }
+    }
+    if allowed.is_empty() {
+        return Ok(results);
+    }
+    let repo = tx.workspace_repo();
+    let expected = allowed.len() as u64;
+    let condition = ids_condition(allowed);
+    if repo.update_many(&condition, patch).await? < expected {
+        let written = repo
+            .find(&condition, &[])
+            .await?
+            .into_iter()
+            .map(|w| w.id)
+            .collect::<Vec<_>>();
+        mark_unwritten(&mut results, &written);
+    }
+    Ok(results)
+}
+
+/// 在事务中逐个检查后一起删除，返回每个工作区的结果
+///
+/// soft_delete_many只删除没有删除的工作区，按删除时间找到实际删除的工作区
+async fn delete_workspaces<S: Storage>(
+    tx: &S,
+    operator: &CurrentUser,
+    ids: &[String],
+    deletion: &Deletion,
+    cascade: bool,
+) -> Result<Vec<Result<String, ServiceError>>, ServiceError> {
+    let access = Access::new(tx);
+    let mut results = Vec::with_capacity(ids.len());
+    let mut allowed = vec![];
+    for id in ids {
+        let checked = match check_item(&access, operator, id, Role::Owner).await {
+            Ok(oid) if !cascade => ensure_workspace_empty(tx, id).await.map(|_| oid),
+            checked => checked,
+        };
+        match checked {
+            Ok(oid) => {
+                allowed.push(oid);
+                results.push(Ok(id.clone()));
+            }
+            Err(e) => results.push(Err(e)),
+        }
+    }
+    if allowed.is_empty() {
+        return Ok(results);
+    }
+    let repo = tx.workspace_repo();
+    let expected = allowed.len() as u64;
+    let condition = ids_condition(allowed);
+    let count = repo.soft_delete_many(&condition, deletion).await?;
+    let written = repo
+        .find_deleted(
+            &Condition::all(vec![condition, Deletion::deleted_at(deletion.at)]),
+            &[],
+        )
+        .await?
+        .into_iter()
+        .map(|w| w.id)
+        .collect::<Vec<_>>();
+    if count < expected {
+        mark_unwritten(&mut results, &written);
+    }
+    for id in &written {
+        delete_workspace_children(tx, id, deletion).await?;
+    }
+    Ok(results)
+}
 
 #[derive(Clone)]
-pub struct WorkspaceService {
-    repo: WorkspaceRepo,
+pub struct WorkspaceService<S: Storage> {
+    storage: S,
+    repo: S::WorkspaceRepo,
+    access: Access<S>,
+    cascade: Cascade<S>,
+    creators: Creators<S>,
 }
 
-impl WorkspaceService {
-    pub fn new(repo: WorkspaceRepo) -> Self {
-        Self { repo }
+impl<S: Storage> WorkspaceService<S> {
+    pub fn new(storage: &S) -> Self {
+        Self {
+            storage: storage.clone(),
+            repo: storage.workspace_repo(),
+            access: Access::new(storage),
+            cascade: Cascade::new(storage),
+            creators: Creators::new(storage),
+        }
     }
 
+    /// 用户只能看到自己加入的工作区
+    async fn member_filter(
+        &self,
+        operator: &CurrentUser,
+        filter: Condition,
+    ) -> Result<Condition, ServiceError> {
+        let ids = self.access.workspace_ids(operator).await?;
+        Ok(Condition::all(vec![
+            Condition::single(
+                String::from("_id"),
+                Operate::In,
+                ConditionValue::ObjectIdVecValue(ids),
+            ),
+            filter,
+        ]))
+    }
+
+    /// 创建工作区，创建者成为工作区的owner
+    ///
+    /// 工作区和owner成员关系在一个事务中创建，不会留下没有owner的工作区
     pub async fn create_workspace(
         &self,
+        operator: &CurrentUser,
         name: String,
         description: String,
-        creator: String,
     ) -> Result<String, ServiceError> {
-        let now = Utc::now();
-        let result = self
-            .repo
-            .create(&entity::Workspace {
-                id: String::new(),
-                name,
-                description,
-                creator: User {
-                    id: creator,
-                    username: String::new(),
-                    passowrd_hash: String::new(),
-                },
-                created_at: now.clone(),
-                updated_at: now.clone(),
-            })
+        self.create_many(operator, vec![(name, description)])
+            .await?
+            .pop()
+            .ok_or_else(|| ServiceError::InternalError(String::from("workspace was not created")))
+    }
+
+    pub async fn find_all_workspace(
+        &self,
+        operator: &CurrentUser,
+        filter: Condition,
+        sorts: &[SortOption],
+        page: &PageOption,
+    ) -> Result<PageResult<entity::Workspace>, ServiceError> {
+        let filter = self.member_filter(operator, filter).await?;
+        let mut result = self.repo.find_page(&filter, sorts, page, true).await?;
+        self.creators
+            .fill(result.datas.iter_mut().map(|w| &mut w.creator))
             .await?;
         Ok(result)
     }
 
-    pub async fn find_all_workspace(&self) -> Result<Vec<entity::Workspace>, ServiceError> {
-        let result = self.repo.find(&Condition::Empty).await?;
+    pub async fn find_workspace_after(
+        &self,
+        operator: &CurrentUser,
+        filter: Condition,
+        sorts: &[SortOption],
+        cursor_option: &CursorOption,
+    ) -> Result<CursorResult<entity::Workspace>, ServiceError> {
+        let filter = self.member_filter(operator, filter).await?;
+        let mut result = self.repo.find_after(&filter, sorts, cursor_option).await?;
+        self.creators
+            .fill(result.datas.iter_mut().map(|w| &mut w.creator))
+            .await?;
         Ok(result)
     }
 
-    pub async fn find_by_id(&self, id: String) -> Result<entity::Workspace, ServiceError> {
+    pub async fn find_by_id(
+        &self,
+        operator: &CurrentUser,
+        id: String,
+    ) -> Result<entity::Workspace, ServiceError> {
         let oid = ObjectId::from_str(&id)?;
-        let result = self
-            .repo
-            .find_one(&Condition::single(
-                String::from("_id"),
-                Operate::Eq,
-                ConditionValue::ObjectIdValue(oid),
-            ))
+        self.access
+            .require_workspace(operator, &id, Role::Viewer)
             .await?;
+        let mut result = self.repo.find_one(&id_condition(oid)).await?;
+        self.creators.fill(iter::once(&mut result.creator)).await?;
         Ok(result)
     }
 
+    /// 更新工作区需要admin角色，返回更新后的版本号
     pub async fn update(
         &self,
+        operator: &CurrentUser,
         id: String,
         name: String,
         description: String,
-    ) -> Result<bool, ServiceError> {
+        expected_version: Option<i64>,
+    ) -> Result<i64, ServiceError> {
+        let workspace = self
+            .patch(
+                operator,
+                id,
+                Some(name),
+                Some(description),
+                expected_version,
+            )
+            .await?;
+        Ok(workspace.version)
+    }
+
+    /// 部分更新工作区，只修改传入的字段，返回更新后的工作区，需要admin角色
+    ///
+    /// 直接在存储中更新字段，不会覆盖同时发生的其他修改；
+    /// 传入期望的版本号时，版本号不一致返回PreconditionFailed
+    pub async fn patch(
+        &self,
+        operator: &CurrentUser,
+        id: String,
+        name: Option<String>,
+        description: Option<String>,
+        expected_version: Option<i64>,
+    ) -> Result<entity::Workspace, ServiceError> {
         let oid = ObjectId::from_str(&id)?;
-        let mut workspace = self
-            .repo
-            .find_one(&Condition::single(
-                String::from("_id"),
-                Operate::Eq,
-                ConditionValue::ObjectIdValue(oid),
-            ))
+        self.access
+            .require_workspace(operator, &id, Role::Admin)
             .await?;
-        workspace.name = name;
-        workspace.description = description;
-        workspace.updated_at = Utc::now();
-        let result = self.repo.update(&workspace).await?;
+        let patch = workspace_patch(name, description);
+        let mut result =
+            patch_versioned(&self.repo, id_condition(oid), expected_version, &patch).await?;
+        self.creators.fill(iter::once(&mut result.creator)).await?;
         Ok(result)
     }
 
-    pub async fn delete(&self, id: String) -> Result<bool, ServiceError> {
+    /// 删除工作区需要owner角色，工作区进入回收站
+    ///
+    /// 成员关系保留到工作区被彻底删除，恢复后成员不变。
+    /// 工作区中还有目录时，只有cascade为true才能删除，其中的目录和表随工作区一起进入回收站
+    pub async fn delete(
+        &self,
+        operator: &CurrentUser,
+        id: String,
+        expected_version: Option<i64>,
+        cascade: bool,
+    ) -> Result<bool, ServiceError> {
         let oid = ObjectId::from_str(&id)?;
-        let result = self
-            .repo
-            .delete(&Condition::single(
-                String::from("_id"),
-                Operate::Eq,
-                ConditionValue::ObjectIdValue(oid),
-            ))
+        self.access
+            .require_workspace(operator, &id, Role::Owner)
             .await?;
+        self.cascade
+            .delete_workspace(
+                &id,
+                &id_condition(oid),
+                expected_version,
+                &Deletion::new(&operator.id),
+                cascade,
+            )
+            .await
+    }
+
+    /// 批量创建工作区，创建者成为每个工作区的owner，返回按顺序生成的id
+    ///
+    /// 在一个事务中完成，全部成功或全部失败
+    pub async fn create_many(
+        &self,
+        operator: &CurrentUser,
+        items: Vec<(String, String)>,
+    ) -> Result<Vec<String>, ServiceError> {
+        operator.check_unrestricted()?;
+        if items.is_empty() {
+            return Ok(vec![]);
+        }
+        let now = Utc::now();
+        let workspaces: Arc<[entity::Workspace]> = items
+            .into_iter()
+            .map(|(name, description)| entity::Workspace {
+                id: String::new(),
+                name,
+                description,
+                creator: operator.summary(),
+                created_at: now,
+                updated_at: now,
+                version: 0,
+                deleted_at: None,
+                deleted_by: None,
+            })
+            .collect();
+        let owner_id = operator.id.clone();
+        self.storage
+            .with_transaction(move |tx| {
+                let workspaces = workspaces.clone();
+                let owner_id = owner_id.clone();
+                Box::pin(async move { create_workspaces(tx, &workspaces, &owner_id).await })
+            })
+            .await
+    }
+
+    /// 批量部分更新工作区，每个工作区单独检查admin角色，不检查版本号
+    ///
+    /// 返回每个工作区的结果，没有权限或不存在的工作区不会更新。
+    /// 检查和更新在一个事务中完成
+    pub async fn patch_many(
+        &self,
+        operator: &CurrentUser,
+        ids: Vec<String>,
+        name: Option<String>,
+        description: Option<String>,
+    ) -> Result<Vec<Result<String, ServiceError>>, ServiceError> {
+        let ids: Arc<[String]> = ids.into();
+        let operator = operator.clone();
+        let patch = workspace_patch(name, description);
+        self.storage
+            .with_transaction(move |tx| {
+                let ids = ids.clone();
+                let operator = operator.clone();
+                let patch = patch.clone();
+                Box::pin(async move { patch_workspaces(tx, &operator, &ids, &patch).await })
+            })
+            .await
+    }
+
+    /// 批量删除工作区，每个工作区单独检查owner角色和下级数据，不检查版本号
+    ///
+    /// 返回每个工作区的结果，可以删除的工作区一起进入回收站。
+    /// 检查和删除在一个事务中完成，cascade为true时其中的目录和表随工作区一起进入回收站
+    pub async fn delete_many(
+        &self,
+        operator: &CurrentUser,
+        ids: Vec<String>,
+        cascade: bool,
+    ) -> Result<Vec<Result<String, ServiceError>>, ServiceError> {
+        let ids: Arc<[String]> = ids.into();
+        let operator = operator.clone();
+        let deletion = Deletion::new(&operator.id);
+        self.storage
+            .with_transaction(move |tx| {
+                let ids = ids.clone();
+                let operator = operator.clone();
+                let deletion = deletion.clone();
+                Box::pin(
+                    async move { delete_workspaces(tx, &operator, &ids, &deletion, cascade).await },
+                )
+            })
+            .await
+    }
+
+    /// 从回收站恢复工作区，需要owner角色，和工作区一起删除的目录和表也一起恢复
+    pub async fn restore(
+        &self,
+        operator: &CurrentUser,
+        id: String,
+    ) -> Result<entity::Workspace, ServiceError> {
+        let oid = ObjectId::from_str(&id)?;
+        self.access.require_role(operator, &id, Role::Owner).await?;
+        let mut result = self.cascade.restore_workspace(&id_condition(oid)).await?;
+        self.creators.fill(iter::once(&mut result.creator)).await?;
         Ok(result)
     }
 }